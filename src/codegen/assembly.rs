@@ -1,7 +1,11 @@
 use super::stack_allocator::StackAllocator;
+use super::target::{RelocationModel, Syntax, Target};
 
 pub struct Assembly {
    pub program: AssemblyProgram,
+   pub target: Target,
+   pub relocation_model: RelocationModel,
+   pub syntax: Syntax,
 }
 
 pub struct AssemblyProgram {
@@ -20,6 +24,13 @@ pub struct Function {
    pub stack_allocator: StackAllocator,
 }
 
+impl Function {
+   // Bytes reserved below %rbp, keeping %rsp 16-byte aligned at calls
+   pub fn frame_size(&self) -> i64 {
+      ((self.stack_allocator.get() + 15) / 16) * 16
+   }
+}
+
 pub struct StaticVar {
    pub name: String,
    pub global: bool,
@@ -30,6 +41,7 @@ pub struct StaticVar {
 pub enum Instruction {
    Mov(Operand, Operand),
    Movb(Operand, Operand),
+   Movq(Operand, Operand),
    Unary(UnaryOp, Operand),
    Binary(BinaryOp, Operand, Operand),
    Cmp(Operand, Operand),
@@ -71,6 +83,10 @@ pub enum Operand {
    Pseudo(String),
    Stack(i64),
    Data(String),
+   // The GOT slot holding the address of a symbol
+   GotEntry(String),
+   // The memory a 64-bit register points to
+   Indirect(Register),
 }
 
 #[derive(Debug, Clone)]
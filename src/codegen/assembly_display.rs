@@ -1,110 +1,221 @@
 use crate::codegen::assembly::*;
+use crate::codegen::target::{RelocationModel, Syntax, Target};
 
+use std::collections::HashSet;
 use std::fmt;
 
 impl fmt::Display for Assembly {
    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-      write!(f, "{}", self.program)
+      // A shared library may have its global functions interposed, so only calls
+      // to static ones can skip the PLT there
+      let direct = self.program.top_level.iter().filter_map(|top_level| match top_level {
+         TopLevel::Function(func) if !func.global || self.relocation_model != RelocationModel::Pic => Some(func.name.as_str()),
+         _ => None,
+      }).collect();
+      let writer = Writer { target: self.target, syntax: self.syntax, direct };
+      writer.program(f, &self.program)
    }
 }
 
-impl fmt::Display for AssemblyProgram {
-   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-      for top_level in &self.top_level {
+// Writes the program in AT&T or Intel syntax, spelling symbols and labels the
+// way the target expects
+struct Writer<'a> {
+   target: Target,
+   syntax: Syntax,
+   // Functions defined in this translation unit that are called directly
+   // rather than through the PLT
+   direct: HashSet<&'a str>,
+}
+
+impl Writer<'_> {
+   fn program(&self, f: &mut fmt::Formatter, program: &AssemblyProgram) -> fmt::Result {
+      if self.syntax == Syntax::Intel {
+         writeln!(f, "\t.intel_syntax noprefix")?;
+      }
+      for top_level in &program.top_level {
          match top_level {
-            TopLevel::Function(func) => writeln!(f, "{}", func)?,
-            TopLevel::StaticVar(var) => writeln!(f, "{}", var)?,
+            TopLevel::Function(func) => self.function(f, func)?,
+            TopLevel::StaticVar(var) => self.static_var(f, var)?,
          }
+         writeln!(f)?;
+      }
+      if let Some(note) = self.target.stack_note() {
+         writeln!(f, "{}", note)?;
       }
       Ok(())
    }
-}
 
-impl fmt::Display for StaticVar {
-   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-      if self.global {
-         writeln!(f, "\t.globl _{}", self.name)?;
+   fn static_var(&self, f: &mut fmt::Formatter, var: &StaticVar) -> fmt::Result {
+      let symbol = self.target.symbol(&var.name);
+      if var.global {
+         writeln!(f, "\t.globl {}", symbol)?;
       }
-      if self.value != 0 {
+      if var.value != 0 {
          writeln!(f, "\t.data")?;
          writeln!(f, "\t.balign 4")?;
-         writeln!(f, "_{}:", self.name)?;
-         writeln!(f, ".long {}", self.value)?;
+         writeln!(f, "{}:", symbol)?;
+         writeln!(f, "\t.long {}", var.value)?;
       } else {
          writeln!(f, "\t.bss")?;
          writeln!(f, "\t.balign 4")?;
-         writeln!(f, "_{}:", self.name)?;
+         writeln!(f, "{}:", symbol)?;
          writeln!(f, "\t.zero 4")?;
       }
       Ok(())
    }
-}
 
-impl fmt::Display for Function {
-   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-      if self.global {
-         writeln!(f, "\t.globl _{}", self.name)?;
+   fn function(&self, f: &mut fmt::Formatter, func: &Function) -> fmt::Result {
+      let symbol = self.target.symbol(&func.name);
+      if func.global {
+         writeln!(f, "\t.globl {}", symbol)?;
       }
       writeln!(f, "\t.text")?;
-      writeln!(f, "_{}:", self.name)?;
-      writeln!(f, "\tpushq\t%rbp")?;
-      writeln!(f, "\tmovq\t%rsp, %rbp")?;
-      for instr in &self.instructions {
-         writeln!(f, "{}", instr)?;
+      writeln!(f, "{}:", symbol)?;
+      match self.syntax {
+         Syntax::Att => {
+            writeln!(f, "\tpushq\t%rbp")?;
+            writeln!(f, "\tmovq\t%rsp, %rbp")?;
+         },
+         Syntax::Intel => {
+            writeln!(f, "\tpush\trbp")?;
+            writeln!(f, "\tmov\trbp, rsp")?;
+         }
+      }
+      for instr in &func.instructions {
+         self.instruction(f, instr)?;
       }
       Ok(())
    }
+
+   fn instruction(&self, f: &mut fmt::Formatter, instr: &Instruction) -> fmt::Result {
+      match instr {
+         Instruction::Mov(src, dest) => self.sized(f, "mov", 4, &[src, dest]),
+         Instruction::Movb(src, dest) => self.sized(f, "mov", 1, &[src, dest]),
+         Instruction::Movq(src, dest) => self.sized(f, "mov", 8, &[src, dest]),
+         Instruction::Unary(op, operand) => self.sized(f, op.mnemonic(), 4, &[operand]),
+         Instruction::Binary(op, left, right) => self.sized(f, op.mnemonic(), 4, &[left, right]),
+         Instruction::Shl(count, dst) => self.sized(f, "shl", 4, &[count, dst]),
+         Instruction::Shr(count, dst) => self.sized(f, "sar", 4, &[count, dst]),
+         Instruction::Cmp(left, right) => self.sized(f, "cmp", 4, &[left, right]),
+         Instruction::Cdq => writeln!(f, "\tcdq"),
+         Instruction::Jmp(label) => writeln!(f, "\tjmp {}", self.target.local_label(label)),
+         Instruction::JmpCC(condition, label) => writeln!(f, "\tj{} {}", condition, self.target.local_label(label)),
+         Instruction::SetCC(condition, operand) => writeln!(f, "\tset{} {}", condition, self.operand(operand, 1)),
+         Instruction::Label(label) => writeln!(f, "{}:", self.target.local_label(label)),
+         Instruction::Idiv(operand) => self.sized(f, "idiv", 4, &[operand]),
+         Instruction::AllocateStack(i) => self.stack_pointer(f, "sub", *i),
+         Instruction::DeallocateStack(i) => self.stack_pointer(f, "add", *i),
+         Instruction::Push(operand) => self.sized(f, "push", 8, &[operand]),
+         Instruction::Call(name) => writeln!(f, "\tcall {}", self.call_target(name)),
+         Instruction::Return => match self.syntax {
+            Syntax::Att => {
+               writeln!(f, "\tmovq\t%rbp, %rsp")?;
+               writeln!(f, "\tpopq\t%rbp")?;
+               writeln!(f, "\tret")
+            },
+            Syntax::Intel => {
+               writeln!(f, "\tmov\trsp, rbp")?;
+               writeln!(f, "\tpop\trbp")?;
+               writeln!(f, "\tret")
+            }
+         }
+      }
+   }
+
+   fn stack_pointer(&self, f: &mut fmt::Formatter, mnemonic: &str, bytes: i64) -> fmt::Result {
+      match self.syntax {
+         Syntax::Att => writeln!(f, "\t{}q ${}, %rsp", mnemonic, bytes),
+         Syntax::Intel => writeln!(f, "\t{} rsp, {}", mnemonic, bytes),
+      }
+   }
+
+   // Writes an instruction whose operands are `size` bytes wide. The operands
+   // are given in AT&T order, source first; AT&T puts the size in the mnemonic
+   // suffix, while Intel annotates memory operands with it.
+   fn sized(&self, f: &mut fmt::Formatter, mnemonic: &str, size: usize, operands: &[&Operand]) -> fmt::Result {
+      let operands: Vec<String> = operands.iter().map(|operand| self.operand(operand, size)).collect();
+      match self.syntax {
+         Syntax::Att => {
+            let suffix = match size {
+               1 => "b",
+               8 => "q",
+               _ => "l",
+            };
+            writeln!(f, "\t{}{} {}", mnemonic, suffix, operands.join(", "))
+         },
+         Syntax::Intel => {
+            let operands: Vec<String> = operands.into_iter().rev().collect();
+            writeln!(f, "\t{} {}", mnemonic, operands.join(", "))
+         }
+      }
+   }
+
+   fn operand(&self, operand: &Operand, size: usize) -> String {
+      match self.syntax {
+         Syntax::Att => match operand {
+            Operand::Data(name) => format!("{}(%rip)", self.target.symbol(name)),
+            Operand::GotEntry(name) => format!("{}@GOTPCREL(%rip)", self.target.symbol(name)),
+            _ => operand.to_string(),
+         },
+         Syntax::Intel => {
+            let ptr = match size {
+               1 => "BYTE PTR",
+               8 => "QWORD PTR",
+               _ => "DWORD PTR",
+            };
+            match operand {
+               Operand::Immediate(value) => value.to_string(),
+               Operand::Register(r) => r.name().to_string(),
+               Operand::Pseudo(name) => name.clone(),
+               Operand::Stack(i) => format!("{} [rbp{:+}]", ptr, i),
+               Operand::Data(name) => format!("{} {}[rip]", ptr, self.target.symbol(name)),
+               Operand::GotEntry(name) => format!("{} {}@GOTPCREL[rip]", ptr, self.target.symbol(name)),
+               Operand::Indirect(r) => format!("{} [{}]", ptr, r.name()),
+            }
+         }
+      }
+   }
+
+   fn call_target(&self, name: &str) -> String {
+      if self.target.uses_plt() && !self.direct.contains(name) {
+         format!("{}@PLT", self.target.symbol(name))
+      } else {
+         self.target.symbol(name)
+      }
+   }
 }
 
-impl fmt::Display for Instruction {
-   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl UnaryOp {
+   fn mnemonic(&self) -> &'static str {
       match self {
-         Instruction::Mov(src, dest) => write!(f, "\tmovl {}, {}", src, dest),
-         Instruction::Movb(src, dest) => write!(f, "\tmovb {}, {}", src, dest),
-         Instruction::Unary(op, operand) => write!(f, "\t{} {}", op, operand),
-         Instruction::Binary(op, left, right) => write!(f, "\t{} {}, {}", op, left, right),
-         Instruction::Shl(dst, count) => write!(f, "\tshll {}, {}", dst, count),
-         Instruction::Shr(dst, count) => write!(f, "\tsarl {}, {}", dst, count),
-         Instruction::Cmp(left, right) => write!(f, "\tcmpl {}, {}", left, right),
-         Instruction::Cdq => write!(f, "\tcdq"),
-         Instruction::Jmp(label) => write!(f, "\tjmp L{}", label),
-         Instruction::JmpCC(condition, label) => write!(f, "\tj{} L{}", condition, label),
-         Instruction::SetCC(condition, operand) => write!(f, "\tset{} {}", condition, operand),
-         Instruction::Label(label) => write!(f, "L{}:", label),
-         Instruction::Idiv(operand) => write!(f, "\tidivl {}", operand),
-         Instruction::AllocateStack(i) => write!(f, "\tsubq ${}, %rsp", i),
-         Instruction::DeallocateStack(i) => write!(f, "\taddq ${}, %rsp", i),
-         Instruction::Push(operand) => write!(f, "\tpushq {}", operand),
-         Instruction::Call(label) => write!(f, "\tcall _{}", label),
-         Instruction::Return => {
-            writeln!(f, "\tmovq\t%rbp, %rsp")?;
-            writeln!(f, "\tpopq\t%rbp")?;
-            writeln!(f, "\tret")?;
-            fmt::Result::Ok(())
-         }
+         UnaryOp::Neg => "neg",
+         UnaryOp::Not => "not",
       }
    }
 }
 
-impl fmt::Display for UnaryOp {
-   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl BinaryOp {
+   fn mnemonic(&self) -> &'static str {
       match self {
-         UnaryOp::Neg => write!(f, "negl"),
-         UnaryOp::Not => write!(f, "notl"),
+         BinaryOp::Add => "add",
+         BinaryOp::Sub => "sub",
+         BinaryOp::Mult => "imul",
+         BinaryOp::BitwiseAnd => "and",
+         BinaryOp::BitwiseOr => "or",
+         BinaryOp::BitwiseXor => "xor",
       }
    }
 }
 
+impl fmt::Display for UnaryOp {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}l", self.mnemonic())
+   }
+}
+
 impl fmt::Display for BinaryOp {
    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-      match self {
-         BinaryOp::Add => write!(f, "addl"),
-         BinaryOp::Sub => write!(f, "subl"),
-         BinaryOp::Mult => write!(f, "imull"),
-         BinaryOp::BitwiseAnd => write!(f, "andl"),
-         BinaryOp::BitwiseOr => write!(f, "orl"),
-         BinaryOp::BitwiseXor => write!(f, "xorl"),
-      }
+      write!(f, "{}l", self.mnemonic())
    }
 }
 
@@ -115,7 +226,9 @@ impl fmt::Display for Operand {
          Operand::Register(r) => write!(f, "{}", r),
          Operand::Pseudo(name) => write!(f, "{}", name),
          Operand::Stack(i) => write!(f, "{}(%rbp)", i),
-         Operand::Data(name) => write!(f, "_{}(%rip)", name)
+         Operand::Data(name) => write!(f, "{}(%rip)", name),
+         Operand::GotEntry(name) => write!(f, "{}@GOTPCREL(%rip)", name),
+         Operand::Indirect(r) => write!(f, "({})", r),
       }
    }
 }
@@ -133,81 +246,31 @@ impl fmt::Display for ConditionCode {
    }
 }
 
+impl Register {
+   // The name of the register at its size, without AT&T's % prefix
+   pub fn name(&self) -> &'static str {
+      let (names, size) = match self {
+         Register::AX(size) => (["rax", "eax", "al"], size),
+         Register::DX(size) => (["rdx", "edx", "dl"], size),
+         Register::CX(size) => (["rcx", "ecx", "cl"], size),
+         Register::DI(size) => (["rdi", "edi", "dil"], size),
+         Register::SI(size) => (["rsi", "esi", "sil"], size),
+         Register::R8(size) => (["r8", "r8d", "r8b"], size),
+         Register::R9(size) => (["r9", "r9d", "r9b"], size),
+         Register::R10(size) => (["r10", "r10d", "r10b"], size),
+         Register::R11(size) => (["r11", "r11d", "r11b"], size),
+      };
+      match size {
+         8 => names[0],
+         4 => names[1],
+         1 => names[2],
+         _ => unreachable!()
+      }
+   }
+}
+
 impl fmt::Display for Register {
    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-      match self {
-         Register::AX(size) => {
-            match size {
-               8 => write!(f, "%rax"),
-               4 => write!(f, "%eax"),
-               1 => write!(f, "%al"),
-               _ => unreachable!()
-            }
-         },
-         Register::DX(size) => {
-            match size {
-               8 => write!(f, "%rdx"),
-               4 => write!(f, "%edx"),
-               1 => write!(f, "%dl"),
-               _ => unreachable!()
-            }
-         },
-         Register::CX(size) => {
-            match size {
-               8 => write!(f, "%rcx"),
-               4 => write!(f, "%ecx"),
-               1 => write!(f, "%cl"),
-               _ => unreachable!()
-            }
-         },
-         Register::DI(size) => {
-            match size {
-               8 => write!(f, "%rdi"),
-               4 => write!(f, "%edi"),
-               1 => write!(f, "%dil"),
-               _ => unreachable!()
-            }
-         },
-         Register::SI(size) => {
-            match size {
-               8 => write!(f, "%rsi"),
-               4 => write!(f, "%esi"),
-               1 => write!(f, "%sil"),
-               _ => unreachable!()
-            }
-         },
-         Register::R8(size) => {
-            match size {
-               8 => write!(f, "%r8"),
-               4 => write!(f, "%r8d"),
-               1 => write!(f, "%r8b"),
-               _ => unreachable!()
-            }
-         },
-         Register::R9(size) => {
-            match size {
-               8 => write!(f, "%r9"),
-               4 => write!(f, "%r9d"),
-               1 => write!(f, "%r9b"),
-               _ => unreachable!()
-            }
-         },
-         Register::R10(size) => {
-            match size {
-               8 => write!(f, "%r10"),
-               4 => write!(f, "%r10d"),
-               1 => write!(f, "%r10b"),
-               _ => unreachable!()
-            }
-         },
-         Register::R11(size) => {
-            match size {
-               8 => write!(f, "%r11"),
-               4 => write!(f, "%r11d"),
-               1 => write!(f, "%r11b"),
-               _ => unreachable!()
-            }
-         }
-      }
+      write!(f, "%{}", self.name())
    }
 }
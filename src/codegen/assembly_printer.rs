@@ -21,6 +21,9 @@ pub fn print_assembly(assembly: &Assembly) {
             Instruction::Movb(src, dest) => {
                println!("  MOVB {:?}, {:?}", src, dest);
             },
+            Instruction::Movq(src, dest) => {
+               println!("  MOVQ {:?}, {:?}", src, dest);
+            },
             Instruction::Return => {
                println!("  RETURN");
             },
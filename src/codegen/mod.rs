@@ -2,6 +2,7 @@ pub mod assembly;
 pub mod assembly_display;
 mod assembly_printer;
 mod stack_allocator;
+pub mod target;
 
 use crate::codegen::assembly::*;
 use crate::tacky::tacky::{BinaryOp, Instr, TackyIR, TopLevel, UnaryOp, Val};
@@ -9,16 +10,18 @@ use crate::validator::symbol_table::*;
 use anyhow::Result;
 use assembly_printer::print_assembly;
 use stack_allocator::StackAllocator;
+use std::collections::HashSet;
+use target::{RelocationModel, Syntax, Target};
 
-pub fn codegen(tacky: TackyIR, print: bool) -> Result<Assembly> {
-   let assembly = generate_assembly(tacky)?;
+pub fn codegen(tacky: TackyIR, target: Target, relocation_model: RelocationModel, syntax: Syntax, print: bool) -> Result<Assembly> {
+   let assembly = generate_assembly(tacky, target, relocation_model, syntax)?;
    if print {
       print_assembly(&assembly);
    }
    Ok(assembly)
 }
 
-fn generate_assembly(tacky: TackyIR) -> Result<Assembly> {
+fn generate_assembly(tacky: TackyIR, target: Target, relocation_model: RelocationModel, syntax: Syntax) -> Result<Assembly> {
    let mut functions = Vec::new();
    for top_level in &tacky.program.top_level {
       match top_level {
@@ -30,9 +33,10 @@ fn generate_assembly(tacky: TackyIR) -> Result<Assembly> {
          }
       }
    }
-   let mut assembly = Assembly{ program: AssemblyProgram {top_level: functions} };
+   let mut assembly = Assembly{ program: AssemblyProgram {top_level: functions}, target, relocation_model, syntax };
    replace_pseudoregisters(&mut assembly, &tacky.symbol_table);
    fixup_instructions(&mut assembly);
+   access_data_through_got(&mut assembly);
    Ok(assembly)
 }
 
@@ -303,10 +307,8 @@ fn fixup_instructions(assembly: &mut Assembly) {
    for top_level in &mut assembly.program.top_level {
       match top_level {
          assembly::TopLevel::Function(func) => {
-            let stack_size = func.stack_allocator.get();
-            let stack_size = ((stack_size + 15) / 16) * 16;
             let mut new_instructions = Vec::new();
-            new_instructions.push(Instruction::AllocateStack(stack_size));
+            new_instructions.push(Instruction::AllocateStack(func.frame_size()));
 
             for instr in &func.instructions {
                match instr {
@@ -360,6 +362,52 @@ fn fixup_instructions(assembly: &mut Assembly) {
    }
 }
 
+// Makes accesses to data that may live in another module load its address from
+// the GOT first. Runs after the fixups, so every instruction has at most one
+// memory operand and the address goes in whichever scratch register it leaves free.
+fn access_data_through_got(assembly: &mut Assembly) {
+   let relocation_model = assembly.relocation_model;
+   if relocation_model == RelocationModel::Static {
+      return;
+   }
+   let direct: HashSet<String> = assembly.program.top_level.iter().filter_map(|top_level| match top_level {
+      assembly::TopLevel::StaticVar(var) if !var.global || relocation_model == RelocationModel::Pie => Some(var.name.clone()),
+      _ => None,
+   }).collect();
+
+   for top_level in &mut assembly.program.top_level {
+      if let assembly::TopLevel::Function(func) = top_level {
+         let mut new_instructions = Vec::new();
+         for mut instr in func.instructions.drain(..) {
+            let scratch = if operands_mut(&mut instr).iter().any(|op| matches!(op, Operand::Register(Register::R11(_)))) {
+               Register::R10(8)
+            } else {
+               Register::R11(8)
+            };
+            for operand in operands_mut(&mut instr) {
+               if let Operand::Data(name) = operand && !direct.contains(name) {
+                  new_instructions.push(Instruction::Movq(Operand::GotEntry(name.clone()), Operand::Register(scratch.clone())));
+                  *operand = Operand::Indirect(scratch.clone());
+               }
+            }
+            new_instructions.push(instr);
+         }
+         func.instructions = new_instructions;
+      }
+   }
+}
+
+fn operands_mut(instr: &mut Instruction) -> Vec<&mut Operand> {
+   match instr {
+      Instruction::Mov(src, dst) | Instruction::Movb(src, dst) | Instruction::Movq(src, dst)
+         | Instruction::Binary(_, src, dst) | Instruction::Cmp(src, dst)
+         | Instruction::Shl(src, dst) | Instruction::Shr(src, dst) => vec![src, dst],
+      Instruction::Unary(_, operand) | Instruction::Idiv(operand)
+         | Instruction::SetCC(_, operand) | Instruction::Push(operand) => vec![operand],
+      _ => Vec::new(),
+   }
+}
+
 fn convert_pseudo(operand: &mut Operand, size: i64, symbol_table: &SymbolTable, stack_allocator: &mut StackAllocator) {
    if let Operand::Pseudo(name) = operand {
       if let Some(entry) =  symbol_table.get(name) {
@@ -0,0 +1,118 @@
+use anyhow::{bail, Result};
+
+// The platform the assembly is written for. It decides how symbols and local
+// labels are spelled, which sections are emitted and whether calls go through
+// the PLT.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Target {
+   LinuxGnu,
+   Darwin,
+}
+
+impl Target {
+   pub const TRIPLES: &[&str] = &["x86_64-linux-gnu", "x86_64-apple-darwin"];
+
+   // The platform crust itself was built for
+   pub fn host() -> Self {
+      if cfg!(target_os = "macos") {
+         Target::Darwin
+      } else {
+         Target::LinuxGnu
+      }
+   }
+
+   pub fn from_triple(triple: &str) -> Result<Self> {
+      match triple {
+         "x86_64-linux-gnu" | "x86_64-pc-linux-gnu" | "x86_64-unknown-linux-gnu" => Ok(Target::LinuxGnu),
+         "x86_64-apple-darwin" | "x86_64-apple-macos" | "x86_64-apple-macosx" => Ok(Target::Darwin),
+         _ => bail!("Unsupported target '{}', expected one of: {}", triple, Self::TRIPLES.join(", ")),
+      }
+   }
+
+   pub fn triple(&self) -> &'static str {
+      match self {
+         Target::LinuxGnu => "x86_64-linux-gnu",
+         Target::Darwin => "x86_64-apple-darwin",
+      }
+   }
+
+   // Mach-O prefixes every C symbol with an underscore
+   pub fn symbol(&self, name: &str) -> String {
+      match self {
+         Target::LinuxGnu => name.to_string(),
+         Target::Darwin => format!("_{}", name),
+      }
+   }
+
+   // Labels that must not end up in the object's symbol table
+   pub fn local_label(&self, name: &str) -> String {
+      match self {
+         Target::LinuxGnu => format!(".L{}", name),
+         Target::Darwin => format!("L{}", name),
+      }
+   }
+
+   // ELF calls functions that may live in a shared library through the PLT;
+   // the Mach-O linker resolves them with stubs on its own
+   pub fn uses_plt(&self) -> bool {
+      *self == Target::LinuxGnu
+   }
+
+   // Directive marking the stack as non-executable, if the target needs one
+   pub fn stack_note(&self) -> Option<&'static str> {
+      match self {
+         Target::LinuxGnu => Some("\t.section .note.GNU-stack,\"\",@progbits"),
+         Target::Darwin => None,
+      }
+   }
+}
+
+impl Default for Target {
+   fn default() -> Self {
+      Self::host()
+   }
+}
+
+// How the generated code refers to symbols, chosen with -fPIC/-fPIE
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum RelocationModel {
+   // Position-dependent code, as long as the linker can resolve every symbol
+   // to a fixed address or a copy in the executable
+   #[default]
+   Static,
+   // Code for a position independent executable: data defined elsewhere is
+   // reached through the GOT, everything else relative to %rip
+   Pie,
+   // Code for a shared library: any global symbol may be interposed at load
+   // time, so only static data is accessed directly
+   Pic,
+}
+
+impl RelocationModel {
+   pub fn from_flag(flag: &str) -> Result<Self> {
+      match flag {
+         "PIC" | "pic" => Ok(RelocationModel::Pic),
+         "PIE" | "pie" => Ok(RelocationModel::Pie),
+         "no-pic" | "no-PIC" | "no-pie" | "no-PIE" => Ok(RelocationModel::Static),
+         _ => bail!("Unknown option '-f{}'", flag),
+      }
+   }
+}
+
+// The assembly dialect to write, chosen with -masm
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum Syntax {
+   #[default]
+   Att,
+   Intel,
+}
+
+impl Syntax {
+   pub fn from_name(name: &str) -> Result<Self> {
+      match name {
+         "att" => Ok(Syntax::Att),
+         "intel" => Ok(Syntax::Intel),
+         _ => bail!("Unknown assembly syntax '{}', expected att or intel", name),
+      }
+   }
+}
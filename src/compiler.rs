@@ -1,81 +1,229 @@
-use crate::{codegen, codegen::assembly::Assembly};
+use crate::{codegen, codegen::assembly::Assembly, codegen::target::{RelocationModel, Syntax, Target}};
 use crate::{lexer, lexer::token::Token};
 use crate::{parser, parser::ast::AST};
 use crate::{tacky, tacky::tacky::TackyIR};
 use crate::emitter;
+use crate::error;
+use crate::error::render::{self, Diagnostic};
+use crate::error::warning::{Warning, WarningOptions};
 use crate::gcc;
+use crate::optimizer::{self, Optimizations};
+use crate::stats::Stats;
 use crate::validator;
 
 use anyhow::Result;
-use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
 
+// Drives one translation unit through the stages of the compiler. Options are
+// set builder style, e.g.
+//
+//    let mut compiler = Compiler::new(source).include_dir("src").print_ast(true);
+//    let result = compiler.compile(&mut output);
+//    let diagnostics = compiler.diagnostics(&result);
 pub struct Compiler {
-   source_path: PathBuf,
-   pp_source_path: PathBuf,
-   assembly_path: PathBuf,
    source: String,
-}
-
-impl Drop for Compiler {
-   fn drop(&mut self) {
-      // let _ = fs::remove_file(&self.assembly_path);
-      let _ = fs::remove_file(&self.pp_source_path);
-   }
+   file: Option<PathBuf>,
+   include_dir: Option<PathBuf>,
+   warning_options: WarningOptions,
+   target: Target,
+   relocation_model: RelocationModel,
+   syntax: Syntax,
+   optimizations: Optimizations,
+   print_tokens: bool,
+   print_ast: bool,
+   print_tacky: bool,
+   print_assembly: bool,
+   pp_source: String,
+   warnings: Vec<Warning>,
+   stats: Stats,
 }
 
 impl Compiler {
-   pub fn new(source_path: PathBuf) -> Result<Self> {
-      let mut compiler = Self {
-         source_path,
-         pp_source_path: PathBuf::new(),
-         assembly_path: PathBuf::new(),
-         source: String::new(),
-      };
-      compiler.pp_source_path = compiler.source_path.with_extension("i");
-      compiler.assembly_path = compiler.source_path.with_extension("s");
-      Ok(compiler)
-   }
-
-   pub fn compile(&mut self, print_tokens: bool, print_ast: bool, print_tacky: bool, print_assembly: bool, library: bool, additional_args: &mut Vec<String>) -> Result<()> {
-      let assembly_ast = self.codegen(print_tokens, print_ast, print_tacky, print_assembly)?;
-      emitter::emit_code(&assembly_ast, &self.assembly_path)?;
-      if library {
-         additional_args.push("-c".to_string());
+   pub fn new(source: impl Into<String>) -> Self {
+      Self {
+         source: source.into(),
+         file: None,
+         include_dir: None,
+         warning_options: WarningOptions::default(),
+         target: Target::host(),
+         relocation_model: RelocationModel::default(),
+         syntax: Syntax::default(),
+         optimizations: Optimizations::default(),
+         print_tokens: false,
+         print_ast: false,
+         print_tacky: false,
+         print_assembly: false,
+         pp_source: String::new(),
+         warnings: Vec::new(),
+         stats: Stats::default(),
       }
-      gcc::assemble(&self.assembly_path, additional_args)?;
-      Ok(())
    }
 
-   pub fn preprocess(&mut self) -> Result<()> {
-      gcc::preprocess(&self.source_path, &self.pp_source_path)?;
-      self.source = fs::read_to_string(&self.pp_source_path)?;
-      Ok(())
+   // Names the file the source was read from. The preprocessor then reads it
+   // from disk, so line markers and `__FILE__` refer to it by name and its
+   // quoted includes are found next to it.
+   pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+      self.file = Some(path.into());
+      self
+   }
+
+   // Sets the directory searched for quoted includes
+   pub fn include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+      self.include_dir = Some(dir.into());
+      self
+   }
+
+   pub fn warning_options(mut self, options: WarningOptions) -> Self {
+      self.warning_options = options;
+      self
+   }
+
+   pub fn target(mut self, target: Target) -> Self {
+      self.target = target;
+      self
+   }
+
+   pub fn relocation_model(mut self, model: RelocationModel) -> Self {
+      self.relocation_model = model;
+      self
    }
 
-   pub fn lex(&mut self, print_tokens: bool) -> Result<Vec<Option<Token>>> {
+   // Sets the dialect of the assembly written by `compile`
+   pub fn syntax(mut self, syntax: Syntax) -> Self {
+      self.syntax = syntax;
+      self
+   }
+
+   // Sets the passes run over the TACKY before code generation
+   pub fn optimizations(mut self, optimizations: Optimizations) -> Self {
+      self.optimizations = optimizations;
+      self
+   }
+
+   pub fn print_tokens(mut self, print: bool) -> Self {
+      self.print_tokens = print;
+      self
+   }
+
+   pub fn print_ast(mut self, print: bool) -> Self {
+      self.print_ast = print;
+      self
+   }
+
+   pub fn print_tacky(mut self, print: bool) -> Self {
+      self.print_tacky = print;
+      self
+   }
+
+   pub fn print_assembly(mut self, print: bool) -> Self {
+      self.print_assembly = print;
+      self
+   }
+
+   // Compiles the source, writing the assembly to `output`
+   pub fn compile(&mut self, output: &mut impl Write) -> Result<()> {
+      let assembly_ast = self.codegen()?;
+      self.stats.time("emit", || emitter::emit_code(&assembly_ast, output))
+   }
+
+   // Like `compile`, but writes a relocatable object with the integrated
+   // assembler instead of assembly source
+   pub fn compile_object(&mut self, output: &mut impl Write) -> Result<()> {
+      let assembly_ast = self.codegen()?;
+      self.stats.time("emit", || emitter::emit_object(&assembly_ast, output))
+   }
+
+   pub fn preprocessed(&self) -> &str {
+      &self.pp_source
+   }
+
+   pub fn preprocess(&mut self) -> Result<&str> {
+      let include_dir = self.include_dir.as_deref();
+      self.pp_source = self.stats.time("preprocess", || match &self.file {
+         Some(path) => gcc::preprocess_file(path, include_dir),
+         None => gcc::preprocess(&self.source, include_dir),
+      })?;
+      Ok(&self.pp_source)
+   }
+
+   pub fn lex(&mut self) -> Result<Vec<Option<Token>>> {
       self.preprocess()?;
-      Ok(lexer::lex(&self.source, print_tokens)?)
+      let tokens = self.stats.time("lex", || lexer::lex(&self.pp_source, self.print_tokens))?;
+      self.stats.tokens = tokens.iter().flatten().count();
+      Ok(tokens)
    }
 
-   pub fn parse(&mut self, print_tokens: bool, print_ast: bool) -> Result<AST> {
-      let tokens = self.lex(print_tokens)?;
-      Ok(parser::parse(tokens, print_ast)?)
+   pub fn parse(&mut self) -> Result<AST> {
+      let tokens = self.lex()?;
+      let ast = self.stats.time("parse", || parser::parse(tokens, self.print_ast))?;
+      self.stats.count_ast(&ast);
+      Ok(ast)
    }
 
-   pub fn validate(&mut self, print_tokens: bool, print_ast: bool) -> Result<AST> {
-      let mut ast = self.parse(print_tokens, print_ast)?;
-      validator::validate(&mut ast, print_ast)?;
+   pub fn validate(&mut self) -> Result<AST> {
+      let mut ast = self.parse()?;
+      let mut warnings = Vec::new();
+      let result = self.stats.time("validate", || validator::validate(&mut ast, self.print_ast, &mut warnings));
+      self.check_warnings(warnings, result)?;
       Ok(ast)
    }
 
-   pub fn tacky(&mut self, print_tokens: bool, print_ast: bool, print_tacky: bool) -> Result<TackyIR> {
-      let ast = self.validate(print_tokens, print_ast)?;
-      Ok(tacky::gen_tacky(ast, print_tacky)?)
+   pub fn warnings(&self) -> &[Warning] {
+      &self.warnings
+   }
+
+   // The warnings reported so far together with the errors carried by `result`,
+   // the value returned by one of the stages, with their columns mapped back
+   // onto the source as written
+   pub fn diagnostics<T>(&self, result: &Result<T>) -> Vec<Diagnostic> {
+      let mut diagnostics = render::collect(None, &self.warnings, result);
+      render::map_columns(&mut diagnostics, &self.source, &self.pp_source);
+      diagnostics
+   }
+
+   // Drops disabled warnings and turns the ones selected by -Werror into errors,
+   // which are reported alongside any errors from `result`.
+   fn check_warnings(&mut self, mut warnings: Vec<Warning>, result: Result<()>) -> Result<()> {
+      warnings.sort_by_key(|w| w.line);
+      let mut errors = Vec::new();
+      if let Err(e) = result {
+         error::push(&mut errors, e)?;
+      }
+      for warning in warnings {
+         if !self.warning_options.enabled(warning.kind) {
+            continue;
+         }
+         if self.warning_options.is_error(warning.kind) {
+            errors.push(warning.into_error());
+         } else {
+            self.warnings.push(warning);
+         }
+      }
+      error::finish(errors)
+   }
+
+   pub fn tacky(&mut self) -> Result<TackyIR> {
+      let ast = self.validate()?;
+      let mut tacky = self.stats.time("tacky", || tacky::gen_tacky(ast, self.print_tacky))?;
+      if self.optimizations.any() {
+         self.stats.time("optimize", || optimizer::optimize(&mut tacky, self.optimizations, self.relocation_model, self.print_tacky));
+      }
+      self.stats.count_tacky(&tacky);
+      Ok(tacky)
+   }
+
+   pub fn codegen(&mut self) -> Result<Assembly> {
+      let tacky = self.tacky()?;
+      let assembly = self.stats.time("codegen", || {
+         codegen::codegen(tacky, self.target, self.relocation_model, self.syntax, self.print_assembly)
+      })?;
+      self.stats.count_assembly(&assembly);
+      Ok(assembly)
    }
 
-   pub fn codegen(&mut self, print_tokens: bool, print_ast: bool, print_tacky: bool, print_assembly: bool) -> Result<Assembly> {
-      let tacky = self.tacky(print_tokens, print_ast, print_tacky)?;
-      Ok(codegen::codegen(tacky, print_assembly)?)
+   // Pass timings and sizes gathered by the stages run so far
+   pub fn stats(&self) -> &Stats {
+      &self.stats
    }
 }
\ No newline at end of file
@@ -0,0 +1,338 @@
+// Writes relocatable ELF64 objects for x86-64, the format `gcc -c` produces on
+// Linux. Only the pieces a C translation unit needs are supported: code, data
+// and zero-initialized data, a symbol table and relocations against .text.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::Write;
+
+// The encoded contents of one translation unit
+#[derive(Default)]
+pub struct Object {
+   pub text: Vec<u8>,
+   pub data: Vec<u8>,
+   pub bss_size: u64,
+   pub symbols: Vec<Symbol>,
+   pub relocations: Vec<Relocation>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Section {
+   Text,
+   Data,
+   Bss,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SymbolKind {
+   Function,
+   Object,
+}
+
+// A symbol defined in this object
+pub struct Symbol {
+   pub name: String,
+   pub section: Section,
+   pub offset: u64,
+   pub size: u64,
+   pub kind: SymbolKind,
+   pub global: bool,
+}
+
+#[derive(Copy, Clone)]
+pub enum RelocationKind {
+   Pc32,
+   Plt32,
+   GotPcRel,
+}
+
+impl RelocationKind {
+   fn elf_type(&self) -> u64 {
+      match self {
+         RelocationKind::Pc32 => 2,
+         RelocationKind::Plt32 => 4,
+         RelocationKind::GotPcRel => 9,
+      }
+   }
+}
+
+// A 32-bit field in .text to be filled in by the linker with the address of
+// `symbol` + `addend`, relative to the field
+pub struct Relocation {
+   pub offset: u64,
+   pub symbol: String,
+   pub kind: RelocationKind,
+   pub addend: i64,
+}
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+// Section header indices, in the order the headers are written
+const TEXT: u16 = 1;
+const DATA: u16 = 2;
+const BSS: u16 = 3;
+const SYMTAB: u32 = 6;
+const STRTAB: u32 = 7;
+const SHSTRTAB: u16 = 8;
+const SECTION_COUNT: u16 = 9;
+
+const ELF_HEADER_SIZE: u64 = 64;
+const SECTION_HEADER_SIZE: u64 = 64;
+const SYMBOL_SIZE: u64 = 24;
+const RELA_SIZE: u64 = 24;
+
+pub fn write(object: &Object, output: &mut impl Write) -> Result<()> {
+   let mut strtab = StringTable::new();
+   let (symtab, first_global, indices) = symbol_table(object, &mut strtab);
+
+   let mut rela = Vec::new();
+   for relocation in &object.relocations {
+      rela.extend(relocation.offset.to_le_bytes());
+      rela.extend((indices[relocation.symbol.as_str()] << 32 | relocation.kind.elf_type()).to_le_bytes());
+      rela.extend(relocation.addend.to_le_bytes());
+   }
+
+   let mut shstrtab = StringTable::new();
+   let mut file = vec![0; ELF_HEADER_SIZE as usize];
+   let mut headers = vec![0; SECTION_HEADER_SIZE as usize];
+
+   let offset = append(&mut file, &object.text, 16);
+   SectionHeader { name: shstrtab.add(".text"), kind: SHT_PROGBITS, flags: SHF_ALLOC | SHF_EXECINSTR, offset, size: object.text.len() as u64, align: 16, ..Default::default() }
+      .write(&mut headers);
+   let offset = append(&mut file, &object.data, 4);
+   SectionHeader { name: shstrtab.add(".data"), kind: SHT_PROGBITS, flags: SHF_ALLOC | SHF_WRITE, offset, size: object.data.len() as u64, align: 4, ..Default::default() }
+      .write(&mut headers);
+   SectionHeader { name: shstrtab.add(".bss"), kind: SHT_NOBITS, flags: SHF_ALLOC | SHF_WRITE, offset, size: object.bss_size, align: 4, ..Default::default() }
+      .write(&mut headers);
+   let offset = append(&mut file, &rela, 8);
+   SectionHeader {
+      name: shstrtab.add(".rela.text"), kind: SHT_RELA, flags: SHF_INFO_LINK, offset, size: rela.len() as u64,
+      link: SYMTAB, info: TEXT as u32, align: 8, entsize: RELA_SIZE,
+   }.write(&mut headers);
+   // Marks the stack as non-executable
+   SectionHeader { name: shstrtab.add(".note.GNU-stack"), kind: SHT_PROGBITS, offset, align: 1, ..Default::default() }
+      .write(&mut headers);
+   let offset = append(&mut file, &symtab, 8);
+   SectionHeader {
+      name: shstrtab.add(".symtab"), kind: SHT_SYMTAB, offset, size: symtab.len() as u64,
+      link: STRTAB, info: first_global, align: 8, entsize: SYMBOL_SIZE, ..Default::default()
+   }.write(&mut headers);
+   let offset = append(&mut file, &strtab.bytes, 1);
+   SectionHeader { name: shstrtab.add(".strtab"), kind: SHT_STRTAB, offset, size: strtab.bytes.len() as u64, align: 1, ..Default::default() }
+      .write(&mut headers);
+   let name = shstrtab.add(".shstrtab");
+   let offset = append(&mut file, &shstrtab.bytes, 1);
+   SectionHeader { name, kind: SHT_STRTAB, offset, size: shstrtab.bytes.len() as u64, align: 1, ..Default::default() }
+      .write(&mut headers);
+
+   let section_headers = append(&mut file, &headers, 8);
+   elf_header(&mut file[..ELF_HEADER_SIZE as usize], section_headers);
+
+   output.write_all(&file)?;
+   Ok(())
+}
+
+// Builds .symtab, where every local symbol has to come before the global ones.
+// Returns it with the index of the first global symbol and the index of every
+// symbol by name, including the undefined ones the relocations refer to.
+fn symbol_table<'a>(object: &'a Object, strtab: &mut StringTable) -> (Vec<u8>, u32, HashMap<&'a str, u64>) {
+   let mut symtab = vec![0; SYMBOL_SIZE as usize];
+   let mut indices = HashMap::new();
+
+   let locals = object.symbols.iter().filter(|symbol| !symbol.global);
+   let globals = object.symbols.iter().filter(|symbol| symbol.global);
+   for symbol in locals.chain(globals) {
+      let binding = if symbol.global { STB_GLOBAL } else { STB_LOCAL };
+      let kind = match symbol.kind {
+         SymbolKind::Function => STT_FUNC,
+         SymbolKind::Object => STT_OBJECT,
+      };
+      let section = match symbol.section {
+         Section::Text => TEXT,
+         Section::Data => DATA,
+         Section::Bss => BSS,
+      };
+      indices.insert(symbol.name.as_str(), symtab.len() as u64 / SYMBOL_SIZE);
+      symbol_entry(&mut symtab, strtab.add(&symbol.name), binding << 4 | kind, section, symbol.offset, symbol.size);
+   }
+   let first_global = object.symbols.iter().filter(|symbol| !symbol.global).count() as u32 + 1;
+
+   for relocation in &object.relocations {
+      let name = relocation.symbol.as_str();
+      if !indices.contains_key(name) {
+         indices.insert(name, symtab.len() as u64 / SYMBOL_SIZE);
+         symbol_entry(&mut symtab, strtab.add(name), STB_GLOBAL << 4 | STT_NOTYPE, 0, 0, 0);
+      }
+   }
+   (symtab, first_global, indices)
+}
+
+fn symbol_entry(symtab: &mut Vec<u8>, name: u32, info: u8, section: u16, value: u64, size: u64) {
+   symtab.extend(name.to_le_bytes());
+   symtab.push(info);
+   symtab.push(0);
+   symtab.extend(section.to_le_bytes());
+   symtab.extend(value.to_le_bytes());
+   symtab.extend(size.to_le_bytes());
+}
+
+#[derive(Default)]
+struct SectionHeader {
+   name: u32,
+   kind: u32,
+   flags: u64,
+   offset: u64,
+   size: u64,
+   link: u32,
+   info: u32,
+   align: u64,
+   entsize: u64,
+}
+
+impl SectionHeader {
+   fn write(&self, headers: &mut Vec<u8>) {
+      headers.extend(self.name.to_le_bytes());
+      headers.extend(self.kind.to_le_bytes());
+      headers.extend(self.flags.to_le_bytes());
+      headers.extend(0u64.to_le_bytes());
+      headers.extend(self.offset.to_le_bytes());
+      headers.extend(self.size.to_le_bytes());
+      headers.extend(self.link.to_le_bytes());
+      headers.extend(self.info.to_le_bytes());
+      headers.extend(self.align.to_le_bytes());
+      headers.extend(self.entsize.to_le_bytes());
+   }
+}
+
+fn elf_header(header: &mut [u8], section_headers: u64) {
+   let mut fields = Vec::new();
+   // Magic, 64-bit, little endian, version 1, System V ABI
+   fields.extend([0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+   fields.extend(1u16.to_le_bytes()); // ET_REL
+   fields.extend(62u16.to_le_bytes()); // EM_X86_64
+   fields.extend(1u32.to_le_bytes());
+   fields.extend(0u64.to_le_bytes()); // entry point
+   fields.extend(0u64.to_le_bytes()); // program headers
+   fields.extend(section_headers.to_le_bytes());
+   fields.extend(0u32.to_le_bytes());
+   fields.extend((ELF_HEADER_SIZE as u16).to_le_bytes());
+   fields.extend(0u16.to_le_bytes());
+   fields.extend(0u16.to_le_bytes());
+   fields.extend((SECTION_HEADER_SIZE as u16).to_le_bytes());
+   fields.extend(SECTION_COUNT.to_le_bytes());
+   fields.extend(SHSTRTAB.to_le_bytes());
+   header.copy_from_slice(&fields);
+}
+
+// Appends `bytes` to the file at the given alignment, returning their offset
+fn append(file: &mut Vec<u8>, bytes: &[u8], align: usize) -> u64 {
+   file.resize(file.len().next_multiple_of(align), 0);
+   let offset = file.len() as u64;
+   file.extend_from_slice(bytes);
+   offset
+}
+
+struct StringTable {
+   bytes: Vec<u8>,
+}
+
+impl StringTable {
+   // Every string table starts with the empty string
+   fn new() -> Self {
+      StringTable { bytes: vec![0] }
+   }
+
+   fn add(&mut self, name: &str) -> u32 {
+      let offset = self.bytes.len() as u32;
+      self.bytes.extend(name.as_bytes());
+      self.bytes.push(0);
+      offset
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   // The index of the .rela.text header
+   const RELA_TEXT: usize = 4;
+
+   fn u32_at(file: &[u8], offset: usize) -> u32 {
+      u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap())
+   }
+
+   fn u64_at(file: &[u8], offset: usize) -> u64 {
+      u64::from_le_bytes(file[offset..offset + 8].try_into().unwrap())
+   }
+
+   // The file offset and size of the section with header `index`
+   fn section(file: &[u8], index: usize) -> (usize, usize) {
+      let header = u64_at(file, 0x28) as usize + index * SECTION_HEADER_SIZE as usize;
+      (u64_at(file, header + 24) as usize, u64_at(file, header + 32) as usize)
+   }
+
+   fn string(file: &[u8], table: usize, offset: usize) -> String {
+      let start = table + offset;
+      let end = start + file[start..].iter().position(|&b| b == 0).unwrap();
+      String::from_utf8(file[start..end].to_vec()).unwrap()
+   }
+
+   // Each relocation in .rela.text as its offset, symbol name, type and addend
+   fn relocations(file: &[u8]) -> Vec<(u64, String, u64, i64)> {
+      let (rela, size) = section(file, RELA_TEXT);
+      let (symtab, _) = section(file, SYMTAB as usize);
+      let (strtab, _) = section(file, STRTAB as usize);
+      (rela..rela + size).step_by(RELA_SIZE as usize).map(|entry| {
+         let info = u64_at(file, entry + 8);
+         let symbol = symtab + (info >> 32) as usize * SYMBOL_SIZE as usize;
+         let name = string(file, strtab, u32_at(file, symbol) as usize);
+         (u64_at(file, entry), name, info & 0xffff_ffff, u64_at(file, entry + 16) as i64)
+      }).collect()
+   }
+
+   #[test]
+   fn plt_and_gotpcrel_relocations() {
+      // call f@PLT; movq x@GOTPCREL(%rip), %rax
+      let object = Object {
+         text: vec![0xe8, 0, 0, 0, 0, 0x48, 0x8b, 0x05, 0, 0, 0, 0],
+         symbols: vec![Symbol { name: "main".to_string(), section: Section::Text, offset: 0, size: 12, kind: SymbolKind::Function, global: true }],
+         relocations: vec![
+            Relocation { offset: 1, symbol: "f".to_string(), kind: RelocationKind::Plt32, addend: -4 },
+            Relocation { offset: 8, symbol: "x".to_string(), kind: RelocationKind::GotPcRel, addend: -4 },
+         ],
+         ..Object::default()
+      };
+      let mut file = Vec::new();
+      write(&object, &mut file).unwrap();
+
+      assert_eq!(file[..4], [0x7f, b'E', b'L', b'F']);
+      assert_eq!(relocations(&file), vec![
+         (1, "f".to_string(), 4, -4),
+         (8, "x".to_string(), 9, -4),
+      ]);
+
+      // The symbols the relocations refer to are undefined globals, after main
+      let (symtab, size) = section(&file, SYMTAB as usize);
+      assert_eq!(size as u64, 4 * SYMBOL_SIZE);
+      for index in 2..4 {
+         let symbol = symtab + index * SYMBOL_SIZE as usize;
+         assert_eq!(file[symbol + 4], STB_GLOBAL << 4 | STT_NOTYPE);
+         assert_eq!(u16::from_le_bytes([file[symbol + 6], file[symbol + 7]]), 0);
+      }
+   }
+}
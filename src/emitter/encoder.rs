@@ -0,0 +1,556 @@
+// Encodes the assembly AST as x86-64 machine code, laying out the functions in
+// .text and the static variables in .data and .bss.
+
+use crate::codegen::assembly::*;
+use crate::emitter::elf::{Object, Relocation, RelocationKind, Section, Symbol, SymbolKind};
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+pub fn encode(assembly: &Assembly) -> Result<Object> {
+   let mut object = Object::default();
+   for top_level in &assembly.program.top_level {
+      match top_level {
+         TopLevel::Function(func) => {
+            let offset = object.text.len();
+            let mut encoder = Encoder { object: &mut object, labels: HashMap::new(), jumps: Vec::new() };
+            encoder.function(func)?;
+            object.symbols.push(Symbol {
+               name: func.name.clone(),
+               section: Section::Text,
+               offset: offset as u64,
+               size: (object.text.len() - offset) as u64,
+               kind: SymbolKind::Function,
+               global: func.global,
+            });
+         },
+         TopLevel::StaticVar(var) => {
+            let (section, offset) = if var.value != 0 {
+               let offset = object.data.len().next_multiple_of(4);
+               object.data.resize(offset, 0);
+               object.data.extend((var.value as i32).to_le_bytes());
+               (Section::Data, offset as u64)
+            } else {
+               let offset = object.bss_size.next_multiple_of(4);
+               object.bss_size = offset + 4;
+               (Section::Bss, offset)
+            };
+            object.symbols.push(Symbol {
+               name: var.name.clone(),
+               section,
+               offset,
+               size: 4,
+               kind: SymbolKind::Object,
+               global: var.global,
+            });
+         }
+      }
+   }
+   Ok(object)
+}
+
+// Immediate that follows the ModRM operand of an instruction
+#[derive(Copy, Clone)]
+enum Imm {
+   None,
+   Byte(i64),
+   Long(i64),
+}
+
+impl Imm {
+   fn size(&self) -> i64 {
+      match self {
+         Imm::None => 0,
+         Imm::Byte(_) => 1,
+         Imm::Long(_) => 4,
+      }
+   }
+}
+
+// r/m value of %rsp, which in memory operands means a SIB byte follows
+const RSP: u8 = 4;
+// r/m value of %rbp-based and, with mod 00, RIP-relative addressing
+const RBP: u8 = 5;
+
+struct Encoder<'a> {
+   object: &'a mut Object,
+   // Offsets of the labels in the current function
+   labels: HashMap<String, usize>,
+   // The rel32 fields of jumps, patched once every label is known
+   jumps: Vec<(usize, String)>,
+}
+
+impl Encoder<'_> {
+   fn function(&mut self, func: &Function) -> Result<()> {
+      // pushq %rbp; movq %rsp, %rbp
+      self.bytes(&[0x55, 0x48, 0x89, 0xe5]);
+      for instr in &func.instructions {
+         self.instruction(instr)?;
+      }
+      self.patch_jumps(&func.name)
+   }
+
+   // Fills in the rel32 field of every jump now that the labels are known
+   fn patch_jumps(&mut self, func_name: &str) -> Result<()> {
+      for (field, label) in &self.jumps {
+         let Some(target) = self.labels.get(label) else {
+            bail!("Undefined label '{}' in function '{}'", label, func_name);
+         };
+         let rel = *target as i64 - (*field as i64 + 4);
+         self.object.text[*field..*field + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+      }
+      Ok(())
+   }
+
+   fn instruction(&mut self, instr: &Instruction) -> Result<()> {
+      match instr {
+         Instruction::Mov(src, dst) => self.mov(src, dst, 4),
+         Instruction::Movb(src, dst) => self.mov(src, dst, 1),
+         Instruction::Movq(src, dst) => self.mov(src, dst, 8),
+         Instruction::Unary(op, operand) => {
+            let ext = match op {
+               UnaryOp::Neg => 3,
+               UnaryOp::Not => 2,
+            };
+            self.modrm(&[0xf7], ext, operand, 4, Imm::None)
+         },
+         Instruction::Binary(BinaryOp::Mult, src, dst) => {
+            let Operand::Register(dst) = dst else {
+               bail!("imull needs a register destination, found {:?}", dst);
+            };
+            let reg = register_number(dst);
+            match src {
+               Operand::Immediate(i) => {
+                  let imm = immediate(*i);
+                  let opcode = if matches!(imm, Imm::Byte(_)) { 0x6b } else { 0x69 };
+                  self.modrm(&[opcode], reg, &Operand::Register(dst.clone()), 4, imm)
+               },
+               _ => self.modrm(&[0x0f, 0xaf], reg, src, 4, Imm::None),
+            }
+         },
+         Instruction::Binary(op, src, dst) => {
+            // The /digit of the immediate form and the opcodes of the two register forms
+            let (ext, to_rm, to_reg) = match op {
+               BinaryOp::Add => (0, 0x01, 0x03),
+               BinaryOp::Sub => (5, 0x29, 0x2b),
+               BinaryOp::BitwiseAnd => (4, 0x21, 0x23),
+               BinaryOp::BitwiseOr => (1, 0x09, 0x0b),
+               BinaryOp::BitwiseXor => (6, 0x31, 0x33),
+               BinaryOp::Mult => unreachable!(),
+            };
+            self.arithmetic(ext, to_rm, to_reg, src, dst)
+         },
+         // cmpl computes right - left
+         Instruction::Cmp(left, right) => self.arithmetic(7, 0x39, 0x3b, left, right),
+         Instruction::Shl(count, dst) => self.shift(4, count, dst),
+         Instruction::Shr(count, dst) => self.shift(7, count, dst),
+         Instruction::Idiv(operand) => self.modrm(&[0xf7], 7, operand, 4, Imm::None),
+         Instruction::Cdq => {
+            self.bytes(&[0x99]);
+            Ok(())
+         },
+         Instruction::Jmp(label) => {
+            self.bytes(&[0xe9]);
+            self.jump(label);
+            Ok(())
+         },
+         Instruction::JmpCC(condition, label) => {
+            self.bytes(&[0x0f, 0x80 | condition_number(condition)]);
+            self.jump(label);
+            Ok(())
+         },
+         Instruction::SetCC(condition, operand) => self.modrm(&[0x0f, 0x90 | condition_number(condition)], 0, operand, 1, Imm::None),
+         Instruction::Label(label) => {
+            self.labels.insert(label.clone(), self.object.text.len());
+            Ok(())
+         },
+         // subq $size, %rsp
+         Instruction::AllocateStack(size) => self.arithmetic_rsp(5, *size),
+         // addq $size, %rsp
+         Instruction::DeallocateStack(size) => self.arithmetic_rsp(0, *size),
+         Instruction::Push(operand) => match operand {
+            Operand::Immediate(i) => {
+               match immediate(*i) {
+                  Imm::Byte(i) => self.bytes(&[0x6a, i as u8]),
+                  _ => {
+                     self.bytes(&[0x68]);
+                     self.bytes(&(*i as i32).to_le_bytes());
+                  },
+               }
+               Ok(())
+            },
+            Operand::Register(r) => {
+               let reg = register_number(r);
+               if reg >= 8 {
+                  self.bytes(&[0x41]);
+               }
+               self.bytes(&[0x50 | (reg & 7)]);
+               Ok(())
+            },
+            _ => self.modrm(&[0xff], 6, operand, 4, Imm::None),
+         },
+         Instruction::Call(name) => {
+            self.bytes(&[0xe8]);
+            self.relocation(name, RelocationKind::Plt32, -4);
+            Ok(())
+         },
+         Instruction::Return => {
+            // movq %rbp, %rsp; popq %rbp; ret
+            self.bytes(&[0x48, 0x89, 0xec, 0x5d, 0xc3]);
+            Ok(())
+         }
+      }
+   }
+
+   fn mov(&mut self, src: &Operand, dst: &Operand, size: usize) -> Result<()> {
+      let (opcode, store, load) = if size == 1 { (0xc6, 0x88, 0x8a) } else { (0xc7, 0x89, 0x8b) };
+      match (src, dst) {
+         (Operand::Immediate(i), _) => self.modrm(&[opcode], 0, dst, size, if size == 1 { Imm::Byte(*i) } else { Imm::Long(*i) }),
+         (Operand::Register(r), _) => self.modrm(&[store], register_number(r), dst, size, Imm::None),
+         (_, Operand::Register(r)) => self.modrm(&[load], register_number(r), src, size, Imm::None),
+         _ => bail!("Cannot encode a move from {:?} to {:?}", src, dst),
+      }
+   }
+
+   fn arithmetic(&mut self, ext: u8, to_rm: u8, to_reg: u8, src: &Operand, dst: &Operand) -> Result<()> {
+      match (src, dst) {
+         (Operand::Immediate(i), _) => {
+            let imm = immediate(*i);
+            let opcode = if matches!(imm, Imm::Byte(_)) { 0x83 } else { 0x81 };
+            self.modrm(&[opcode], ext, dst, 4, imm)
+         },
+         (Operand::Register(r), _) => self.modrm(&[to_rm], register_number(r), dst, 4, Imm::None),
+         (_, Operand::Register(r)) => self.modrm(&[to_reg], register_number(r), src, 4, Imm::None),
+         _ => bail!("Cannot encode an instruction from {:?} to {:?}", src, dst),
+      }
+   }
+
+   // The 64-bit form of an arithmetic instruction with an immediate, on %rsp
+   fn arithmetic_rsp(&mut self, ext: u8, value: i64) -> Result<()> {
+      let modrm = 0xc0 | ext << 3 | RSP;
+      match immediate(value) {
+         Imm::Byte(i) => self.bytes(&[0x48, 0x83, modrm, i as u8]),
+         _ => {
+            self.bytes(&[0x48, 0x81, modrm]);
+            self.bytes(&(value as i32).to_le_bytes());
+         },
+      }
+      Ok(())
+   }
+
+   fn shift(&mut self, ext: u8, count: &Operand, dst: &Operand) -> Result<()> {
+      match count {
+         Operand::Immediate(i) => self.modrm(&[0xc1], ext, dst, 4, Imm::Byte(*i)),
+         Operand::Register(Register::CX(_)) => self.modrm(&[0xd3], ext, dst, 4, Imm::None),
+         _ => bail!("Shift count must be an immediate or %cl, found {:?}", count),
+      }
+   }
+
+   // Writes an instruction with a ModRM byte. `reg` is either a register or the
+   // opcode extension, `rm` the register or memory operand and `size` the
+   // operand size in bytes.
+   fn modrm(&mut self, opcode: &[u8], reg: u8, rm: &Operand, size: usize, imm: Imm) -> Result<()> {
+      let mut rex = if size == 8 { 0x48 } else { 0 };
+      if reg >= 8 {
+         rex |= 0x44;
+      }
+      // Without a REX prefix the byte registers 4-7 are %ah..%bh rather than
+      // %spl..%dil. Opcode extensions of byte instructions are always 0.
+      if size == 1 && (4..8).contains(&reg) {
+         rex |= 0x40;
+      }
+      match rm {
+         Operand::Register(r) | Operand::Indirect(r) => {
+            let base = register_number(r);
+            if base >= 8 {
+               rex |= 0x41;
+            }
+            if size == 1 && matches!(rm, Operand::Register(_)) && (4..8).contains(&base) {
+               rex |= 0x40;
+            }
+         },
+         _ => {}
+      }
+      if rex != 0 {
+         self.bytes(&[rex]);
+      }
+      self.bytes(opcode);
+
+      let reg = (reg & 7) << 3;
+      match rm {
+         Operand::Register(r) => self.bytes(&[0xc0 | reg | (register_number(r) & 7)]),
+         Operand::Stack(offset) => self.memory(reg, RBP, *offset),
+         Operand::Indirect(r) => self.memory(reg, register_number(r), 0),
+         Operand::Data(name) | Operand::GotEntry(name) => {
+            // RIP-relative, from the end of the instruction
+            self.bytes(&[reg | RBP]);
+            let kind = if matches!(rm, Operand::GotEntry(_)) { RelocationKind::GotPcRel } else { RelocationKind::Pc32 };
+            self.relocation(name, kind, -4 - imm.size());
+         },
+         Operand::Immediate(_) | Operand::Pseudo(_) => bail!("Cannot encode {:?} as a register or memory operand", rm),
+      }
+
+      match imm {
+         Imm::None => {},
+         Imm::Byte(i) => self.bytes(&[i as u8]),
+         Imm::Long(i) => self.bytes(&(i as i32).to_le_bytes()),
+      }
+      Ok(())
+   }
+
+   // Writes the ModRM byte for the memory at `base` + `displacement`, followed by
+   // the SIB byte and displacement it needs. `reg` is already in place. With a
+   // base of %rsp or %r12 the r/m field introduces a SIB byte instead, and with
+   // %rbp or %r13 and no displacement it means RIP-relative, so those get a SIB
+   // byte naming the base and a zero displacement respectively.
+   fn memory(&mut self, reg: u8, base: u8, displacement: i64) {
+      let base = base & 7;
+      let sib: &[u8] = if base == RSP { &[0x24] } else { &[] };
+      match i8::try_from(displacement) {
+         Ok(0) if base != RBP => {
+            self.bytes(&[reg | base]);
+            self.bytes(sib);
+         },
+         Ok(displacement) => {
+            self.bytes(&[0x40 | reg | base]);
+            self.bytes(sib);
+            self.bytes(&[displacement as u8]);
+         },
+         Err(_) => {
+            self.bytes(&[0x80 | reg | base]);
+            self.bytes(sib);
+            self.bytes(&(displacement as i32).to_le_bytes());
+         },
+      }
+   }
+
+   fn jump(&mut self, label: &str) {
+      self.jumps.push((self.object.text.len(), label.to_string()));
+      self.bytes(&[0; 4]);
+   }
+
+   fn relocation(&mut self, symbol: &str, kind: RelocationKind, addend: i64) {
+      self.object.relocations.push(Relocation {
+         offset: self.object.text.len() as u64,
+         symbol: symbol.to_string(),
+         kind,
+         addend,
+      });
+      self.bytes(&[0; 4]);
+   }
+
+   fn bytes(&mut self, bytes: &[u8]) {
+      self.object.text.extend_from_slice(bytes);
+   }
+}
+
+// The shorter sign-extended imm8 form where the value fits. Operations are on
+// 32-bit ints, so only the low 32 bits of the value count.
+fn immediate(value: i64) -> Imm {
+   match i8::try_from(value as i32) {
+      Ok(byte) => Imm::Byte(byte as i64),
+      Err(_) => Imm::Long(value),
+   }
+}
+
+fn register_number(register: &Register) -> u8 {
+   match register {
+      Register::AX(_) => 0,
+      Register::CX(_) => 1,
+      Register::DX(_) => 2,
+      Register::SI(_) => 6,
+      Register::DI(_) => 7,
+      Register::R8(_) => 8,
+      Register::R9(_) => 9,
+      Register::R10(_) => 10,
+      Register::R11(_) => 11,
+   }
+}
+
+fn condition_number(condition: &ConditionCode) -> u8 {
+   match condition {
+      ConditionCode::E => 0x4,
+      ConditionCode::NE => 0x5,
+      ConditionCode::L => 0xc,
+      ConditionCode::GE => 0xd,
+      ConditionCode::LE => 0xe,
+      ConditionCode::G => 0xf,
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn encode_instruction(instr: Instruction) -> Object {
+      let mut object = Object::default();
+      let mut encoder = Encoder { object: &mut object, labels: HashMap::new(), jumps: Vec::new() };
+      encoder.instruction(&instr).unwrap();
+      object
+   }
+
+   // The expected bytes are what `as` assembles the instruction in the comment to
+   fn assert_encodes(instr: Instruction, expected: &[u8]) {
+      assert_eq!(encode_instruction(instr).text, expected);
+   }
+
+   fn reg(register: Register) -> Operand {
+      Operand::Register(register)
+   }
+
+   #[test]
+   fn moves() {
+      // movl %eax, -4(%rbp)
+      assert_encodes(Instruction::Mov(reg(Register::AX(4)), Operand::Stack(-4)), &[0x89, 0x45, 0xfc]);
+      // movl -4(%rbp), %r10d
+      assert_encodes(Instruction::Mov(Operand::Stack(-4), reg(Register::R10(4))), &[0x44, 0x8b, 0x55, 0xfc]);
+      // movl $5, -8(%rbp)
+      assert_encodes(Instruction::Mov(Operand::Immediate(5), Operand::Stack(-8)), &[0xc7, 0x45, 0xf8, 0x05, 0x00, 0x00, 0x00]);
+      // movl %eax, -200(%rbp)
+      assert_encodes(Instruction::Mov(reg(Register::AX(4)), Operand::Stack(-200)), &[0x89, 0x85, 0x38, 0xff, 0xff, 0xff]);
+      // movq %rdi, -16(%rbp)
+      assert_encodes(Instruction::Movq(reg(Register::DI(8)), Operand::Stack(-16)), &[0x48, 0x89, 0x7d, 0xf0]);
+      // movl %eax, (%rdi)
+      assert_encodes(Instruction::Mov(reg(Register::AX(4)), Operand::Indirect(Register::DI(8))), &[0x89, 0x07]);
+      // movq %r11, (%r10)
+      assert_encodes(Instruction::Movq(reg(Register::R11(8)), Operand::Indirect(Register::R10(8))), &[0x4d, 0x89, 0x1a]);
+      // movb %sil, -1(%rbp), which needs an empty REX prefix to mean %sil and not %dh
+      assert_encodes(Instruction::Movb(reg(Register::SI(1)), Operand::Stack(-1)), &[0x40, 0x88, 0x75, 0xff]);
+      // movb $1, -1(%rbp)
+      assert_encodes(Instruction::Movb(Operand::Immediate(1), Operand::Stack(-1)), &[0xc6, 0x45, 0xff, 0x01]);
+   }
+
+   #[test]
+   fn memory_operands() {
+      let memory = |base: u8, displacement: i64| {
+         let mut object = Object::default();
+         let mut encoder = Encoder { object: &mut object, labels: HashMap::new(), jumps: Vec::new() };
+         encoder.memory(0, base, displacement);
+         object.text
+      };
+      // movl %eax, (%rsp) and movl %eax, (%r12), which only differ in REX.B
+      assert_eq!(memory(4, 0), [0x04, 0x24]);
+      assert_eq!(memory(12, 0), [0x04, 0x24]);
+      // movl %eax, 8(%rsp)
+      assert_eq!(memory(4, 8), [0x44, 0x24, 0x08]);
+      // movl %eax, (%rbp) and movl %eax, (%r13)
+      assert_eq!(memory(5, 0), [0x45, 0x00]);
+      assert_eq!(memory(13, 0), [0x45, 0x00]);
+      // movl %eax, (%rdi)
+      assert_eq!(memory(7, 0), [0x07]);
+      // A zero offset from the frame pointer still needs its displacement
+      assert_encodes(Instruction::Mov(reg(Register::AX(4)), Operand::Stack(0)), &[0x89, 0x45, 0x00]);
+   }
+
+   #[test]
+   fn arithmetic() {
+      // negl %eax
+      assert_encodes(Instruction::Unary(UnaryOp::Neg, reg(Register::AX(4))), &[0xf7, 0xd8]);
+      // notl -4(%rbp)
+      assert_encodes(Instruction::Unary(UnaryOp::Not, Operand::Stack(-4)), &[0xf7, 0x55, 0xfc]);
+      // subl %eax, %r11d
+      assert_encodes(Instruction::Binary(BinaryOp::Sub, reg(Register::AX(4)), reg(Register::R11(4))), &[0x41, 0x29, 0xc3]);
+      // xorl -4(%rbp), %edx
+      assert_encodes(Instruction::Binary(BinaryOp::BitwiseXor, Operand::Stack(-4), reg(Register::DX(4))), &[0x33, 0x55, 0xfc]);
+      // imull -4(%rbp), %r11d
+      assert_encodes(Instruction::Binary(BinaryOp::Mult, Operand::Stack(-4), reg(Register::R11(4))), &[0x44, 0x0f, 0xaf, 0x5d, 0xfc]);
+      // cmpl %eax, %edx
+      assert_encodes(Instruction::Cmp(reg(Register::AX(4)), reg(Register::DX(4))), &[0x39, 0xc2]);
+      // sall $2, %eax
+      assert_encodes(Instruction::Shl(Operand::Immediate(2), reg(Register::AX(4))), &[0xc1, 0xe0, 0x02]);
+      // sarl %cl, %edx
+      assert_encodes(Instruction::Shr(reg(Register::CX(1)), reg(Register::DX(4))), &[0xd3, 0xfa]);
+      // idivl %r10d
+      assert_encodes(Instruction::Idiv(reg(Register::R10(4))), &[0x41, 0xf7, 0xfa]);
+      // cltd
+      assert_encodes(Instruction::Cdq, &[0x99]);
+   }
+
+   #[test]
+   fn immediates_use_imm8_when_they_fit() {
+      // addl $1, %eax
+      assert_encodes(Instruction::Binary(BinaryOp::Add, Operand::Immediate(1), reg(Register::AX(4))), &[0x83, 0xc0, 0x01]);
+      // addl $1000, %ecx
+      assert_encodes(Instruction::Binary(BinaryOp::Add, Operand::Immediate(1000), reg(Register::CX(4))), &[0x81, 0xc1, 0xe8, 0x03, 0x00, 0x00]);
+      // addl $-128, %r10d and addl $128, %r10d, on either side of the imm8 range
+      assert_encodes(Instruction::Binary(BinaryOp::Add, Operand::Immediate(-128), reg(Register::R10(4))), &[0x41, 0x83, 0xc2, 0x80]);
+      assert_encodes(Instruction::Binary(BinaryOp::Add, Operand::Immediate(128), reg(Register::R10(4))), &[0x41, 0x81, 0xc2, 0x80, 0x00, 0x00, 0x00]);
+      // cmpl $0, -4(%rbp)
+      assert_encodes(Instruction::Cmp(Operand::Immediate(0), Operand::Stack(-4)), &[0x83, 0x7d, 0xfc, 0x00]);
+      // imull $3, %eax and imull $1000, %r10d
+      assert_encodes(Instruction::Binary(BinaryOp::Mult, Operand::Immediate(3), reg(Register::AX(4))), &[0x6b, 0xc0, 0x03]);
+      assert_encodes(Instruction::Binary(BinaryOp::Mult, Operand::Immediate(1000), reg(Register::R10(4))), &[0x45, 0x69, 0xd2, 0xe8, 0x03, 0x00, 0x00]);
+      // pushq $1 and pushq $1000
+      assert_encodes(Instruction::Push(Operand::Immediate(1)), &[0x6a, 0x01]);
+      assert_encodes(Instruction::Push(Operand::Immediate(1000)), &[0x68, 0xe8, 0x03, 0x00, 0x00]);
+      // subq $16, %rsp and addq $1000, %rsp
+      assert_encodes(Instruction::AllocateStack(16), &[0x48, 0x83, 0xec, 0x10]);
+      assert_encodes(Instruction::DeallocateStack(1000), &[0x48, 0x81, 0xc4, 0xe8, 0x03, 0x00, 0x00]);
+   }
+
+   #[test]
+   fn set_push_and_return() {
+      // sete %al
+      assert_encodes(Instruction::SetCC(ConditionCode::E, reg(Register::AX(1))), &[0x0f, 0x94, 0xc0]);
+      // setl %sil
+      assert_encodes(Instruction::SetCC(ConditionCode::L, reg(Register::SI(1))), &[0x40, 0x0f, 0x9c, 0xc6]);
+      // setg %r11b
+      assert_encodes(Instruction::SetCC(ConditionCode::G, reg(Register::R11(1))), &[0x41, 0x0f, 0x9f, 0xc3]);
+      // pushq %rax, pushq %r9 and pushq -4(%rbp)
+      assert_encodes(Instruction::Push(reg(Register::AX(8))), &[0x50]);
+      assert_encodes(Instruction::Push(reg(Register::R9(8))), &[0x41, 0x51]);
+      assert_encodes(Instruction::Push(Operand::Stack(-4)), &[0xff, 0x75, 0xfc]);
+      // movq %rbp, %rsp; popq %rbp; ret
+      assert_encodes(Instruction::Return, &[0x48, 0x89, 0xec, 0x5d, 0xc3]);
+   }
+
+   #[test]
+   fn jumps_are_patched() {
+      let mut object = Object::default();
+      let mut encoder = Encoder { object: &mut object, labels: HashMap::new(), jumps: Vec::new() };
+      let instructions = [
+         Instruction::Label("top".to_string()),
+         Instruction::JmpCC(ConditionCode::NE, "end".to_string()),
+         Instruction::Jmp("top".to_string()),
+         Instruction::Label("end".to_string()),
+      ];
+      for instr in &instructions {
+         encoder.instruction(instr).unwrap();
+      }
+      encoder.patch_jumps("f").unwrap();
+      // jne +5 over the jmp, then jmp -11 back to the jne
+      assert_eq!(object.text, [0x0f, 0x85, 0x05, 0x00, 0x00, 0x00, 0xe9, 0xf5, 0xff, 0xff, 0xff]);
+   }
+
+   #[test]
+   fn relocations() {
+      // movl x(%rip), %eax
+      let object = encode_instruction(Instruction::Mov(Operand::Data("x".to_string()), reg(Register::AX(4))));
+      assert_eq!(object.text, [0x8b, 0x05, 0x00, 0x00, 0x00, 0x00]);
+      let relocation = &object.relocations[0];
+      assert!(matches!(relocation.kind, RelocationKind::Pc32));
+      assert_eq!((relocation.offset, relocation.addend), (2, -4));
+
+      // addl $1, x(%rip) and movl $5, x(%rip): the field is further from the end
+      // of the instruction by the size of the immediate
+      let object = encode_instruction(Instruction::Binary(BinaryOp::Add, Operand::Immediate(1), Operand::Data("x".to_string())));
+      assert_eq!(object.text, [0x83, 0x05, 0x00, 0x00, 0x00, 0x00, 0x01]);
+      assert_eq!(object.relocations[0].addend, -5);
+      let object = encode_instruction(Instruction::Mov(Operand::Immediate(5), Operand::Data("x".to_string())));
+      assert_eq!(object.relocations[0].addend, -8);
+
+      // movq x@GOTPCREL(%rip), %rax
+      let object = encode_instruction(Instruction::Movq(Operand::GotEntry("x".to_string()), reg(Register::AX(8))));
+      assert_eq!(object.text, [0x48, 0x8b, 0x05, 0x00, 0x00, 0x00, 0x00]);
+      let relocation = &object.relocations[0];
+      assert!(matches!(relocation.kind, RelocationKind::GotPcRel));
+      assert_eq!((relocation.offset, relocation.addend), (3, -4));
+
+      // call f@PLT
+      let object = encode_instruction(Instruction::Call("f".to_string()));
+      assert_eq!(object.text, [0xe8, 0x00, 0x00, 0x00, 0x00]);
+      let relocation = &object.relocations[0];
+      assert!(matches!(relocation.kind, RelocationKind::Plt32));
+      assert_eq!((relocation.symbol.as_str(), relocation.offset, relocation.addend), ("f", 1, -4));
+   }
+}
@@ -1,12 +1,23 @@
+mod elf;
+mod encoder;
+
 use crate::codegen::assembly::*;
+use crate::codegen::target::Target;
 
-use anyhow::Result;
-use std::fs::File;
+use anyhow::{bail, Result};
 use std::io::Write;
-use std::path::Path;
 
-pub fn emit_code(assembly_ast: &Assembly, output: &Path) -> Result<()> {
-   let mut output = File::create(output)?;
+pub fn emit_code(assembly_ast: &Assembly, output: &mut impl Write) -> Result<()> {
    writeln!(output, "{}", assembly_ast)?;
    Ok(())
 }
+
+// Writes the program as a relocatable object, without going through an
+// external assembler
+pub fn emit_object(assembly_ast: &Assembly, output: &mut impl Write) -> Result<()> {
+   if assembly_ast.target != Target::LinuxGnu {
+      bail!("The integrated assembler only writes ELF objects, drop -fintegrated-as for {}", assembly_ast.target.triple());
+   }
+   let object = encoder::encode(assembly_ast)?;
+   elf::write(&object, output)
+}
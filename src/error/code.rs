@@ -0,0 +1,634 @@
+use std::fmt;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Category {
+   Lexical,
+   Syntax,
+   Semantic,
+   Warning,
+}
+
+impl Category {
+   pub fn name(&self) -> &'static str {
+      match self {
+         Category::Lexical => "Lexical Error",
+         Category::Syntax => "Syntax Error",
+         Category::Semantic => "Semantic Error",
+         Category::Warning => "Warning",
+      }
+   }
+}
+
+// Declares every diagnostic the compiler can report. Ids are stable: once
+// published, an id must never be reused for a different diagnostic.
+macro_rules! codes {
+   ($($name:ident = $id:literal, $category:ident, $summary:literal, $explanation:literal;)*) => {
+      #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+      pub enum Code {
+         $($name,)*
+      }
+
+      impl Code {
+         pub const ALL: &[Self] = &[$(Code::$name,)*];
+
+         pub fn id(&self) -> &'static str {
+            match self {
+               $(Code::$name => $id,)*
+            }
+         }
+
+         pub fn category(&self) -> Category {
+            match self {
+               $(Code::$name => Category::$category,)*
+            }
+         }
+
+         pub fn summary(&self) -> &'static str {
+            match self {
+               $(Code::$name => $summary,)*
+            }
+         }
+
+         pub fn explanation(&self) -> &'static str {
+            match self {
+               $(Code::$name => $explanation,)*
+            }
+         }
+      }
+   };
+}
+
+codes! {
+   InvalidCharacter = "E0001", Lexical, "invalid character",
+r#"A character that cannot start any token appeared in the source.
+
+Erroneous code example:
+
+    int main(void) {
+        return 1 $ 2;
+    }
+
+Only the characters used by C operators, punctuation, identifiers and integer
+constants may appear outside of comments."#;
+
+   InvalidNumericLiteral = "E0002", Lexical, "invalid numeric literal",
+r#"An integer constant was immediately followed by letters, which does not form
+a valid constant or identifier.
+
+Erroneous code example:
+
+    int main(void) {
+        int x = 123abc;
+        return x;
+    }
+
+Identifiers cannot start with a digit. Separate the constant from the name or
+rename the identifier so that it starts with a letter or underscore."#;
+
+   ExpectedToken = "E0010", Syntax, "unexpected token",
+r#"The parser required a specific token, such as ';' or ')', but found something
+else.
+
+Erroneous code example:
+
+    int main(void) {
+        int x = 1
+        return x;
+    }
+
+Add the missing token. In the example above, the declaration must end with a
+semicolon:
+
+    int x = 1;"#;
+
+   ExpectedTypeOrStorageClass = "E0011", Syntax, "expected a type or storage class",
+r#"A declaration must start with a type and optionally a storage class
+specifier.
+
+Erroneous code example:
+
+    main(void) {
+        return 0;
+    }
+
+Declare the type explicitly:
+
+    int main(void) {
+        return 0;
+    }"#;
+
+   InvalidTypeSpecifier = "E0012", Syntax, "invalid type specifier",
+r#"A declaration has no type specifier, or more than one.
+
+Erroneous code example:
+
+    static x;
+    int int y;
+
+Give each declaration exactly one type:
+
+    static int x;
+    int y;"#;
+
+   InvalidStorageClass = "E0013", Syntax, "conflicting storage class specifiers",
+r#"A declaration has more than one storage class specifier.
+
+Erroneous code example:
+
+    static extern int x;
+
+A declaration may be 'static' or 'extern', but not both."#;
+
+   ExpectedIdentifier = "E0014", Syntax, "expected an identifier",
+r#"A name was required, for example after the type in a declaration or in a
+parameter list, but some other token was found.
+
+Erroneous code example:
+
+    int 3 = 4;
+    int f(int);
+
+Every declaration and every parameter of a function must be named:
+
+    int x = 4;
+    int f(int a);"#;
+
+   ExpectedExpression = "E0015", Syntax, "expected an expression",
+r#"An expression was required, but the next token cannot start one.
+
+Erroneous code example:
+
+    int main(void) {
+        return 1 + ;
+    }
+
+Complete the expression:
+
+    int main(void) {
+        return 1 + 2;
+    }"#;
+
+   InlineVariable = "E0016", Syntax, "'inline' on a variable",
+r#"The 'inline' function specifier was used in the declaration of a variable.
+
+Erroneous code example:
+
+    static inline int counter = 0;
+
+Only functions can be declared 'inline':
+
+    static int counter = 0;
+    static inline int next(void) { return counter + 1; }"#;
+
+   CaseOutsideSwitch = "E0100", Semantic, "case label outside of switch",
+r#"A 'case' label appeared outside the body of a switch statement.
+
+Erroneous code example:
+
+    int main(void) {
+        case 1: return 0;
+    }
+
+Case labels are only meaningful inside a switch:
+
+    int main(void) {
+        switch (1) {
+            case 1: return 0;
+        }
+        return 1;
+    }"#;
+
+   DefaultOutsideSwitch = "E0101", Semantic, "default label outside of switch",
+r#"A 'default' label appeared outside the body of a switch statement.
+
+Erroneous code example:
+
+    int main(void) {
+        default: return 0;
+    }
+
+Move the label into a switch statement, or remove it."#;
+
+   DuplicateCase = "E0102", Semantic, "duplicate case value",
+r#"Two case labels of the same switch statement have the same value, so it is
+ambiguous which one control should jump to.
+
+Erroneous code example:
+
+    int f(int x) {
+        switch (x) {
+            case 1: return 10;
+            case 1: return 20;
+        }
+        return 0;
+    }
+
+Each case value may appear only once per switch. Merge the two cases or change
+one of the values."#;
+
+   MultipleDefaults = "E0103", Semantic, "multiple default labels",
+r#"A switch statement has more than one 'default' label.
+
+Erroneous code example:
+
+    int f(int x) {
+        switch (x) {
+            default: return 1;
+            default: return 2;
+        }
+    }
+
+A switch may have at most one default label."#;
+
+   NonConstantCase = "E0104", Semantic, "case value is not a constant",
+r#"The value of a case label must be an integer constant.
+
+Erroneous code example:
+
+    int f(int x, int y) {
+        switch (x) {
+            case y: return 1;
+        }
+        return 0;
+    }
+
+Use an integer constant for the case, or replace the switch with an if
+statement comparing against the variable."#;
+
+   BreakOutsideLoop = "E0105", Semantic, "break outside of loop or switch",
+r#"A 'break' statement appeared outside of any loop or switch statement, so
+there is nothing for it to break out of.
+
+Erroneous code example:
+
+    int main(void) {
+        break;
+        return 0;
+    }
+
+Remove the break statement, or use 'return' to leave the function."#;
+
+   ContinueOutsideLoop = "E0106", Semantic, "continue outside of loop",
+r#"A 'continue' statement appeared outside of any loop. Note that a switch
+statement is not a loop.
+
+Erroneous code example:
+
+    int f(int x) {
+        switch (x) {
+            case 1: continue;
+        }
+        return 0;
+    }
+
+Only use continue inside a while, do-while or for loop."#;
+
+   UndefinedLabel = "E0107", Semantic, "use of undefined label",
+r#"A 'goto' statement refers to a label that is not defined in the same
+function.
+
+Erroneous code example:
+
+    int main(void) {
+        goto end;
+        return 1;
+    }
+
+Define the label in the same function as the goto:
+
+    int main(void) {
+        goto end;
+        return 1;
+    end:
+        return 0;
+    }"#;
+
+   DuplicateLabel = "E0108", Semantic, "duplicate label",
+r#"The same label is defined more than once in a function, so a goto to it
+would be ambiguous.
+
+Erroneous code example:
+
+    int main(void) {
+    retry:
+        ;
+    retry:
+        return 0;
+    }
+
+Labels have function scope: every label in a function must have a distinct
+name, even when they are in different blocks."#;
+
+   UndeclaredVariable = "E0200", Semantic, "use of undeclared variable",
+r#"A variable was used without being declared in any enclosing scope.
+
+Erroneous code example:
+
+    int main(void) {
+        return count;
+    }
+
+Declare the variable before using it, and check the name for typos:
+
+    int main(void) {
+        int count = 0;
+        return count;
+    }"#;
+
+   UndeclaredFunction = "E0201", Semantic, "call to undeclared function",
+r#"A function was called without being declared first.
+
+Erroneous code example:
+
+    int main(void) {
+        return helper();
+    }
+    int helper(void) { return 0; }
+
+Declare the function before the call:
+
+    int helper(void);
+    int main(void) {
+        return helper();
+    }
+    int helper(void) { return 0; }"#;
+
+   Redeclaration = "E0202", Semantic, "conflicting declarations",
+r#"A name was declared twice in the same scope in a way that is not allowed,
+for example two local variables or two parameters with the same name.
+
+Erroneous code example:
+
+    int f(int a, int a) {
+        int b = 1;
+        int b = 2;
+        return a + b;
+    }
+
+Rename one of the declarations, or declare the second one in a nested
+block."#;
+
+   NestedFunctionDefinition = "E0203", Semantic, "nested function definition",
+r#"A function was defined inside the body of another function. C only allows
+function declarations at block scope.
+
+Erroneous code example:
+
+    int main(void) {
+        int helper(void) { return 1; }
+        return helper();
+    }
+
+Move the definition to file scope."#;
+
+   InvalidLvalue = "E0204", Semantic, "invalid lvalue",
+r#"The left side of an assignment, or the operand of '++' or '--', must be a
+variable.
+
+Erroneous code example:
+
+    int main(void) {
+        int x = 1;
+        x + 1 = 3;
+        return ++3;
+    }
+
+Only assign to and increment variables."#;
+
+   NonConstantInitializer = "E0300", Semantic, "initializer is not a constant",
+r#"Variables with static storage duration, i.e. file scope variables and local
+variables declared 'static', must be initialized with a constant.
+
+Erroneous code example:
+
+    int a = 1;
+    int b = a + 1;
+
+Use a constant initializer, or assign the value at run time inside a
+function."#;
+
+   RedeclaredAsVariable = "E0301", Semantic, "function redeclared as a variable",
+r#"A name that was declared as a function is declared again as a variable.
+
+Erroneous code example:
+
+    int f(void);
+    int f;
+
+Functions and variables with linkage share one namespace; use different
+names."#;
+
+   ConflictingStorageClass = "E0302", Semantic, "conflicting linkage",
+r#"Declarations of the same variable or function disagree about whether it has
+internal ('static') or external linkage.
+
+Erroneous code example:
+
+    int x;
+    static int x;
+
+All declarations of an object must agree on its linkage. A later 'extern'
+declaration inherits the linkage of the earlier one."#;
+
+   ConflictingDefinitions = "E0303", Semantic, "variable defined more than once",
+r#"A file scope variable was given an initializer more than once.
+
+Erroneous code example:
+
+    int x = 1;
+    int x = 2;
+
+A variable may be declared many times but defined (initialized) only once."#;
+
+   FunctionRedefined = "E0304", Semantic, "function defined more than once",
+r#"A function was given a body more than once.
+
+Erroneous code example:
+
+    int f(void) { return 1; }
+    int f(void) { return 2; }
+
+Remove one of the definitions, or turn it into a declaration:
+
+    int f(void);"#;
+
+   IncompatibleFunctionDeclarations = "E0305", Semantic, "incompatible function declarations",
+r#"Two declarations of the same function disagree about its parameters, or a
+variable was redeclared as a function.
+
+Erroneous code example:
+
+    int f(int a);
+    int f(int a, int b);
+
+Every declaration of a function must have the same number of parameters."#;
+
+   StaticFunctionInBlockScope = "E0306", Semantic, "static function declared in block scope",
+r#"A function declaration inside a block may not use the 'static' storage
+class.
+
+Erroneous code example:
+
+    int main(void) {
+        static int helper(void);
+        return helper();
+    }
+
+Declare static functions at file scope."#;
+
+   StaticInForInit = "E0307", Semantic, "static variable in for loop initializer",
+r#"The declaration in the initializer of a for loop cannot be 'static'.
+
+Erroneous code example:
+
+    int main(void) {
+        for (static int i = 0; i < 10; i++)
+            ;
+        return 0;
+    }
+
+Declare the loop variable without a storage class, or declare the static
+variable before the loop."#;
+
+   ExternLocalInitializer = "E0308", Semantic, "initializer on local extern declaration",
+r#"A local 'extern' declaration refers to a variable defined elsewhere, so it
+cannot have an initializer.
+
+Erroneous code example:
+
+    int main(void) {
+        extern int x = 3;
+        return x;
+    }
+
+Move the initializer to the file scope definition of the variable."#;
+
+   FunctionUsedAsVariable = "E0309", Semantic, "function used as a variable",
+r#"The name of a function was used where a variable is expected.
+
+Erroneous code example:
+
+    int f(void) { return 1; }
+    int main(void) {
+        return f + 1;
+    }
+
+Call the function instead:
+
+    return f() + 1;"#;
+
+   VariableUsedAsFunction = "E0310", Semantic, "variable called as a function",
+r#"A variable was called as if it were a function.
+
+Erroneous code example:
+
+    int main(void) {
+        int x = 1;
+        return x();
+    }
+
+Only functions can be called."#;
+
+   WrongArgumentCount = "E0311", Semantic, "wrong number of arguments",
+r#"A function was called with a different number of arguments than it was
+declared with.
+
+Erroneous code example:
+
+    int add(int a, int b);
+    int main(void) {
+        return add(1);
+    }
+
+Pass exactly one argument per declared parameter."#;
+
+   UnusedVariable = "W0001", Warning, "unused variable",
+r#"A local variable is declared but never referenced. Enabled by -Wall or
+-Wunused-variable.
+
+Example:
+
+    int main(void) {
+        int unused = 3;
+        return 0;
+    }
+
+Remove the variable, or use it."#;
+
+   UnusedParameter = "W0002", Warning, "unused parameter",
+r#"A function parameter is never referenced in the function body. Enabled by
+-Wextra or -Wunused-parameter.
+
+Example:
+
+    int first(int a, int b) {
+        return a;
+    }
+
+Remove the parameter if no caller needs it, or disable the warning with
+-Wno-unused-parameter."#;
+
+   UnusedLabel = "W0003", Warning, "unused label",
+r#"A label is defined but no goto statement jumps to it. Enabled by -Wall or
+-Wunused-label.
+
+Example:
+
+    int main(void) {
+    done:
+        return 0;
+    }
+
+Remove the label."#;
+
+   MissingReturn = "W0004", Warning, "control reaches end of non-void function",
+r#"Control can reach the end of a function that returns a value without
+executing a return statement, so the caller receives an indeterminate value.
+The main function is exempt because it implicitly returns 0. Enabled by -Wall
+or -Wreturn-type.
+
+Example:
+
+    int sign(int x) {
+        if (x > 0) return 1;
+        if (x < 0) return -1;
+    }
+
+Make sure every path ends with a return statement:
+
+    int sign(int x) {
+        if (x > 0) return 1;
+        if (x < 0) return -1;
+        return 0;
+    }"#;
+
+   ImplicitFallthrough = "W0005", Warning, "implicit fallthrough",
+r#"The statements of a switch case can run on into the next case label because
+they do not end with break, return, continue or goto. Enabled by -Wextra or
+-Wimplicit-fallthrough.
+
+Example:
+
+    int f(int x) {
+        int y = 0;
+        switch (x) {
+            case 1: y = 1;
+            case 2: y = 2; break;
+        }
+        return y;
+    }
+
+Add a break statement if the fallthrough is unintended."#;
+}
+
+impl Code {
+   pub fn from_id(id: &str) -> Option<Self> {
+      Self::ALL.iter().copied().find(|code| code.id().eq_ignore_ascii_case(id))
+   }
+}
+
+impl fmt::Display for Code {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", self.id())
+   }
+}
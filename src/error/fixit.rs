@@ -0,0 +1,283 @@
+use super::Error;
+use crate::lexer;
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// A range of characters on a single source line. Columns are 1-based.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Span {
+   pub line: usize,
+   pub column: usize,
+   pub len: usize,
+}
+
+impl Span {
+   pub fn new(line: usize, column: usize, len: usize) -> Self {
+      Span { line, column, len }
+   }
+
+   // The empty span right after this one
+   pub fn end(&self) -> Self {
+      Span::new(self.line, self.column + self.len, 0)
+   }
+
+   // Extends this span up to the end of `other`, if both are on the same line
+   pub fn to(&self, other: Span) -> Self {
+      if other.line != self.line || other.column < self.column {
+         return *self;
+      }
+      Span::new(self.line, self.column, other.column + other.len - self.column)
+   }
+}
+
+// A machine-applicable edit that replaces the text covered by `span`
+#[derive(Clone, Debug)]
+pub struct FixIt {
+   pub span: Span,
+   pub replacement: String,
+}
+
+impl FixIt {
+   pub fn insert(at: Span, text: &str) -> Self {
+      FixIt { span: at.end(), replacement: text.to_string() }
+   }
+
+   pub fn replace(span: Span, text: &str) -> Self {
+      FixIt { span, replacement: text.to_string() }
+   }
+
+   pub fn remove(span: Span) -> Self {
+      FixIt { span, replacement: String::new() }
+   }
+}
+
+impl fmt::Display for FixIt {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      if self.span.len == 0 {
+         write!(f, "insert '{}'", self.replacement)?;
+      } else if self.replacement.is_empty() {
+         write!(f, "remove {} characters", self.span.len)?;
+      } else {
+         write!(f, "replace with '{}'", self.replacement)?;
+      }
+      write!(f, " at line {}, column {}", self.span.line, self.span.column)
+   }
+}
+
+// Maps columns of the preprocessed text of one file, which the preprocessor
+// collapses runs of whitespace in, back onto the file as written
+pub struct SourceMap<'a> {
+   lines: Vec<&'a str>,
+   expanded: HashMap<usize, &'a str>,
+}
+
+impl<'a> SourceMap<'a> {
+   // `file` names the header `source` was read from, or is None for the file
+   // being compiled
+   pub fn new(source: &'a str, preprocessed: &'a str, file: Option<&str>) -> Self {
+      SourceMap { lines: source.split('\n').collect(), expanded: expanded_lines(preprocessed, file) }
+   }
+
+   // The column of `line` that `column` of its preprocessed text corresponds to,
+   // or None where the line was rewritten, as by a macro expansion
+   pub fn column(&self, line: usize, column: usize) -> Option<usize> {
+      let original = self.lines.get(line.checked_sub(1)?)?;
+      map_column(original, self.expanded.get(&line)?, column)
+   }
+
+   pub fn span(&self, span: Span) -> Option<Span> {
+      let start = self.column(span.line, span.column)?;
+      let end = self.column(span.line, span.column + span.len)?.max(start);
+      Some(Span::new(span.line, start, end - start))
+   }
+}
+
+// Applies the fix-its to `source`. Spans point into `preprocessed`, the output
+// of the preprocessor, so each one is mapped back onto the original line and
+// skipped when that fails, as on a line a macro was expanded in. Fix-its that
+// overlap one applied before them are skipped too, so applying the result again
+// never corrupts the file.
+pub fn apply(source: &str, preprocessed: &str, fixits: &[&FixIt]) -> (String, usize) {
+   let map = SourceMap::new(source, preprocessed, None);
+   let mut fixits: Vec<(Span, &str)> = fixits.iter()
+      .filter_map(|fixit| Some((map.span(fixit.span)?, fixit.replacement.as_str())))
+      .collect();
+   fixits.sort_by_key(|(span, _)| (span.line, span.column));
+
+   let mut lines: Vec<String> = source.split('\n').map(String::from).collect();
+   let mut applied = 0;
+   // Edits are made right to left so the columns of the remaining ones stay valid
+   let mut last: Option<Span> = None;
+   for (span, replacement) in fixits.into_iter().rev() {
+      if let Some(last) = last && last.line == span.line && span.column + span.len > last.column {
+         continue;
+      }
+      let line = &mut lines[span.line - 1];
+      let start = span.column - 1;
+      line.replace_range(start..start + span.len, replacement);
+      // Don't leave behind a blank line when a whole statement is removed
+      if replacement.is_empty() && line.trim().is_empty() {
+         lines.remove(span.line - 1);
+      }
+      last = Some(span);
+      applied += 1;
+   }
+   (lines.join("\n"), applied)
+}
+
+// Collects the preprocessed text of each line of `file`, or of the main file,
+// which is the one named by the first line marker, if `file` is None
+fn expanded_lines<'a>(preprocessed: &'a str, file: Option<&str>) -> HashMap<usize, &'a str> {
+   let mut lines = HashMap::new();
+   let mut main_file = None;
+   let mut in_file = false;
+   let mut line = 1;
+   for text in preprocessed.lines() {
+      if let Some(marker) = text.strip_prefix('#') {
+         let mut parts = marker.split_whitespace();
+         if let Some(number) = parts.next().and_then(|n| n.parse().ok()) {
+            let marked = lexer::marker_file(marker);
+            let main_file = main_file.get_or_insert_with(|| marked.clone());
+            in_file = match file {
+               Some(file) => marked.as_deref() == Some(file),
+               None => marked == *main_file,
+            };
+            line = number;
+            continue;
+         }
+      }
+      if in_file {
+         lines.entry(line).or_insert(text);
+      }
+      line += 1;
+   }
+   lines
+}
+
+// Maps a 1-based column of the preprocessed `expanded` line onto `original`.
+// The preprocessor collapses runs of whitespace, so those only need to line up
+// as a whole; any other difference means the line was rewritten. A column on a
+// token maps to that token, and one between tokens to right after the previous.
+fn map_column(original: &str, expanded: &str, column: usize) -> Option<usize> {
+   let (original, expanded) = (original.as_bytes(), expanded.as_bytes());
+   let target = column - 1;
+   if target > expanded.len() {
+      return None;
+   }
+   let (mut i, mut j) = (0, 0);
+   loop {
+      while j < target && expanded[j].is_ascii_whitespace() {
+         j += 1;
+      }
+      if j == target {
+         break;
+      }
+      while i < original.len() && original[i].is_ascii_whitespace() {
+         i += 1;
+      }
+      if original.get(i) != Some(&expanded[j]) {
+         return None;
+      }
+      i += 1;
+      j += 1;
+   }
+   if expanded.get(target).is_some_and(|c| !c.is_ascii_whitespace()) {
+      while i < original.len() && original[i].is_ascii_whitespace() {
+         i += 1;
+      }
+   }
+   Some(i + 1)
+}
+
+// Rewrites the file at `path` with every fix-it attached to `errors`, returning
+// how many were applied. `preprocessed` is the source the errors refer to. The
+// fix-its of errors in included headers are left alone.
+pub fn apply_to_file(path: &Path, preprocessed: &str, errors: &[Error]) -> Result<usize> {
+   let fixits: Vec<&FixIt> = errors.iter().filter(|e| e.file.is_none()).flat_map(|e| &e.fixits).collect();
+   if fixits.is_empty() {
+      return Ok(0);
+   }
+   let source = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+   let (fixed, applied) = apply(&source, preprocessed, &fixits);
+   fs::write(path, fixed).with_context(|| format!("Failed to write {}", path.display()))?;
+   Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn apply_one(source: &str, preprocessed: &str, fixit: FixIt) -> (String, usize) {
+      apply(source, preprocessed, &[&fixit])
+   }
+
+   #[test]
+   fn applies_edits_right_to_left() {
+      let source = "int main(void) {\n   retrun 0\n}";
+      let preprocessed = "# 1 \"main.c\"\nint main(void) {\n   retrun 0\n}";
+      let insert = FixIt::insert(Span::new(2, 11, 1), ";");
+      let replace = FixIt::replace(Span::new(2, 4, 6), "return");
+      let (fixed, applied) = apply(source, preprocessed, &[&replace, &insert]);
+      assert_eq!(fixed, "int main(void) {\n   return 0;\n}");
+      assert_eq!(applied, 2);
+   }
+
+   #[test]
+   fn skips_overlapping_edits() {
+      let source = "int x = y;";
+      let preprocessed = "# 1 \"main.c\"\nint x = y;";
+      let first = FixIt::replace(Span::new(1, 9, 1), "z");
+      let second = FixIt::remove(Span::new(1, 7, 3));
+      let (fixed, applied) = apply(source, preprocessed, &[&first, &second]);
+      assert_eq!(fixed, "int x = z;");
+      assert_eq!(applied, 1);
+   }
+
+   #[test]
+   fn removes_blank_lines() {
+      let source = "int main(void) {\n   break;\n   return 0;\n}";
+      let preprocessed = format!("# 1 \"main.c\"\n{}", source);
+      let (fixed, applied) = apply_one(source, &preprocessed, FixIt::remove(Span::new(2, 4, 6)));
+      assert_eq!(fixed, "int main(void) {\n   return 0;\n}");
+      assert_eq!(applied, 1);
+   }
+
+   #[test]
+   fn maps_columns_over_collapsed_whitespace() {
+      let source = "\tint  x = 1\t/* one */";
+      let preprocessed = "# 1 \"main.c\"\n int x = 1";
+      let (fixed, applied) = apply_one(source, preprocessed, FixIt::insert(Span::new(1, 10, 1), ";"));
+      assert_eq!(fixed, "\tint  x = 1;\t/* one */");
+      assert_eq!(applied, 1);
+
+      let (fixed, applied) = apply_one(source, preprocessed, FixIt::replace(Span::new(1, 6, 1), "y"));
+      assert_eq!(fixed, "\tint  y = 1\t/* one */");
+      assert_eq!(applied, 1);
+   }
+
+   #[test]
+   fn skips_macro_expanded_lines() {
+      let source = "#define ONE 1\nint x = ONE\nint main(void) { return x; }";
+      let preprocessed = "# 1 \"main.c\"\n\nint x = 1\nint main(void) { return x; }";
+      let (fixed, applied) = apply_one(source, preprocessed, FixIt::insert(Span::new(2, 9, 1), ";"));
+      assert_eq!(fixed, source);
+      assert_eq!(applied, 0);
+   }
+
+   #[test]
+   fn follows_line_markers() {
+      let source = "#include \"a.h\"\nint x = 1";
+      let preprocessed = "# 1 \"main.c\"\n# 1 \"a.h\" 1\nint x = 1\n# 2 \"main.c\" 2\nint x = 1";
+      let (fixed, applied) = apply_one(source, preprocessed, FixIt::insert(Span::new(2, 9, 1), ";"));
+      assert_eq!(fixed, "#include \"a.h\"\nint x = 1;");
+      assert_eq!(applied, 1);
+
+      let (fixed, applied) = apply_one(source, preprocessed, FixIt::insert(Span::new(1, 9, 1), ";"));
+      assert_eq!(fixed, source);
+      assert_eq!(applied, 0);
+   }
+}
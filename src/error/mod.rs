@@ -1,44 +1,99 @@
+pub mod code;
+pub mod fixit;
+pub mod render;
+pub mod warning;
+
+use std::fmt;
 use thiserror::Error;
 
+pub use code::Code;
+pub use fixit::{FixIt, Span};
+use warning::WarningKind;
+
+#[derive(Error, Debug)]
+pub struct Error {
+   pub code: Code,
+   pub line: usize,
+   pub column: Option<usize>,
+   pub msg: String,
+   pub notes: Vec<String>,
+   pub fixits: Vec<FixIt>,
+   // The header the error is in, or None for the file being compiled
+   pub file: Option<String>,
+}
+
+impl fmt::Display for Error {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match WarningKind::from_code(self.code) {
+         Some(kind) => write!(f, "[line {}] Error [{}]: {} [-Werror={}]", self.line, self.code, self.msg, kind.name())?,
+         None => write!(f, "[line {}] {} [{}]: {}", self.line, self.code.category().name(), self.code, self.msg)?,
+      }
+      for note in &self.notes {
+         write!(f, "\n  note: {}", note)?;
+      }
+      for fixit in &self.fixits {
+         write!(f, "\n  fix-it: {}", fixit)?;
+      }
+      Ok(())
+   }
+}
+
+impl Error {
+   pub fn with_column(mut self, column: usize) -> Self {
+      self.column = Some(column);
+      self
+   }
+
+   pub fn with_note(mut self, note: String) -> Self {
+      self.notes.push(note);
+      self
+   }
+
+   pub fn with_fixit(mut self, fixit: FixIt) -> Self {
+      self.fixits.push(fixit);
+      self
+   }
+
+   pub fn with_file(mut self, file: Option<&str>) -> Self {
+      self.file = file.map(String::from);
+      self
+   }
+}
+
+pub fn error(line: usize, msg: String, code: Code) -> Error {
+   Error { code, line, column: None, msg, notes: Vec::new(), fixits: Vec::new(), file: None }
+}
+
 #[derive(Error, Debug)]
-pub enum Error {
-   #[error("[line {}] Error at '{}': Invalid Token", line, msg)]
-   InvalidToken {
-      line: usize,
-      msg: String,
-   },
-
-   #[error("[line {}] Error at '{}': Invalid Identifier", line, msg)]
-   InvalidIdentifier {
-      line: usize,
-      msg: String
-   },
-
-   #[error("[line {}] Syntax Error: {}", line, msg)]
-   SyntaxError {
-      line: usize,
-      msg: String,
-   },
-
-   #[error("[line {}] Semantic Error: {}", line, msg)]
-   SemanticError {
-      line: usize,
-      msg: String,
+#[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))]
+pub struct Errors(pub Vec<Error>);
+
+// The diagnostics carried by `err`, or None if it does not come from the source
+pub fn source_errors(err: &anyhow::Error) -> Option<&[Error]> {
+   if let Some(Errors(errors)) = err.downcast_ref::<Errors>() {
+      Some(errors)
+   } else {
+      err.downcast_ref::<Error>().map(std::slice::from_ref)
    }
 }
 
-pub enum ErrorType {
-   InvalidToken,
-   InvalidIdentifier,
-   SyntaxError,
-   SemanticError,
+pub fn push(errors: &mut Vec<Error>, err: anyhow::Error) -> anyhow::Result<()> {
+   let err = match err.downcast::<Errors>() {
+      Ok(Errors(list)) => {
+         errors.extend(list);
+         return Ok(());
+      },
+      Err(err) => err,
+   };
+   errors.push(err.downcast::<Error>()?);
+   Ok(())
 }
 
-pub fn error(line: usize, msg: String, err_type: ErrorType) -> Error {
-   match err_type {
-      ErrorType::InvalidIdentifier => Error::InvalidIdentifier { line, msg },
-      ErrorType::InvalidToken => Error::InvalidToken { line, msg },
-      ErrorType::SyntaxError => Error::SyntaxError { line, msg },
-      ErrorType::SemanticError => Error::SemanticError { line, msg },
+pub fn finish(mut errors: Vec<Error>) -> anyhow::Result<()> {
+   if errors.is_empty() {
+      Ok(())
+   } else {
+      errors.sort_by_key(|e| (e.line, e.column));
+      Err(Errors(errors).into())
    }
 }
@@ -0,0 +1,331 @@
+use super::{source_errors, Code, Error, FixIt};
+use super::fixit::SourceMap;
+use super::warning::Warning;
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Copy, Clone, Default, ValueEnum)]
+pub enum DiagnosticsFormat {
+   #[default]
+   Text,
+   Json,
+   Sarif,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Severity {
+   Error,
+   Warning,
+}
+
+impl Severity {
+   fn name(&self) -> &'static str {
+      match self {
+         Severity::Error => "error",
+         Severity::Warning => "warning",
+      }
+   }
+}
+
+pub struct Diagnostic {
+   pub file: Option<PathBuf>,
+   pub code: &'static str,
+   pub severity: Severity,
+   pub line: Option<usize>,
+   pub column: Option<usize>,
+   pub msg: String,
+   pub notes: Vec<String>,
+   pub fixits: Vec<FixIt>,
+   text: String,
+}
+
+impl fmt::Display for Diagnostic {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", self.text)
+   }
+}
+
+impl From<&Error> for Diagnostic {
+   fn from(e: &Error) -> Self {
+      Diagnostic {
+         file: e.file.as_ref().map(PathBuf::from),
+         code: e.code.id(),
+         severity: Severity::Error,
+         line: Some(e.line),
+         column: e.column,
+         msg: e.msg.clone(),
+         notes: e.notes.clone(),
+         fixits: e.fixits.clone(),
+         text: e.to_string(),
+      }
+   }
+}
+
+impl From<&Warning> for Diagnostic {
+   fn from(w: &Warning) -> Self {
+      Diagnostic {
+         file: w.file.as_ref().map(PathBuf::from),
+         code: w.kind.code().id(),
+         severity: Severity::Warning,
+         line: Some(w.line),
+         column: w.column,
+         msg: w.msg.clone(),
+         notes: w.notes.clone(),
+         fixits: Vec::new(),
+         text: w.to_string(),
+      }
+   }
+}
+
+// Flattens the warnings and the errors carried by `result` for `file` into one
+// list. Errors that do not come from the source (e.g. a failing assembler) have
+// no line.
+pub fn collect<T>(file: Option<&Path>, warnings: &[Warning], result: &anyhow::Result<T>) -> Vec<Diagnostic> {
+   let mut diagnostics: Vec<Diagnostic> = warnings.iter().map(Diagnostic::from).collect();
+   if let Err(err) = result {
+      if let Some(errors) = source_errors(err) {
+         diagnostics.extend(errors.iter().map(Diagnostic::from));
+      } else {
+         diagnostics.push(Diagnostic {
+            file: None,
+            code: "driver-error",
+            severity: Severity::Error,
+            line: None,
+            column: None,
+            msg: format!("{:#}", err),
+            notes: Vec::new(),
+            fixits: Vec::new(),
+            text: format!("Error: {:#}", err),
+         });
+      }
+   }
+   if let Some(file) = file {
+      name_file(&mut diagnostics, file);
+   }
+   diagnostics
+}
+
+// Names `file` as the one the diagnostics are in, except for those in included
+// headers, which already name the header
+pub fn name_file(diagnostics: &mut [Diagnostic], file: &Path) {
+   for diagnostic in diagnostics {
+      if diagnostic.file.is_none() {
+         diagnostic.file = Some(file.to_path_buf());
+      }
+   }
+}
+
+// Maps the columns of the diagnostics, which point into `preprocessed`, back
+// onto the files they are in: `source` for those that don't name a file yet,
+// and the headers, read from disk, for the rest. Columns and fix-its that can't
+// be mapped, as on a line a macro was expanded in, are dropped.
+pub fn map_columns(diagnostics: &mut [Diagnostic], source: &str, preprocessed: &str) {
+   let headers: HashMap<PathBuf, String> = diagnostics.iter()
+      .filter_map(|d| d.file.clone())
+      .filter_map(|file| Some((file.clone(), fs::read_to_string(&file).ok()?)))
+      .collect();
+   let mut maps: HashMap<Option<&Path>, SourceMap> = HashMap::new();
+   maps.insert(None, SourceMap::new(source, preprocessed, None));
+   for (file, text) in &headers {
+      maps.insert(Some(file.as_path()), SourceMap::new(text, preprocessed, Some(&file.to_string_lossy())));
+   }
+
+   for diagnostic in diagnostics {
+      let map = maps.get(&diagnostic.file.as_deref());
+      if let Some(line) = diagnostic.line && let Some(column) = diagnostic.column {
+         diagnostic.column = map.and_then(|map| map.column(line, column));
+      }
+      diagnostic.fixits.retain_mut(|fixit| match map.and_then(|map| map.span(fixit.span)) {
+         Some(span) => {
+            fixit.span = span;
+            true
+         },
+         None => false,
+      });
+   }
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+   diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+pub fn emit(format: DiagnosticsFormat, diagnostics: &[Diagnostic]) {
+   match format {
+      DiagnosticsFormat::Text => emit_text(diagnostics),
+      DiagnosticsFormat::Json => eprintln!("{}", to_json(diagnostics)),
+      DiagnosticsFormat::Sarif => eprintln!("{}", to_sarif(diagnostics)),
+   }
+}
+
+fn emit_text(diagnostics: &[Diagnostic]) {
+   for diagnostic in diagnostics {
+      match &diagnostic.file {
+         Some(file) => eprintln!("{}: {}", file.display(), diagnostic),
+         None => eprintln!("{}", diagnostic),
+      }
+   }
+
+   let mut summary = Vec::new();
+   for severity in [Severity::Warning, Severity::Error] {
+      let count = diagnostics.iter().filter(|d| d.severity == severity).count();
+      if count > 0 {
+         summary.push(format!("{} {}{}", count, severity.name(), if count == 1 { "" } else { "s" }));
+      }
+   }
+   if !summary.is_empty() {
+      eprintln!("{} generated.", summary.join(" and "));
+   }
+}
+
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+   let diagnostics: Vec<Value> = diagnostics.iter().map(|d| {
+      json!({
+         "code": d.code,
+         "severity": d.severity.name(),
+         "file": d.file.as_ref().map(|file| file.to_string_lossy()),
+         "line": d.line,
+         "column": d.column,
+         "message": d.msg,
+         "notes": d.notes,
+         "fixits": d.fixits.iter().map(|fixit| json!({
+            "line": fixit.span.line,
+            "column": fixit.span.column,
+            "length": fixit.span.len,
+            "replacement": fixit.replacement,
+         })).collect::<Vec<_>>(),
+      })
+   }).collect();
+   serde_json::to_string_pretty(&diagnostics).unwrap()
+}
+
+pub fn to_sarif(diagnostics: &[Diagnostic]) -> String {
+   let mut rules: Vec<&str> = diagnostics.iter().map(|d| d.code).collect();
+   rules.sort();
+   rules.dedup();
+
+   let results: Vec<Value> = diagnostics.iter().map(|d| {
+      let mut region = json!({});
+      if let Some(line) = d.line {
+         region["startLine"] = json!(line);
+      }
+      if let Some(column) = d.column {
+         region["startColumn"] = json!(column);
+      }
+      let notes: Vec<Value> = d.notes.iter().enumerate().map(|(i, note)| {
+         json!({ "id": i, "message": { "text": note } })
+      }).collect();
+      // Driver errors are not tied to any input file
+      let Some(file) = &d.file else {
+         return json!({
+            "ruleId": d.code,
+            "level": d.severity.name(),
+            "message": { "text": d.msg },
+         });
+      };
+      json!({
+         "ruleId": d.code,
+         "level": d.severity.name(),
+         "message": { "text": d.msg },
+         "locations": [{
+            "physicalLocation": {
+               "artifactLocation": { "uri": file.to_string_lossy() },
+               "region": region,
+            }
+         }],
+         "relatedLocations": notes,
+         "fixes": d.fixits.iter().map(|fixit| sarif_fix(file, fixit)).collect::<Vec<_>>(),
+      })
+   }).collect();
+
+   let sarif = json!({
+      "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+      "version": "2.1.0",
+      "runs": [{
+         "tool": {
+            "driver": {
+               "name": env!("CARGO_PKG_NAME"),
+               "version": env!("CARGO_PKG_VERSION"),
+               "rules": rules.iter().map(|id| rule(id)).collect::<Vec<_>>(),
+            }
+         },
+         "results": results,
+      }]
+   });
+   serde_json::to_string_pretty(&sarif).unwrap()
+}
+
+fn rule(id: &str) -> Value {
+   match Code::from_id(id) {
+      Some(code) => json!({
+         "id": id,
+         "shortDescription": { "text": code.summary() },
+         "fullDescription": { "text": code.explanation() },
+      }),
+      None => json!({ "id": id }),
+   }
+}
+
+fn sarif_fix(file: &Path, fixit: &FixIt) -> Value {
+   json!({
+      "description": { "text": fixit.to_string() },
+      "artifactChanges": [{
+         "artifactLocation": { "uri": file.to_string_lossy() },
+         "replacements": [{
+            "deletedRegion": {
+               "startLine": fixit.span.line,
+               "startColumn": fixit.span.column,
+               "endColumn": fixit.span.column + fixit.span.len,
+            },
+            "insertedContent": { "text": fixit.replacement },
+         }],
+      }],
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::error::{self, Span};
+
+   fn diagnostic(line: usize, column: usize) -> Diagnostic {
+      let e = error::error(line, "Expected ';'".to_string(), Code::ExpectedToken)
+         .with_column(column)
+         .with_fixit(FixIt::insert(Span::new(line, column, 0), ";"));
+      Diagnostic::from(&e)
+   }
+
+   #[test]
+   fn maps_columns_onto_the_source_as_written() {
+      let source = "int main(void) {\n\tint    y = count\n  return y;\n}";
+      let preprocessed = "# 1 \"main.c\"\nint main(void) {\n int y = count\n  return y;\n}";
+      let mut diagnostics = vec![diagnostic(2, 15)];
+      map_columns(&mut diagnostics, source, preprocessed);
+      name_file(&mut diagnostics, Path::new("main.c"));
+
+      let json: Value = serde_json::from_str(&to_json(&diagnostics)).unwrap();
+      assert_eq!(json[0]["column"], 18);
+      assert_eq!(json[0]["fixits"][0]["column"], 18);
+
+      let sarif: Value = serde_json::from_str(&to_sarif(&diagnostics)).unwrap();
+      let result = &sarif["runs"][0]["results"][0];
+      assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startColumn"], 18);
+      assert_eq!(result["fixes"][0]["artifactChanges"][0]["replacements"][0]["deletedRegion"]["startColumn"], 18);
+   }
+
+   #[test]
+   fn drops_columns_on_rewritten_lines() {
+      let source = "#define N 10\nint x = N\n";
+      let preprocessed = "# 1 \"main.c\"\n\nint x = 10\n";
+      let mut diagnostics = vec![diagnostic(2, 11)];
+      map_columns(&mut diagnostics, source, preprocessed);
+
+      let json: Value = serde_json::from_str(&to_json(&diagnostics)).unwrap();
+      assert_eq!(json[0]["column"], Value::Null);
+      assert_eq!(json[0]["fixits"], json!([]));
+   }
+}
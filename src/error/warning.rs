@@ -0,0 +1,171 @@
+use super::Code;
+
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum WarningKind {
+   UnusedVariable,
+   UnusedParameter,
+   UnusedLabel,
+   ReturnType,
+   ImplicitFallthrough,
+}
+
+impl WarningKind {
+   pub const ALL: &[Self] = &[
+      WarningKind::UnusedVariable,
+      WarningKind::UnusedParameter,
+      WarningKind::UnusedLabel,
+      WarningKind::ReturnType,
+      WarningKind::ImplicitFallthrough,
+   ];
+
+   // Warnings enabled by -Wall
+   const WALL: &[Self] = &[
+      WarningKind::UnusedVariable,
+      WarningKind::UnusedLabel,
+      WarningKind::ReturnType,
+   ];
+
+   // Warnings enabled by -Wextra
+   const WEXTRA: &[Self] = &[
+      WarningKind::UnusedParameter,
+      WarningKind::ImplicitFallthrough,
+   ];
+
+   // Warnings enabled by -Wunused
+   const WUNUSED: &[Self] = &[
+      WarningKind::UnusedVariable,
+      WarningKind::UnusedParameter,
+      WarningKind::UnusedLabel,
+   ];
+
+   pub fn name(&self) -> &'static str {
+      match self {
+         WarningKind::UnusedVariable => "unused-variable",
+         WarningKind::UnusedParameter => "unused-parameter",
+         WarningKind::UnusedLabel => "unused-label",
+         WarningKind::ReturnType => "return-type",
+         WarningKind::ImplicitFallthrough => "implicit-fallthrough",
+      }
+   }
+
+   pub fn code(&self) -> Code {
+      match self {
+         WarningKind::UnusedVariable => Code::UnusedVariable,
+         WarningKind::UnusedParameter => Code::UnusedParameter,
+         WarningKind::UnusedLabel => Code::UnusedLabel,
+         WarningKind::ReturnType => Code::MissingReturn,
+         WarningKind::ImplicitFallthrough => Code::ImplicitFallthrough,
+      }
+   }
+
+   pub fn from_code(code: Code) -> Option<Self> {
+      Self::ALL.iter().copied().find(|kind| kind.code() == code)
+   }
+
+   fn from_name(name: &str) -> Result<&'static [Self]> {
+      if name == "unused" {
+         return Ok(Self::WUNUSED);
+      }
+      match Self::ALL.iter().position(|kind| kind.name() == name) {
+         Some(i) => Ok(&Self::ALL[i..=i]),
+         None => bail!("Unknown warning option '-W{}'", name)
+      }
+   }
+}
+
+#[derive(Debug)]
+pub struct Warning {
+   pub kind: WarningKind,
+   pub line: usize,
+   pub column: Option<usize>,
+   pub msg: String,
+   pub notes: Vec<String>,
+   // The header the warning is in, or None for the file being compiled
+   pub file: Option<String>,
+}
+
+impl fmt::Display for Warning {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "[line {}] Warning [{}]: {} [-W{}]", self.line, self.kind.code(), self.msg, self.kind.name())?;
+      for note in &self.notes {
+         write!(f, "\n  note: {}", note)?;
+      }
+      Ok(())
+   }
+}
+
+pub fn warning(line: usize, msg: String, kind: WarningKind) -> Warning {
+   Warning { kind, line, column: None, msg, notes: Vec::new(), file: None }
+}
+
+#[derive(Clone, Default)]
+pub struct WarningOptions {
+   enabled: HashSet<WarningKind>,
+   errors: HashSet<WarningKind>,
+   all_errors: bool,
+}
+
+impl WarningOptions {
+   // Builds the options from the values of each -W flag, in command line order,
+   // so that later flags override earlier ones (e.g. `-Wall -Wno-unused-label`).
+   pub fn from_flags(flags: &[String]) -> Result<Self> {
+      let mut options = Self::default();
+      for flag in flags {
+         match flag.as_str() {
+            "all" => options.enabled.extend(WarningKind::WALL),
+            "extra" => options.enabled.extend(WarningKind::WEXTRA),
+            "error" => options.all_errors = true,
+            "no-error" => options.all_errors = false,
+            _ => {
+               if let Some(name) = flag.strip_prefix("error=") {
+                  let kinds = WarningKind::from_name(name)?;
+                  options.enabled.extend(kinds);
+                  options.errors.extend(kinds);
+               } else if let Some(name) = flag.strip_prefix("no-error=") {
+                  for kind in WarningKind::from_name(name)? {
+                     options.errors.remove(kind);
+                  }
+               } else if let Some(name) = flag.strip_prefix("no-") {
+                  for kind in WarningKind::from_name(name)? {
+                     options.enabled.remove(kind);
+                  }
+               } else {
+                  options.enabled.extend(WarningKind::from_name(flag)?);
+               }
+            }
+         }
+      }
+      Ok(options)
+   }
+
+   pub fn enabled(&self, kind: WarningKind) -> bool {
+      self.enabled.contains(&kind)
+   }
+
+   pub fn is_error(&self, kind: WarningKind) -> bool {
+      self.all_errors || self.errors.contains(&kind)
+   }
+}
+
+impl Warning {
+   pub fn with_file(mut self, file: Option<&str>) -> Self {
+      self.file = file.map(String::from);
+      self
+   }
+
+   pub fn into_error(self) -> super::Error {
+      super::Error {
+         code: self.kind.code(),
+         line: self.line,
+         column: self.column,
+         msg: self.msg,
+         notes: self.notes,
+         fixits: Vec::new(),
+         file: self.file,
+      }
+   }
+}
@@ -1,51 +1,89 @@
 use anyhow::{Result, ensure};
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::thread;
 
-pub fn preprocess(source: &Path, dest: &Path) -> Result<()> {
-   let output = Command::new("gcc")
-      .args([
-         "-E",
-         "-P",
-         source.to_string_lossy().as_ref(),
-         "-o",
-         dest.to_string_lossy().as_ref()
-      ])
-      .output()?;
+// Runs the preprocessor over `source`, which is piped to gcc rather than read
+// from disk. Quoted includes are searched for in `include_dir`, if given.
+pub fn preprocess(source: &str, include_dir: Option<&Path>) -> Result<String> {
+   let mut command = preprocessor(Path::new("-"), include_dir);
+   let mut child = command
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()?;
 
-   ensure!(
-      output.status.success(),
-      "Error code: {}\n\n{}",
-      output.status.code().unwrap_or(-1),
-      String::from_utf8_lossy(&output.stderr));
+   // Write from another thread so a large output can't fill the pipe and block us
+   let mut stdin = child.stdin.take().unwrap();
+   let source = source.to_string();
+   let writer = thread::spawn(move || stdin.write_all(source.as_bytes()));
+   let output = child.wait_with_output()?;
+   writer.join().unwrap()?;
+   preprocessed(output)
+}
 
-   Ok(())
+// Like `preprocess`, but gcc reads the source from `path` itself, so the line
+// markers, `__FILE__` and the preprocessor's own errors all name the file
+pub fn preprocess_file(path: &Path, include_dir: Option<&Path>) -> Result<String> {
+   let output = preprocessor(path, include_dir).output()?;
+   preprocessed(output)
 }
 
-pub fn assemble(source: &Path, additional_args: &mut Vec<String>) -> Result<()> {
-   let output_ext = if additional_args.contains(&"-c".to_string()) {
-      "o"
-   } else {
-      ""
-   };
+fn preprocessor(input: &Path, include_dir: Option<&Path>) -> Command {
+   let mut command = Command::new("gcc");
+   command.args(["-E", "-x", "c"]).arg(input);
+   if let Some(dir) = include_dir {
+      command.arg("-iquote").arg(dir);
+   }
+   command
+}
 
-   let output = source.with_extension(output_ext);
+fn preprocessed(output: Output) -> Result<String> {
+   check("Preprocessing", &output)?;
+   Ok(String::from_utf8(output.stdout)?)
+}
+
+pub fn assemble(source: &Path, dest: &Path, additional_args: &[String]) -> Result<()> {
    let mut args: Vec<String> = vec![
+         "-c".to_string(),
          source.to_string_lossy().into_owned(),
          "-o".to_string(),
-         output.to_string_lossy().into_owned()
+         dest.to_string_lossy().into_owned()
       ];
+   args.extend_from_slice(additional_args);
+   run("Assembling", args)
+}
+
+// Links the assembly, object and archive files in `inputs` into an executable
+pub fn link(inputs: &[PathBuf], dest: &Path, additional_args: &[String]) -> Result<()> {
+   let mut args: Vec<String> = inputs.iter().map(|input| input.to_string_lossy().into_owned()).collect();
+   args.push("-o".to_string());
+   args.push(dest.to_string_lossy().into_owned());
+   args.extend_from_slice(additional_args);
+   run("Linking", args)
+}
 
-   args.append(additional_args);
+fn run(step: &str, args: Vec<String>) -> Result<()> {
    let output = Command::new("gcc")
       .args(args)
       .output()?;
+   check(step, &output)
+}
 
+// Fails with gcc's exit status and what it printed, e.g. the linker's errors,
+// unless it succeeded
+fn check(step: &str, output: &Output) -> Result<()> {
+   // A gcc killed by a signal has no exit code
+   let status = match output.status.code() {
+      Some(code) => format!("exit status {}", code),
+      None => output.status.to_string(),
+   };
    ensure!(
       output.status.success(),
-      "Error code: {}\n\n{}",
-      output.status.code().unwrap_or(-1),
-      String::from_utf8_lossy(&output.stderr));
-
+      "{} failed with {}:\n{}",
+      step,
+      status,
+      String::from_utf8_lossy(&output.stderr).trim_end());
    Ok(())
-}
\ No newline at end of file
+}
@@ -1,8 +1,9 @@
 pub mod token;
 
-use crate::error;
+use crate::error::{self, Code};
 
 use anyhow::{bail, Result};
+use std::rc::Rc;
 use token::{Token, TokenType};
 
 struct Lexer<'a> {
@@ -11,6 +12,11 @@ struct Lexer<'a> {
    start: usize,
    current: usize,
    line: usize,
+   line_start: usize,
+   // The file named by the first line marker, and the header being lexed when
+   // the current line comes from somewhere else
+   main_file: Option<String>,
+   file: Option<Rc<str>>,
 }
 
 pub fn lex(source: &String, print_tokens: bool) -> Result<Vec<Option<Token>>> {
@@ -27,6 +33,9 @@ impl<'a> Lexer<'a> {
          start: 0,
          current: 0,
          line: 1,
+         line_start: 0,
+         main_file: None,
+         file: None,
       }
    }
 
@@ -36,7 +45,8 @@ impl<'a> Lexer<'a> {
          self.scan_token()?;
       }
 
-      let token = Token::new(TokenType::EOF, String::from(""), self.line);
+      self.start = self.current;
+      let token = Token::new(TokenType::EOF, String::from(""), self.line, self.column(), self.file.clone());
       self.tokens.push(Some(token));
 
       if print_tokens {
@@ -51,6 +61,7 @@ impl<'a> Lexer<'a> {
    fn scan_token(&mut self) -> Result<()> {
       let c = self.advance();
       match c {
+         '#' if self.start == self.line_start => self.line_marker(),
          '(' => self.add_token(TokenType::OpenParen),
          ')' => self.add_token(TokenType::CloseParen),
          '{' => self.add_token(TokenType::OpenBrace),
@@ -184,20 +195,43 @@ impl<'a> Lexer<'a> {
          '?' => self.add_token(TokenType::Question),
          ':' => self.add_token(TokenType::Colon),
          ',' => self.add_token(TokenType::Comma),
-         '\n'=> self.line += 1,
+         '\n'=> {
+            self.line += 1;
+            self.line_start = self.current;
+         },
          _ if c.is_whitespace() => (),
          _ if c.is_digit(10) => self.number()?,
          _ if is_alpha(c) => self.identifier()?,
-         _ => bail!(error::error(self.line, String::from(c), error::ErrorType::InvalidToken))
+         _ => bail!(error::error(self.line, format!("Invalid character '{}'", c), Code::InvalidCharacter).with_column(self.column()).with_file(self.file.as_deref()))
       };
 
       Ok(())
    }
 
+   // Skips a `# <line> "<file>" <flags>` marker left by the preprocessor, which
+   // gives the file and line number in the original source of the line that
+   // follows it
+   fn line_marker(&mut self) {
+      while !self.at_end() && self.peek() != '\n' {
+         self.advance();
+      }
+      let marker = &self.lexeme()[1..];
+      let line = marker.split_whitespace().next().and_then(|n| n.parse::<usize>().ok());
+      if let Some(file) = marker_file(marker) {
+         let main_file = self.main_file.get_or_insert_with(|| file.clone());
+         self.file = if file == *main_file { None } else { Some(file.into()) };
+      }
+      if !self.at_end() {
+         self.advance();
+      }
+      self.line = line.unwrap_or(self.line + 1);
+      self.line_start = self.current;
+   }
+
    fn add_token(&mut self, token_type: TokenType) {
       let line_number = self.line;
       let lexeme = self.lexeme();
-      let token = Token::new(token_type, lexeme.to_string(), line_number);
+      let token = Token::new(token_type, lexeme.to_string(), line_number, self.column(), self.file.clone());
       self.tokens.push(Some(token));
    }
 
@@ -215,6 +249,11 @@ impl<'a> Lexer<'a> {
       self.current >= self.source.len()
    }
 
+   // 1-based column of the start of the current lexeme
+   fn column(&self) -> usize {
+      self.start - self.line_start + 1
+   }
+
    fn lexeme(&self) -> &str {
       &self.source.as_str()[self.start..self.current]
    }
@@ -228,7 +267,7 @@ impl<'a> Lexer<'a> {
          while !self.at_end() && (is_alpha(self.peek()) || is_digit(self.peek())) {
             self.advance();
          }
-         bail!(error::error(self.line, self.lexeme().to_string(), error::ErrorType::InvalidIdentifier))
+         bail!(error::error(self.line, format!("Invalid numeric literal '{}'", self.lexeme()), Code::InvalidNumericLiteral).with_column(self.column()).with_file(self.file.as_deref()))
       }
 
       let token_string = self.lexeme();
@@ -276,10 +315,40 @@ fn to_keyword(identifier: &str) -> Option<TokenType> {
       "continue" => Some(TokenType::Continue),
       "static" => Some(TokenType::Static),
       "extern" => Some(TokenType::Extern),
+      "inline" => Some(TokenType::Inline),
       "goto" => Some(TokenType::Goto),
       "switch" => Some(TokenType::Switch),
       "case" => Some(TokenType::Case),
       "default" => Some(TokenType::Default),
       _ => None
    }
-}
\ No newline at end of file
+}
+
+// The quoted file name in a line marker, in which the preprocessor escapes
+// backslashes and quotes
+pub fn marker_file(marker: &str) -> Option<String> {
+   let mut chars = marker.split_once('"')?.1.chars();
+   let mut file = String::new();
+   loop {
+      match chars.next()? {
+         '"' => return Some(file),
+         '\\' => file.push(chars.next()?),
+         c => file.push(c),
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn tokens_remember_the_header_they_come_from() {
+      let source = "# 0 \"main.c\"\n# 1 \"dir/a \\\"b\\\".h\" 1\nint\n# 3 \"main.c\" 2\nreturn".to_string();
+      let tokens: Vec<Token> = lex(&source, false).unwrap().into_iter().flatten().collect();
+      assert_eq!(tokens[0].file.as_deref(), Some("dir/a \"b\".h"));
+      assert_eq!(tokens[0].line_number, 1);
+      assert_eq!(tokens[1].file, None);
+      assert_eq!(tokens[1].line_number, 3);
+   }
+}
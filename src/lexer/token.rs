@@ -1,4 +1,7 @@
+use crate::error::Span;
+
 use std::fmt;
+use std::rc::Rc;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
@@ -58,6 +61,7 @@ pub enum TokenType {
    Continue,
    Static,
    Extern,
+   Inline,
    Goto,
    Switch,
    Case,
@@ -73,17 +77,26 @@ pub enum TokenType {
 pub struct Token {
    pub token_type: TokenType,
    pub lexeme: String,
-   pub line_number: usize
+   pub line_number: usize,
+   pub column: usize,
+   // The header the token was included from, or None for the main file
+   pub file: Option<Rc<str>>,
 }
 
 impl Token {
-   pub fn new(token_type: TokenType, lexeme: String, line_number: usize) -> Self {
+   pub fn new(token_type: TokenType, lexeme: String, line_number: usize, column: usize, file: Option<Rc<str>>) -> Self {
       Self {
          token_type,
          lexeme,
          line_number,
+         column,
+         file,
       }
    }
+
+   pub fn span(&self) -> Span {
+      Span::new(self.line_number, self.column, self.lexeme.len())
+   }
 }
 
 impl fmt::Display for TokenType {
@@ -144,6 +157,7 @@ impl fmt::Display for TokenType {
          TokenType::Comma => write!(f, ","),
          TokenType::Static => write!(f, "STATIC"),
          TokenType::Extern => write!(f, "EXTERN"),
+         TokenType::Inline => write!(f, "INLINE"),
          TokenType::Goto => write!(f, "GOTO"),
          TokenType::Switch => write!(f, "SWITCH"),
          TokenType::Case => write!(f, "CASE"),
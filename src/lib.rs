@@ -0,0 +1,18 @@
+pub mod codegen;
+pub mod compiler;
+pub mod emitter;
+pub mod error;
+pub mod gcc;
+pub mod lexer;
+mod name_generator;
+pub mod optimizer;
+pub mod parser;
+pub mod stats;
+pub mod tacky;
+pub mod validator;
+
+pub use compiler::Compiler;
+
+#[macro_use]
+extern crate num_derive;
+extern crate num_traits;
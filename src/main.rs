@@ -1,35 +1,120 @@
-mod codegen;
-mod compiler;
-mod emitter;
-mod error;
-mod gcc;
-mod lexer;
-mod name_generator;
-mod parser;
-mod tacky;
-mod validator;
-
-use anyhow::Result;
-use compiler::Compiler;
-use clap::Parser;
-use std::path::PathBuf;
-
-#[macro_use]
-extern crate num_derive;
-extern crate num_traits;
+mod temps;
+
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use crust::Compiler;
+use crust::codegen::target::{RelocationModel, Syntax, Target};
+use crust::optimizer::Optimizations;
+use crust::error::{self, Code};
+use crust::error::render::{self, Diagnostic, DiagnosticsFormat};
+use crust::error::warning::WarningOptions;
+use crust::gcc;
+use std::fs;
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use temps::Temps;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// Path to C source file to compile. Running without any additional arguments,
-    /// or with only print_* arguments, will run all stages of the compiler and
-    /// generate an executable in the same directory as the source file.
-    source: PathBuf,
+    /// C source files to compile, along with any assembly (.s), object (.o) or
+    /// archive (.a) files to link them with. Running without any additional
+    /// arguments, or with only print_* arguments, will run all stages of the
+    /// compiler and generate an executable in the same directory as the first input.
+    #[arg(required_unless_present = "explain")]
+    sources: Vec<PathBuf>,
+
+    /// Print a detailed explanation of a diagnostic code (e.g. E0102) and exit
+    #[arg(long, value_name = "CODE")]
+    explain: Option<String>,
 
     /// Compile as library (generate .o file instead of executable)
     #[arg(short)]
     c: bool,
 
+    /// Stop after generating assembly (.s file)
+    #[arg(short = 'S')]
+    assembly: bool,
+
+    /// Only run the preprocessor, printing the result to stdout or the -o file
+    #[arg(short = 'E')]
+    preprocess: bool,
+
+    /// Write the executable, object, assembly or preprocessed output to <FILE>, or
+    /// to stdout if <FILE> is '-'
+    #[arg(short, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Target to generate code for: x86_64-linux-gnu or x86_64-apple-darwin.
+    /// Defaults to the host
+    #[arg(long, value_name = "TRIPLE", value_parser = Target::from_triple)]
+    target: Option<Target>,
+
+    /// Optimization level: -O0 (the default) runs no optimizations, -O1 or -O
+    /// folds constants, propagates copies and removes unreachable code and dead
+    /// stores, and -O2 also inlines functions, propagates constants through branches and
+    /// eliminates common subexpressions
+    #[arg(short = 'O', value_name = "LEVEL", value_parser = clap::value_parser!(u8).range(0..=2), default_value_t = 0)]
+    opt_level: u8,
+
+    /// Fold constant expressions, whatever the -O level
+    #[arg(long)]
+    fold_constants: bool,
+
+    /// Replace variables with the values copied into them, whatever the -O level
+    #[arg(long)]
+    propagate_copies: bool,
+
+    /// Remove code that can never run, whatever the -O level
+    #[arg(long)]
+    eliminate_unreachable_code: bool,
+
+    /// Remove writes to variables that are never read, whatever the -O level
+    #[arg(long)]
+    eliminate_dead_stores: bool,
+
+    /// Find constants through branches that can't be taken and remove those
+    /// branches, whatever the -O level
+    #[arg(long)]
+    propagate_constants: bool,
+
+    /// Reuse the result of a computation that was already done on every path,
+    /// whatever the -O level
+    #[arg(long)]
+    eliminate_common_subexpressions: bool,
+
+    /// Replace calls to small functions and to functions declared 'inline' with
+    /// the body of the function, whatever the -O level
+    #[arg(long)]
+    inline_functions: bool,
+
+    /// Code generation options: -fPIC for shared libraries, -fPIE for position
+    /// independent executables, -fno-pic, or -fintegrated-as to write ELF object
+    /// files directly rather than having gcc assemble the generated assembly
+    #[arg(short = 'f', value_name = "OPTION")]
+    codegen_options: Vec<String>,
+
+    /// Machine options: -masm=att or -masm=intel to choose the syntax of the
+    /// generated assembly
+    #[arg(short = 'm', value_name = "OPTION")]
+    machine_options: Vec<String>,
+
+    /// Link a shared library instead of an executable
+    #[arg(long)]
+    shared: bool,
+
+    /// Treat the inputs that follow as the given language, or go back to telling
+    /// it from their extensions with 'none'. Required to read C source from stdin
+    /// with '-'
+    #[arg(short = 'x', value_enum, value_name = "LANGUAGE")]
+    language: Vec<Language>,
+
+    // The language set by the last -x before each of the sources
+    #[arg(skip)]
+    source_languages: Vec<Language>,
+
     /// Run only the lexer
     #[arg(long)]
     lex: bool,
@@ -66,41 +151,354 @@ struct Cli {
     #[arg(long)]
     print_assembly: bool,
 
+    /// Configure warnings: -Wall, -Wextra, -W<name>, -Wno-<name>, -Werror, -Werror=<name>
+    #[arg(short = 'W', value_name = "WARNING")]
+    warnings: Vec<String>,
+
+    /// Apply the suggested fix-its to the source file in place
+    #[arg(long)]
+    fixit: bool,
+
+    /// Format used to report errors and warnings
+    #[arg(long, value_enum, default_value_t = DiagnosticsFormat::Text)]
+    diagnostics_format: DiagnosticsFormat,
+
+    /// Keep the intermediate .i and .s files in the current directory
+    #[arg(long = "save-temps")]
+    save_temps: bool,
+
+    /// Report the time spent in each pass of the compiler
+    #[arg(long)]
+    time_passes: bool,
+
+    /// Report the number of tokens, AST nodes and instructions, and the stack
+    /// frame size of each function
+    #[arg(long)]
+    stats: bool,
+
     /// Additional arguments to pass to the assembler
     #[arg(long, allow_hyphen_values = true, num_args = 0..)]
     args: Vec<String>,
 }
 
-fn main() -> Result<()> {
-    let mut args = Cli::parse();
-    let mut compiler = Compiler::new(args.source)?;
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Language {
+    C,
+    None,
+}
 
-    if args.lex {
-        compiler.lex(args.print_tokens)?;
+fn main() -> ExitCode {
+    // Accept gcc's single dash spelling of -save-temps and -shared, and -O on its
+    // own for -O1
+    let args = parse_args(std::env::args_os().map(|arg| {
+        if arg == "-save-temps" || arg == "-shared" { format!("-{}", arg.display()).into() } else if arg == "-O" { "-O1".into() } else { arg }
+    }));
+    if let Some(id) = &args.explain {
+        return explain(id);
+    }
+
+    let warning_options = match WarningOptions::from_flags(&args.warnings) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    let result = run(&args, &warning_options, &mut diagnostics);
+    diagnostics.extend(render::collect(None, &[], &result));
+    render::emit(args.diagnostics_format, &diagnostics);
+
+    if render::has_errors(&diagnostics) { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
+
+// Parses the command line like gcc, where -x applies to the inputs after it
+// rather than to all of them
+fn parse_args(args: impl IntoIterator<Item = OsString>) -> Cli {
+    let matches = Cli::command().get_matches_from(args);
+    let mut args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let languages: Vec<(usize, Language)> = matches.indices_of("language").into_iter().flatten().zip(args.language.iter().copied()).collect();
+    args.source_languages = matches.indices_of("sources").into_iter().flatten().map(|index| {
+        languages.iter().rev().find(|(position, _)| *position < index).map_or(Language::None, |(_, language)| *language)
+    }).collect();
+    args
+}
+
+fn explain(id: &str) -> ExitCode {
+    match Code::from_id(id) {
+        Some(code) => {
+            println!("{}: {}\n\n{}", code, code.summary(), code.explanation());
+            ExitCode::SUCCESS
+        },
+        None => {
+            eprintln!("Error: '{}' is not a valid diagnostic code", id);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Compiles every C source independently, reporting the diagnostics of each one,
+// then assembles or links the results together with the other inputs
+fn run(args: &Cli, warning_options: &WarningOptions, diagnostics: &mut Vec<Diagnostic>) -> Result<()> {
+    for (index, input) in args.sources.iter().enumerate() {
+        if is_stdio(input) && args.source_languages[index] != Language::C {
+            bail!("'-x c' is required when reading from stdin");
+        }
+        if !is_c_source(args, index) && !matches!(extension(input), Some("s" | "S" | "o" | "a")) {
+            bail!("Unrecognized input file '{}'", input.display());
+        }
+    }
+    if args.output.is_some() && (args.c || args.assembly || args.preprocess) && args.sources.len() > 1 {
+        bail!("Cannot specify '-o' with '-c', '-S' or '-E' with multiple files");
+    }
+    if let Some(output) = &args.output && let Some(input) = args.sources.iter().find(|input| temps::same_file(input, output)) {
+        bail!("Input file '{}' is the same as the output file", input.display());
+    }
+
+    let (relocation_model, integrated_as) = codegen_options(&args.codegen_options)?;
+    let target = args.target.unwrap_or_else(Target::host);
+    let syntax = machine_options(&args.machine_options)?;
+
+    let mut temps = Temps::new(args.save_temps);
+    // Each input paired with the file passed on to the assembler or linker for it
+    let mut link_inputs = Vec::new();
+    for (index, input) in args.sources.iter().enumerate() {
+        if !is_c_source(args, index) {
+            link_inputs.push((input.clone(), input.clone()));
+            continue;
+        }
+
+        let (name, source) = if is_stdio(input) {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            (Path::new("<stdin>"), source)
+        } else {
+            let source = fs::read_to_string(input).with_context(|| format!("Failed to read {}", input.display()))?;
+            (input.as_path(), source)
+        };
+        let mut compiler = Compiler::new(source)
+            .warning_options(warning_options.clone())
+            .target(target)
+            .relocation_model(relocation_model)
+            .syntax(syntax)
+            .optimizations(optimizations(&args))
+            .print_tokens(args.print_tokens)
+            .print_ast(args.print_ast)
+            .print_tacky(args.print_tacky)
+            .print_assembly(args.print_assembly);
+        if !is_stdio(input) {
+            compiler = compiler.file(input);
+        }
+
+        let object = integrated_as && !args.assembly;
+        let output = match &args.output {
+            Some(output) if args.assembly || (args.c && object) => output.clone(),
+            _ if args.assembly && is_stdio(input) => PathBuf::from("-"),
+            _ if args.assembly => output_file(input, "s"),
+            _ if args.c && object && is_stdio(input) => bail!("'-o' is required with '-c' when reading from stdin"),
+            _ if args.c && object => output_file(input, "o"),
+            _ => temps.file(index, input, if object { "o" } else { "s" })?,
+        };
+        let result = compile(&mut compiler, args, &output, object);
+        let mut found = compiler.diagnostics(&result);
+        render::name_file(&mut found, name);
+        diagnostics.extend(found);
+        if args.time_passes {
+            eprint!("Pass timings for {}:\n{}", name.display(), compiler.stats().timings());
+        }
+        if args.stats {
+            eprint!("Statistics for {}:\n{}", name.display(), compiler.stats().counts());
+        }
+        if args.save_temps && !compiler.preprocessed().is_empty() {
+            fs::write(temps.file(index, input, "i")?, compiler.preprocessed())?;
+        }
+        if args.fixit && !is_stdio(input) && let Err(err) = &result && let Some(errors) = error::source_errors(err) {
+            let applied = error::fixit::apply_to_file(input, compiler.preprocessed(), errors)?;
+            if applied > 0 {
+                eprintln!("{} fix-it{} applied to {}", applied, if applied == 1 { "" } else { "s" }, input.display());
+            }
+        }
+        if let Ok(true) = result {
+            link_inputs.push((input.clone(), output));
+        }
+    }
+    temps.save(&args.sources)?;
+
+    let stop_early = args.lex || args.parse || args.validate || args.tacky || args.codegen || args.preprocess || args.assembly;
+    if stop_early || render::has_errors(diagnostics) {
         return Ok(());
     }
 
+    if args.c {
+        for (input, file) in &link_inputs {
+            if matches!(extension(file), Some("s" | "S")) {
+                let dest = match &args.output {
+                    Some(output) => output.clone(),
+                    None if is_stdio(input) => bail!("'-o' is required with '-c' when reading from stdin"),
+                    None => output_file(input, "o"),
+                };
+                gcc::assemble(file, &dest, &args.args)?;
+            }
+        }
+    } else {
+        let files: Vec<PathBuf> = link_inputs.into_iter().map(|(_, file)| file).collect();
+        let dest = match &args.output {
+            Some(output) => output.clone(),
+            None if is_stdio(&args.sources[0]) => PathBuf::from("a.out"),
+            None if args.shared => args.sources[0].with_extension("so"),
+            None => args.sources[0].with_extension(""),
+        };
+        let mut link_args = args.args.clone();
+        if args.shared {
+            link_args.insert(0, "-shared".to_string());
+        }
+        gcc::link(&files, &dest, &link_args)?;
+    }
+
+    Ok(())
+}
+
+// Runs the stages of the compiler requested on the command line, returning
+// whether all of them were run and the assembly, or the object if `object` is
+// set, was written to `output`
+fn compile(compiler: &mut Compiler, args: &Cli, output: &Path, object: bool) -> Result<bool> {
+    if args.preprocess {
+        let source = compiler.preprocess()?;
+        let mut output = create_output(args.output.as_deref().unwrap_or(Path::new("-")))?;
+        output.write_all(source.as_bytes())?;
+        output.flush()?;
+        return Ok(false);
+    }
+
+    if args.lex {
+        compiler.lex()?;
+        return Ok(false);
+    }
+
     if args.parse {
-        compiler.parse(args.print_tokens, args.print_ast)?;
-        return Ok(());
+        compiler.parse()?;
+        return Ok(false);
     }
 
     if args.validate {
-        compiler.validate(args.print_tokens, args.print_ast)?;
-        return Ok(());
+        compiler.validate()?;
+        return Ok(false);
     }
 
     if args.tacky {
-        compiler.tacky(args.print_tokens, args.print_ast, args.print_tacky)?;
-        return Ok(());
+        compiler.tacky()?;
+        return Ok(false);
     }
 
     if args.codegen {
-        compiler.codegen(args.print_tokens, args.print_ast, args.print_tacky, args.print_assembly)?;
-        return Ok(());
+        compiler.codegen()?;
+        return Ok(false);
     }
 
-    compiler.compile(args.print_tokens, args.print_ast, args.print_tacky, args.print_assembly, args.c, &mut args.args)?;
+    let mut output = create_output(output)?;
+    if object {
+        compiler.compile_object(&mut output)?;
+    } else {
+        compiler.compile(&mut output)?;
+    }
+    output.flush()?;
+    Ok(true)
+}
 
-    Ok(())
+// Reads the -f options, returning the relocation model and whether objects are
+// written by the integrated assembler instead of gcc
+fn codegen_options(options: &[String]) -> Result<(RelocationModel, bool)> {
+    let mut relocation_model = RelocationModel::default();
+    let mut integrated_as = false;
+    for option in options {
+        match option.as_str() {
+            "integrated-as" => integrated_as = true,
+            "no-integrated-as" => integrated_as = false,
+            _ => relocation_model = RelocationModel::from_flag(option)?,
+        }
+    }
+    Ok((relocation_model, integrated_as))
+}
+
+// The passes enabled by the -O level, along with any enabled individually
+fn optimizations(args: &Cli) -> Optimizations {
+    let mut optimizations = Optimizations::level(args.opt_level);
+    optimizations.fold_constants |= args.fold_constants;
+    optimizations.propagate_copies |= args.propagate_copies;
+    optimizations.eliminate_unreachable_code |= args.eliminate_unreachable_code;
+    optimizations.eliminate_dead_stores |= args.eliminate_dead_stores;
+    optimizations.propagate_constants |= args.propagate_constants;
+    optimizations.eliminate_common_subexpressions |= args.eliminate_common_subexpressions;
+    optimizations.inline_functions |= args.inline_functions;
+    optimizations
+}
+
+// Reads the -m options, returning the assembly syntax
+fn machine_options(options: &[String]) -> Result<Syntax> {
+    let mut syntax = Syntax::default();
+    for option in options {
+        match option.strip_prefix("asm=") {
+            Some(name) => syntax = Syntax::from_name(name)?,
+            None => bail!("Unknown option '-m{}'", option),
+        }
+    }
+    Ok(syntax)
+}
+
+// Opens `path` for writing, where '-' means stdout
+fn create_output(path: &Path) -> Result<Box<dyn Write>> {
+    if is_stdio(path) {
+        Ok(Box::new(io::stdout()))
+    } else {
+        let file = fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Box::new(io::BufWriter::new(file)))
+    }
+}
+
+// Where -c and -S write the output for `input` without -o: like gcc, a file named
+// after it in the current directory rather than next to it
+fn output_file(input: &Path, extension: &str) -> PathBuf {
+    Path::new(input.file_name().unwrap_or(input.as_os_str())).with_extension(extension)
+}
+
+// '-' stands for stdin as an input and for stdout as an output
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+// Whether the source at `index` is C, by the -x before it or else its extension
+fn is_c_source(args: &Cli, index: usize) -> bool {
+    match args.source_languages[index] {
+        Language::C => true,
+        Language::None => extension(&args.sources[index]) == Some("c"),
+    }
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|ext| ext.to_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c_sources(command_line: &str) -> Vec<bool> {
+        let args = parse_args(command_line.split_whitespace().map(OsString::from));
+        (0..args.sources.len()).map(|index| is_c_source(&args, index)).collect()
+    }
+
+    #[test]
+    fn language_applies_to_the_inputs_after_it() {
+        assert_eq!(c_sources("crust a.c -x c b.txt - -x none c.o d.c"), vec![true, true, true, false, true]);
+        assert_eq!(c_sources("crust lib.o -x c main"), vec![false, true]);
+        assert_eq!(c_sources("crust main.i b.o"), vec![false, false]);
+    }
+
+    #[test]
+    fn outputs_go_in_the_current_directory() {
+        assert_eq!(output_file(Path::new("src/foo.c"), "o"), Path::new("foo.o"));
+        assert_eq!(output_file(Path::new("../lib/bar.s"), "o"), Path::new("bar.o"));
+        assert_eq!(output_file(Path::new("main.c"), "s"), Path::new("main.s"));
+    }
 }
@@ -0,0 +1,246 @@
+// Control flow graph of a TACKY function. The instructions are partitioned into
+// basic blocks: straight-line runs that are only entered at the top, through a
+// label or by falling through, and only left at the bottom, through a jump, a
+// return or by falling through.
+
+use crate::tacky::tacky::Instr;
+
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Node {
+   Entry,
+   Block(usize),
+   Exit,
+}
+
+pub struct BasicBlock {
+   pub instrs: Vec<Instr>,
+   pub predecessors: Vec<Node>,
+   pub successors: Vec<Node>,
+}
+
+impl BasicBlock {
+   pub fn new(instrs: Vec<Instr>) -> Self {
+      BasicBlock { instrs, predecessors: Vec::new(), successors: Vec::new() }
+   }
+}
+
+pub struct Cfg {
+   // In the order they appear in the function, so falling through from block i
+   // leads to block i + 1
+   pub blocks: Vec<BasicBlock>,
+   pub entry_successors: Vec<Node>,
+   pub exit_predecessors: Vec<Node>,
+}
+
+impl Cfg {
+   pub fn new(instrs: Vec<Instr>) -> Self {
+      let mut blocks = Vec::new();
+      let mut current = Vec::new();
+      for instr in instrs {
+         match instr {
+            Instr::Label(_) => {
+               if !current.is_empty() {
+                  blocks.push(std::mem::take(&mut current));
+               }
+               current.push(instr);
+            },
+            Instr::Jump(_) | Instr::JumpIfZero(..) | Instr::JumpIfNotZero(..) | Instr::Return(_) => {
+               current.push(instr);
+               blocks.push(std::mem::take(&mut current));
+            },
+            _ => current.push(instr),
+         }
+      }
+      if !current.is_empty() {
+         blocks.push(current);
+      }
+
+      let blocks = blocks.into_iter().map(BasicBlock::new).collect();
+      let mut cfg = Cfg { blocks, entry_successors: Vec::new(), exit_predecessors: Vec::new() };
+      cfg.update_edges();
+      cfg
+   }
+
+   // Flattens the blocks back into a function body
+   pub fn into_instrs(self) -> Vec<Instr> {
+      self.blocks.into_iter().flat_map(|block| block.instrs).collect()
+   }
+
+   pub fn successors(&self, node: Node) -> &[Node] {
+      match node {
+         Node::Entry => &self.entry_successors,
+         Node::Block(i) => &self.blocks[i].successors,
+         Node::Exit => &[],
+      }
+   }
+
+   pub fn predecessors(&self, node: Node) -> &[Node] {
+      match node {
+         Node::Entry => &[],
+         Node::Block(i) => &self.blocks[i].predecessors,
+         Node::Exit => &self.exit_predecessors,
+      }
+   }
+
+   // Recomputes every edge from the instructions, after a pass has changed the
+   // jumps or labels of some blocks
+   pub fn update_edges(&mut self) {
+      let labels: HashMap<String, usize> = self.blocks.iter().enumerate().filter_map(|(i, block)| match block.instrs.first() {
+         Some(Instr::Label(label)) => Some((label.clone(), i)),
+         _ => None,
+      }).collect();
+
+      let count = self.blocks.len();
+      let next = |i: usize| if i + 1 < count { Node::Block(i + 1) } else { Node::Exit };
+      let mut edges = Vec::new();
+      for (i, block) in self.blocks.iter().enumerate() {
+         let target = |label: &String| Node::Block(labels[label]);
+         match block.instrs.last() {
+            Some(Instr::Return(_)) => edges.push((i, Node::Exit)),
+            Some(Instr::Jump(label)) => edges.push((i, target(label))),
+            Some(Instr::JumpIfZero(_, label) | Instr::JumpIfNotZero(_, label)) => {
+               edges.push((i, target(label)));
+               edges.push((i, next(i)));
+            },
+            _ => edges.push((i, next(i))),
+         }
+      }
+
+      self.entry_successors = vec![if count > 0 { Node::Block(0) } else { Node::Exit }];
+      self.exit_predecessors = if count > 0 { Vec::new() } else { vec![Node::Entry] };
+      for block in &mut self.blocks {
+         block.predecessors.clear();
+         block.successors.clear();
+      }
+      if count > 0 {
+         self.blocks[0].predecessors.push(Node::Entry);
+      }
+      for (from, to) in edges {
+         let successors = &mut self.blocks[from].successors;
+         if successors.contains(&to) {
+            continue;
+         }
+         successors.push(to);
+         match to {
+            Node::Block(j) => self.blocks[j].predecessors.push(Node::Block(from)),
+            Node::Exit => self.exit_predecessors.push(Node::Block(from)),
+            Node::Entry => unreachable!(),
+         }
+      }
+   }
+
+   // Drops the blocks for which `keep` returns false, renumbering the rest
+   pub fn retain_blocks(&mut self, mut keep: impl FnMut(usize, &BasicBlock) -> bool) {
+      let mut i = 0;
+      self.blocks.retain(|block| {
+         let kept = keep(i, block);
+         i += 1;
+         kept
+      });
+      self.update_edges();
+   }
+
+   // The blocks in reverse postorder from the entry, which visits every block
+   // before its successors apart from loop back edges. Unreachable blocks are
+   // left out.
+   pub fn reverse_postorder(&self) -> Vec<usize> {
+      let mut visited = vec![false; self.blocks.len()];
+      let mut order = Vec::new();
+      // Iterative depth first search, keeping the next successor to visit
+      let mut stack: Vec<(Node, usize)> = vec![(Node::Entry, 0)];
+      while let Some((node, index)) = stack.pop() {
+         let successors = self.successors(node);
+         if index < successors.len() {
+            stack.push((node, index + 1));
+            if let Node::Block(j) = successors[index] && !visited[j] {
+               visited[j] = true;
+               stack.push((Node::Block(j), 0));
+            }
+         } else if let Node::Block(i) = node {
+            order.push(i);
+         }
+      }
+      order.reverse();
+      order
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{int, var};
+   use crate::tacky::tacky::BinaryOp;
+
+   // if (c) x = 1; else x = 2; return x; with a dead block after the return
+   fn diamond() -> Vec<Instr> {
+      vec![
+         Instr::JumpIfZero(var("c"), "else".to_string()),
+         Instr::Copy(int(1), var("x")),
+         Instr::Jump("end".to_string()),
+         Instr::Label("else".to_string()),
+         Instr::Copy(int(2), var("x")),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("x")),
+         Instr::Copy(int(3), var("x")),
+      ]
+   }
+
+   #[test]
+   fn splits_blocks_at_labels_and_jumps() {
+      let cfg = Cfg::new(diamond());
+      let sizes: Vec<usize> = cfg.blocks.iter().map(|block| block.instrs.len()).collect();
+      assert_eq!(sizes, vec![1, 2, 2, 2, 1]);
+      assert!(matches!(cfg.blocks[2].instrs[0], Instr::Label(_)));
+      assert_eq!(cfg.into_instrs().len(), diamond().len());
+   }
+
+   #[test]
+   fn connects_jumps_and_fall_through() {
+      let cfg = Cfg::new(diamond());
+      assert_eq!(cfg.successors(Node::Entry), [Node::Block(0)]);
+      // A conditional jump goes to its label or falls through
+      assert_eq!(cfg.successors(Node::Block(0)), [Node::Block(2), Node::Block(1)]);
+      assert_eq!(cfg.successors(Node::Block(1)), [Node::Block(3)]);
+      assert_eq!(cfg.successors(Node::Block(2)), [Node::Block(3)]);
+      assert_eq!(cfg.predecessors(Node::Block(3)), [Node::Block(1), Node::Block(2)]);
+      // The block after the return falls off the end of the function
+      assert_eq!(cfg.predecessors(Node::Block(4)), []);
+      assert_eq!(cfg.predecessors(Node::Exit), [Node::Block(3), Node::Block(4)]);
+   }
+
+   #[test]
+   fn orders_blocks_before_their_successors() {
+      let cfg = Cfg::new(vec![
+         Instr::Copy(int(0), var("i")),
+         Instr::Label("loop".to_string()),
+         Instr::Binary(BinaryOp::LessThan, var("i"), int(10), var("t")),
+         Instr::JumpIfZero(var("t"), "end".to_string()),
+         Instr::Binary(BinaryOp::Add, var("i"), int(1), var("i")),
+         Instr::Jump("loop".to_string()),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("i")),
+      ]);
+      let order = cfg.reverse_postorder();
+      assert_eq!(order.len(), cfg.blocks.len());
+      let position = |i: usize| order.iter().position(|&j| j == i).unwrap();
+      assert_eq!(order[0], 0);
+      // Every edge but the back edge to the loop header goes forward
+      assert!(position(1) < position(2) && position(1) < position(3));
+      assert!(cfg.successors(Node::Block(2)).contains(&Node::Block(1)));
+
+      // Unreachable blocks are left out
+      assert_eq!(Cfg::new(diamond()).reverse_postorder(), vec![0, 1, 2, 3]);
+   }
+
+   #[test]
+   fn renumbers_blocks_when_some_are_dropped() {
+      let mut cfg = Cfg::new(diamond());
+      cfg.retain_blocks(|i, _| i != 1);
+      assert_eq!(cfg.blocks.len(), 4);
+      // Falling through from the first block now leads to the old else block
+      assert_eq!(cfg.successors(Node::Block(0)), [Node::Block(1)]);
+      assert_eq!(cfg.predecessors(Node::Block(2)), [Node::Block(1)]);
+   }
+}
@@ -0,0 +1,126 @@
+// Evaluates operations whose operands are all constants at compile time. The
+// results match what the generated code computes on 32-bit ints, and operations
+// that are undefined in C (division by zero, INT_MIN / -1, out of range shifts)
+// are left for the program to perform.
+
+use crate::tacky::tacky::*;
+
+// Returns whether anything was folded
+pub fn fold_constants(instrs: &mut Vec<Instr>) -> bool {
+   let mut changed = false;
+   let mut folded = Vec::with_capacity(instrs.len());
+   for instr in instrs.drain(..) {
+      // What to replace the instruction with, if it can be folded
+      let replacement = match &instr {
+         Instr::Unary(op, Val::Integer(src), dest) => {
+            fold_unary(op, *src).map(|value| Some(Instr::Copy(Val::Integer(value), dest.clone())))
+         },
+         Instr::Binary(op, Val::Integer(left), Val::Integer(right), dest) => {
+            fold_binary(op, *left, *right).map(|value| Some(Instr::Copy(Val::Integer(value), dest.clone())))
+         },
+         Instr::JumpIfZero(Val::Integer(condition), label) => Some((*condition as i32 == 0).then(|| Instr::Jump(label.clone()))),
+         Instr::JumpIfNotZero(Val::Integer(condition), label) => Some((*condition as i32 != 0).then(|| Instr::Jump(label.clone()))),
+         _ => None,
+      };
+      match replacement {
+         Some(replacement) => {
+            folded.extend(replacement);
+            changed = true;
+         },
+         None => folded.push(instr),
+      }
+   }
+   *instrs = folded;
+   changed
+}
+
+pub fn fold_unary(op: &UnaryOp, src: i64) -> Option<i64> {
+   let src = src as i32;
+   let value = match op {
+      UnaryOp::Negate => src.wrapping_neg(),
+      UnaryOp::Complement => !src,
+      UnaryOp::Not => (src == 0) as i32,
+      // These write to their operand, which is never a constant
+      UnaryOp::PreIncrement | UnaryOp::PreDecrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement => return None,
+   };
+   Some(value as i64)
+}
+
+pub fn fold_binary(op: &BinaryOp, left: i64, right: i64) -> Option<i64> {
+   let (left, right) = (left as i32, right as i32);
+   let value = match op {
+      BinaryOp::Add => left.wrapping_add(right),
+      BinaryOp::Subtract => left.wrapping_sub(right),
+      BinaryOp::Multiply => left.wrapping_mul(right),
+      BinaryOp::Divide => left.checked_div(right)?,
+      BinaryOp::Modulus => left.checked_rem(right)?,
+      BinaryOp::BitwiseAnd => left & right,
+      BinaryOp::BitwiseOr => left | right,
+      BinaryOp::BitwiseXor => left ^ right,
+      BinaryOp::LeftShift => left.checked_shl(u32::try_from(right).ok()?)?,
+      BinaryOp::RightShift => left.checked_shr(u32::try_from(right).ok()?)?,
+      BinaryOp::Equal => (left == right) as i32,
+      BinaryOp::NotEqual => (left != right) as i32,
+      BinaryOp::LessThan => (left < right) as i32,
+      BinaryOp::LessOrEqual => (left <= right) as i32,
+      BinaryOp::GreaterThan => (left > right) as i32,
+      BinaryOp::GreaterOrEqual => (left >= right) as i32,
+   };
+   Some(value as i64)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{int, var};
+
+   fn fold(instr: Instr) -> Vec<Instr> {
+      let mut instrs = vec![instr];
+      fold_constants(&mut instrs);
+      instrs
+   }
+
+   fn folds_to(instr: Instr) -> Option<i64> {
+      match fold(instr).as_slice() {
+         [Instr::Copy(Val::Integer(value), _)] => Some(*value),
+         _ => None,
+      }
+   }
+
+   #[test]
+   fn folds_on_32_bit_ints() {
+      assert_eq!(folds_to(Instr::Binary(BinaryOp::Add, int(2), int(3), var("x"))), Some(5));
+      assert_eq!(folds_to(Instr::Binary(BinaryOp::Add, int(i32::MAX as i64), int(1), var("x"))), Some(i32::MIN as i64));
+      assert_eq!(folds_to(Instr::Binary(BinaryOp::Divide, int(-7), int(2), var("x"))), Some(-3));
+      assert_eq!(folds_to(Instr::Binary(BinaryOp::Modulus, int(-7), int(2), var("x"))), Some(-1));
+      assert_eq!(folds_to(Instr::Binary(BinaryOp::RightShift, int(-8), int(1), var("x"))), Some(-4));
+      assert_eq!(folds_to(Instr::Binary(BinaryOp::LessOrEqual, int(3), int(3), var("x"))), Some(1));
+      assert_eq!(folds_to(Instr::Unary(UnaryOp::Negate, int(i32::MIN as i64), var("x"))), Some(i32::MIN as i64));
+      assert_eq!(folds_to(Instr::Unary(UnaryOp::Complement, int(0), var("x"))), Some(-1));
+      assert_eq!(folds_to(Instr::Unary(UnaryOp::Not, int(5), var("x"))), Some(0));
+   }
+
+   #[test]
+   fn leaves_undefined_operations_to_the_program() {
+      for (op, left, right) in [
+         (BinaryOp::Divide, 1, 0),
+         (BinaryOp::Modulus, 1, 0),
+         (BinaryOp::Divide, i32::MIN as i64, -1),
+         (BinaryOp::Modulus, i32::MIN as i64, -1),
+         (BinaryOp::LeftShift, 1, 32),
+         (BinaryOp::RightShift, 1, -1),
+      ] {
+         let instr = Instr::Binary(op, int(left), int(right), var("x"));
+         assert!(matches!(fold(instr).as_slice(), [Instr::Binary(..)]));
+      }
+   }
+
+   #[test]
+   fn folds_constant_conditions() {
+      assert!(matches!(fold(Instr::JumpIfZero(int(0), "l".to_string())).as_slice(), [Instr::Jump(_)]));
+      assert!(fold(Instr::JumpIfZero(int(4), "l".to_string())).is_empty());
+      // Only the low 32 bits are the int
+      assert!(fold(Instr::JumpIfNotZero(int(1 << 32), "l".to_string())).is_empty());
+      assert!(matches!(fold(Instr::JumpIfNotZero(var("c"), "l".to_string())).as_slice(), [Instr::JumpIfNotZero(..)]));
+   }
+}
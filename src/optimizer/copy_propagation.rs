@@ -0,0 +1,220 @@
+// Replaces uses of a variable with the value last copied into it, wherever that
+// copy reaches the use along every path. A copy stops reaching once either side
+// is written again, and function calls may write any static variable.
+
+use super::cfg::{Cfg, Node};
+use super::is_static;
+use crate::tacky::tacky::*;
+use crate::validator::symbol_table::SymbolTable;
+
+use std::collections::HashSet;
+
+// The copies `dest = src` that hold at some point in a function
+type Copies = HashSet<(Val, String)>;
+
+// Returns whether any instruction was rewritten or removed
+pub fn propagate_copies(cfg: &mut Cfg, symbol_table: &SymbolTable) -> bool {
+   let reaching = reaching_copies(cfg, symbol_table);
+   let mut changed = false;
+   for i in cfg.reverse_postorder() {
+      let mut copies = reaching[i].clone();
+      let instrs = std::mem::take(&mut cfg.blocks[i].instrs);
+      for mut instr in instrs {
+         changed |= rewrite_uses(&mut instr, &copies);
+         if let Instr::Copy(src, Val::Var(dest)) = &instr && holds(&copies, src, dest) {
+            changed = true;
+            continue;
+         }
+         transfer(&instr, &mut copies, symbol_table);
+         cfg.blocks[i].instrs.push(instr);
+      }
+   }
+   changed
+}
+
+// Whether `dest = src` is already true, because the same copy or its reverse
+// reaches this point
+fn holds(copies: &Copies, src: &Val, dest: &String) -> bool {
+   match src {
+      Val::Var(name) if name == dest => true,
+      Val::Var(name) => copies.contains(&(src.clone(), dest.clone())) || copies.contains(&(Val::Var(dest.clone()), name.clone())),
+      Val::Integer(_) => copies.contains(&(src.clone(), dest.clone())),
+   }
+}
+
+// The copies reaching the start of each block, found by iterating to a fixed
+// point. Every block starts out with every copy in the function, so loops only
+// lose the copies that are broken along some path.
+fn reaching_copies(cfg: &Cfg, symbol_table: &SymbolTable) -> Vec<Copies> {
+   let all: Copies = cfg.blocks.iter().flat_map(|block| &block.instrs).filter_map(|instr| match instr {
+      Instr::Copy(src, Val::Var(dest)) => Some((src.clone(), dest.clone())),
+      _ => None,
+   }).collect();
+   let order = cfg.reverse_postorder();
+   let mut reaching_in = vec![all.clone(); cfg.blocks.len()];
+   let mut reaching_out = vec![all; cfg.blocks.len()];
+
+   let mut changed = true;
+   while changed {
+      changed = false;
+      for &i in &order {
+         let mut copies: Option<Copies> = None;
+         for predecessor in cfg.predecessors(Node::Block(i)) {
+            let incoming = match predecessor {
+               Node::Block(j) => &reaching_out[*j],
+               _ => &Copies::new(),
+            };
+            copies = Some(match copies {
+               Some(copies) => copies.intersection(incoming).cloned().collect(),
+               None => incoming.clone(),
+            });
+         }
+         let copies = copies.unwrap_or_default();
+         let mut out = copies.clone();
+         for instr in &cfg.blocks[i].instrs {
+            transfer(instr, &mut out, symbol_table);
+         }
+         reaching_in[i] = copies;
+         if out != reaching_out[i] {
+            reaching_out[i] = out;
+            changed = true;
+         }
+      }
+   }
+   reaching_in
+}
+
+// Updates the copies that hold after `instr`
+fn transfer(instr: &Instr, copies: &mut Copies, symbol_table: &SymbolTable) {
+   match instr {
+      Instr::Copy(src, Val::Var(dest)) => {
+         if holds(copies, src, dest) {
+            return;
+         }
+         kill(copies, dest);
+         copies.insert((src.clone(), dest.clone()));
+      },
+      Instr::Unary(op, src, dest) => {
+         if let (UnaryOp::PreIncrement | UnaryOp::PreDecrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement, Val::Var(name)) = (op, src) {
+            kill(copies, name);
+         }
+         written(copies, dest);
+      },
+      Instr::Binary(_, _, _, dest) => written(copies, dest),
+      Instr::FuncCall(_, _, dest) => {
+         copies.retain(|(src, dest)| {
+            !is_static(dest, symbol_table) && !matches!(src, Val::Var(name) if is_static(name, symbol_table))
+         });
+         written(copies, dest);
+      },
+      _ => (),
+   }
+}
+
+fn written(copies: &mut Copies, dest: &Val) {
+   if let Val::Var(name) = dest {
+      kill(copies, name);
+   }
+}
+
+// Drops the copies to or from `var`, which has just been written
+fn kill(copies: &mut Copies, var: &String) {
+   copies.retain(|(src, dest)| dest != var && !matches!(src, Val::Var(name) if name == var));
+}
+
+// Replaces the variables `instr` reads with their copied value. The operand of
+// an increment or decrement is also written, so it is left alone.
+fn rewrite_uses(instr: &mut Instr, copies: &Copies) -> bool {
+   let uses: Vec<&mut Val> = match instr {
+      Instr::Return(val) | Instr::JumpIfZero(val, _) | Instr::JumpIfNotZero(val, _) | Instr::Copy(val, _) => vec![val],
+      Instr::Unary(UnaryOp::PreIncrement | UnaryOp::PreDecrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement, ..) => vec![],
+      Instr::Unary(_, src, _) => vec![src],
+      Instr::Binary(_, left, right, _) => vec![left, right],
+      Instr::FuncCall(_, args, _) => args.iter_mut().collect(),
+      Instr::Jump(_) | Instr::Label(_) => vec![],
+   };
+   let mut changed = false;
+   for val in uses {
+      let Val::Var(name) = val else {
+         continue;
+      };
+      if let Some((src, _)) = copies.iter().find(|(_, dest)| dest == name) {
+         *val = src.clone();
+         changed = true;
+      }
+   }
+   changed
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{function, int, symbol_table, var, Machine};
+
+   fn propagate(instrs: Vec<Instr>, symbol_table: &SymbolTable) -> Vec<Instr> {
+      let mut cfg = Cfg::new(instrs);
+      propagate_copies(&mut cfg, symbol_table);
+      cfg.into_instrs()
+   }
+
+   // sum = 0; i = 0; step = n; while (i < 3) { sum += step; i += 1; } return sum;
+   fn counting_loop() -> Vec<Instr> {
+      vec![
+         Instr::Copy(int(0), var("sum")),
+         Instr::Copy(int(0), var("i")),
+         Instr::Copy(var("n"), var("step")),
+         Instr::Label("loop".to_string()),
+         Instr::Binary(BinaryOp::LessThan, var("i"), int(3), var("t")),
+         Instr::JumpIfZero(var("t"), "end".to_string()),
+         Instr::Binary(BinaryOp::Add, var("sum"), var("step"), var("sum")),
+         Instr::Binary(BinaryOp::Add, var("i"), int(1), var("i")),
+         Instr::Jump("loop".to_string()),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("sum")),
+      ]
+   }
+
+   #[test]
+   fn copies_reach_around_the_loop_only_if_unbroken() {
+      let instrs = propagate(counting_loop(), &symbol_table(&[]));
+      assert_eq!(Machine::new(&[], &[]).run(&instrs, &[("n", 4)]), 12);
+      // step is never written in the loop, so the copy reaches across the back
+      // edge, but i and sum are written there
+      assert!(matches!(&instrs[6], Instr::Binary(BinaryOp::Add, Val::Var(sum), Val::Var(n), _) if sum == "sum" && n == "n"));
+      assert!(matches!(&instrs[4], Instr::Binary(BinaryOp::LessThan, Val::Var(i), _, _) if i == "i"));
+      assert!(matches!(&instrs[10], Instr::Return(Val::Var(sum)) if sum == "sum"));
+   }
+
+   #[test]
+   fn calls_break_copies_of_statics() {
+      let table = symbol_table(&["counter"]);
+      let bump = function("bump", &[], vec![
+         Instr::Binary(BinaryOp::Add, var("counter"), int(1), var("counter")),
+         Instr::Return(int(0)),
+      ]);
+      let instrs = propagate(vec![
+         Instr::Copy(int(5), var("counter")),
+         Instr::Copy(int(7), var("local")),
+         Instr::Copy(var("counter"), var("saved")),
+         Instr::FuncCall("bump".to_string(), vec![], var("ignored")),
+         Instr::Binary(BinaryOp::Add, var("counter"), var("local"), var("sum")),
+         Instr::Binary(BinaryOp::Add, var("sum"), var("saved"), var("total")),
+         Instr::Return(var("total")),
+      ], &table);
+      assert_eq!(Machine::new(&[bump], &[("counter", 0)]).run(&instrs, &[]), 18);
+      // The call may change counter but not the local
+      assert!(matches!(&instrs[4], Instr::Binary(_, Val::Var(counter), Val::Integer(7), _) if counter == "counter"));
+      assert!(matches!(&instrs[5], Instr::Binary(_, _, Val::Integer(5), _)));
+   }
+
+   #[test]
+   fn removes_copies_that_already_hold() {
+      let instrs = propagate(vec![
+         Instr::Copy(var("a"), var("b")),
+         Instr::Copy(var("b"), var("a")),
+         Instr::Copy(var("a"), var("b")),
+         Instr::Return(var("b")),
+      ], &symbol_table(&[]));
+      assert!(matches!(instrs.as_slice(), [Instr::Copy(..), Instr::Return(Val::Var(a))] if a == "a"));
+   }
+}
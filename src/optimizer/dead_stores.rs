@@ -0,0 +1,165 @@
+// Deletes instructions whose result is never read. Which variables are live,
+// meaning they may still be read before being written again, is found by a
+// backward analysis over the CFG. Function calls and writes to static
+// variables are observable outside the function, so they are always kept.
+
+use super::cfg::{Cfg, Node};
+use super::is_static;
+use crate::tacky::tacky::*;
+use crate::validator::symbol_table::{Attrs, SymbolTable};
+
+use std::collections::HashSet;
+
+type Live = HashSet<String>;
+
+// Returns whether any instruction was deleted
+pub fn eliminate_dead_stores(cfg: &mut Cfg, symbol_table: &SymbolTable) -> bool {
+   // Callers and callees may read any static variable
+   let statics: Live = symbol_table.iter().filter_map(|(name, entry)| match entry.attrs {
+      Attrs::StaticAttr { .. } => Some(name.clone()),
+      _ => None,
+   }).collect();
+   let live_out = live_variables(cfg, &statics);
+
+   let mut changed = false;
+   for (block, mut live) in cfg.blocks.iter_mut().zip(live_out) {
+      let mut kept = Vec::with_capacity(block.instrs.len());
+      for instr in std::mem::take(&mut block.instrs).into_iter().rev() {
+         if is_dead(&instr, &live, symbol_table) {
+            changed = true;
+            continue;
+         }
+         transfer(&instr, &mut live, &statics);
+         kept.push(instr);
+      }
+      kept.reverse();
+      block.instrs = kept;
+   }
+   changed
+}
+
+// The variables live at the end of each block, found by iterating to a fixed
+// point from the exit backwards
+fn live_variables(cfg: &Cfg, statics: &Live) -> Vec<Live> {
+   let mut live_in = vec![Live::new(); cfg.blocks.len()];
+   let mut live_out = vec![Live::new(); cfg.blocks.len()];
+   let mut changed = true;
+   while changed {
+      changed = false;
+      for i in (0..cfg.blocks.len()).rev() {
+         let mut live = Live::new();
+         for successor in cfg.successors(Node::Block(i)) {
+            match successor {
+               Node::Block(j) => live.extend(live_in[*j].iter().cloned()),
+               _ => live.extend(statics.iter().cloned()),
+            }
+         }
+         live_out[i] = live.clone();
+         for instr in cfg.blocks[i].instrs.iter().rev() {
+            transfer(instr, &mut live, statics);
+         }
+         if live != live_in[i] {
+            live_in[i] = live;
+            changed = true;
+         }
+      }
+   }
+   live_out
+}
+
+// Updates the variables live before `instr` from those live after it
+fn transfer(instr: &Instr, live: &mut Live, statics: &Live) {
+   let (dest, uses): (Option<&Val>, Vec<&Val>) = match instr {
+      Instr::Return(val) | Instr::JumpIfZero(val, _) | Instr::JumpIfNotZero(val, _) => (None, vec![val]),
+      Instr::Copy(src, dest) | Instr::Unary(_, src, dest) => (Some(dest), vec![src]),
+      Instr::Binary(_, left, right, dest) => (Some(dest), vec![left, right]),
+      Instr::FuncCall(_, args, dest) => (Some(dest), args.iter().collect()),
+      Instr::Jump(_) | Instr::Label(_) => (None, vec![]),
+   };
+   if let Some(Val::Var(name)) = dest {
+      live.remove(name);
+   }
+   for val in uses {
+      if let Val::Var(name) = val {
+         live.insert(name.clone());
+      }
+   }
+   if let Instr::FuncCall(..) = instr {
+      live.extend(statics.iter().cloned());
+   }
+}
+
+// Whether `instr` only writes a variable nobody reads afterwards
+fn is_dead(instr: &Instr, live: &Live, symbol_table: &SymbolTable) -> bool {
+   let dead = |val: &Val| match val {
+      Val::Var(name) => !live.contains(name) && !is_static(name, symbol_table),
+      Val::Integer(_) => false,
+   };
+   match instr {
+      // Increments and decrements also write their operand
+      Instr::Unary(UnaryOp::PreIncrement | UnaryOp::PreDecrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement, src, dest) => {
+         dead(src) && dead(dest)
+      },
+      Instr::Copy(_, dest) | Instr::Unary(_, _, dest) | Instr::Binary(_, _, _, dest) => dead(dest),
+      _ => false,
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{int, symbol_table, var};
+
+   fn eliminate(instrs: Vec<Instr>, symbol_table: &SymbolTable) -> Vec<Instr> {
+      let mut cfg = Cfg::new(instrs);
+      eliminate_dead_stores(&mut cfg, symbol_table);
+      cfg.into_instrs()
+   }
+
+   #[test]
+   fn removes_stores_overwritten_before_a_read() {
+      let instrs = eliminate(vec![
+         Instr::Copy(int(1), var("x")),
+         Instr::Binary(BinaryOp::Add, var("a"), int(1), var("unused")),
+         Instr::Copy(int(2), var("x")),
+         Instr::Return(var("x")),
+      ], &symbol_table(&[]));
+      assert!(matches!(instrs.as_slice(), [Instr::Copy(Val::Integer(2), _), Instr::Return(_)]));
+   }
+
+   #[test]
+   fn keeps_stores_read_after_a_call_or_to_statics() {
+      let original = vec![
+         Instr::Copy(int(1), var("saved")),
+         Instr::Copy(int(2), var("counter")),
+         // The call may read counter, and its own result is never removed
+         Instr::FuncCall("f".to_string(), vec![], var("ignored")),
+         Instr::Copy(int(3), var("counter")),
+         Instr::Return(var("saved")),
+      ];
+      let instrs = eliminate(original.clone(), &symbol_table(&["counter"]));
+      assert_eq!(instrs.len(), original.len());
+
+      // A local the call can't see is dead once nothing after it reads it
+      let instrs = eliminate(vec![
+         Instr::Copy(int(2), var("local")),
+         Instr::FuncCall("f".to_string(), vec![], var("ignored")),
+         Instr::Return(int(0)),
+      ], &symbol_table(&[]));
+      assert!(matches!(instrs.as_slice(), [Instr::FuncCall(..), Instr::Return(_)]));
+   }
+
+   #[test]
+   fn keeps_stores_read_on_the_next_trip_around_a_loop() {
+      let original = vec![
+         Instr::Copy(int(0), var("i")),
+         Instr::Label("loop".to_string()),
+         Instr::Binary(BinaryOp::Add, var("i"), int(1), var("i")),
+         Instr::Binary(BinaryOp::LessThan, var("i"), int(3), var("t")),
+         Instr::JumpIfNotZero(var("t"), "loop".to_string()),
+         Instr::Return(int(0)),
+      ];
+      let instrs = eliminate(original.clone(), &symbol_table(&[]));
+      assert_eq!(instrs.len(), original.len());
+   }
+}
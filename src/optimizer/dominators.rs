@@ -0,0 +1,118 @@
+// Dominators of the blocks of a CFG. Block a dominates block b when every path
+// from the entry to b goes through a, and the immediate dominator of b is the
+// closest of its dominators other than itself.
+
+use super::cfg::{Cfg, Node};
+
+use std::collections::HashSet;
+
+pub struct Dominators {
+   // None for the first block and for unreachable blocks
+   pub idom: Vec<Option<usize>>,
+   // The blocks each block immediately dominates, forming the dominator tree
+   pub children: Vec<Vec<usize>>,
+}
+
+impl Dominators {
+   // Uses the iterative algorithm of Cooper, Harvey and Kennedy, which walks the
+   // blocks in reverse postorder until the immediate dominators stop changing
+   pub fn new(cfg: &Cfg) -> Self {
+      let order = cfg.reverse_postorder();
+      let mut position = vec![usize::MAX; cfg.blocks.len()];
+      for (k, &i) in order.iter().enumerate() {
+         position[i] = k;
+      }
+      let mut idom: Vec<Option<usize>> = vec![None; cfg.blocks.len()];
+      let Some(&root) = order.first() else {
+         return Dominators { idom, children: Vec::new() };
+      };
+      idom[root] = Some(root);
+
+      let mut changed = true;
+      while changed {
+         changed = false;
+         for &i in &order[1..] {
+            let mut new_idom = None;
+            for predecessor in cfg.predecessors(Node::Block(i)) {
+               let Node::Block(p) = *predecessor else {
+                  continue;
+               };
+               if idom[p].is_none() {
+                  continue;
+               }
+               new_idom = Some(match new_idom {
+                  Some(other) => intersect(&idom, &position, p, other),
+                  None => p,
+               });
+            }
+            if idom[i] != new_idom {
+               idom[i] = new_idom;
+               changed = true;
+            }
+         }
+      }
+      idom[root] = None;
+
+      let mut children = vec![Vec::new(); cfg.blocks.len()];
+      for &i in &order {
+         if let Some(parent) = idom[i] {
+            children[parent].push(i);
+         }
+      }
+      Dominators { idom, children }
+   }
+
+   // Whether block a dominates block b
+   pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+      loop {
+         if a == b {
+            return true;
+         }
+         match self.idom[b] {
+            Some(parent) => b = parent,
+            None => return false,
+         }
+      }
+   }
+
+   // The dominance frontier of each block: the blocks where its dominance ends,
+   // which it reaches without dominating
+   pub fn frontiers(&self, cfg: &Cfg) -> Vec<HashSet<usize>> {
+      let mut frontiers = vec![HashSet::new(); cfg.blocks.len()];
+      for (i, block) in cfg.blocks.iter().enumerate() {
+         if block.predecessors.len() < 2 {
+            continue;
+         }
+         for predecessor in &block.predecessors {
+            let Node::Block(mut runner) = *predecessor else {
+               continue;
+            };
+            // Unreachable predecessors don't lead anywhere
+            if runner != 0 && self.idom[runner].is_none() {
+               continue;
+            }
+            while Some(runner) != self.idom[i] {
+               frontiers[runner].insert(i);
+               match self.idom[runner] {
+                  Some(parent) => runner = parent,
+                  None => break,
+               }
+            }
+         }
+      }
+      frontiers
+   }
+}
+
+// The closest common dominator of blocks a and b
+fn intersect(idom: &[Option<usize>], position: &[usize], mut a: usize, mut b: usize) -> usize {
+   while a != b {
+      while position[a] > position[b] {
+         a = idom[a].unwrap();
+      }
+      while position[b] > position[a] {
+         b = idom[b].unwrap();
+      }
+   }
+   a
+}
@@ -0,0 +1,206 @@
+// Global value numbering over SSA form. Walking the dominator tree, each pure
+// computation is looked up among the ones that dominate it, and one that was
+// already computed becomes a copy of the earlier result. Copies are folded into
+// their source, so `y = x; b = y * 2` matches an earlier `a = x * 2`.
+//
+// Static variables aren't in SSA form, so computations that read them are only
+// reused while nothing could have written them in between: no write to a
+// static, no call and no block that can be entered from elsewhere.
+
+use super::cfg::Node;
+use super::dominators::Dominators;
+use super::ssa::Ssa;
+use super::{dest, is_static, sources_mut};
+use crate::tacky::tacky::*;
+use crate::validator::symbol_table::SymbolTable;
+
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Expr {
+   Unary(UnaryOp, Val),
+   Binary(BinaryOp, Val, Val),
+   // Reads a static variable
+   Load(String),
+}
+
+// An expression, along with the epoch of the statics it reads if it reads any.
+// The epoch moves on whenever the statics may have changed.
+type Key = (Expr, Option<usize>);
+
+// Returns whether any computation was replaced
+pub fn number_values(ssa: &mut Ssa, symbol_table: &SymbolTable) -> bool {
+   let dominators = Dominators::new(&ssa.cfg);
+   let mut numbering = Numbering { table: HashMap::new(), aliases: HashMap::new(), symbol_table, changed: false };
+   if !ssa.cfg.blocks.is_empty() {
+      numbering.number(0, ssa, &dominators, 0);
+   }
+   // Phi arguments are read at the end of a predecessor, which may come after
+   // the phi's own block in the walk
+   for phis in &mut ssa.phis {
+      for phi in phis {
+         for (_, arg) in &mut phi.args {
+            numbering.canonicalize(arg);
+         }
+      }
+   }
+   numbering.changed
+}
+
+struct Numbering<'a> {
+   // The version holding the value of each expression computed in a dominating
+   // block
+   table: HashMap<Key, String>,
+   // Versions known to hold the same value as an earlier one
+   aliases: HashMap<String, Val>,
+   symbol_table: &'a SymbolTable,
+   changed: bool,
+}
+
+impl Numbering<'_> {
+   fn number(&mut self, i: usize, ssa: &mut Ssa, dominators: &Dominators, mut epoch: usize) {
+      if !matches!(ssa.cfg.predecessors(Node::Block(i)), [_]) {
+         epoch += 1;
+      }
+      let mut scope = Vec::new();
+      for instr in &mut ssa.cfg.blocks[i].instrs {
+         for val in sources_mut(instr) {
+            self.canonicalize(val);
+         }
+         let key = match &*instr {
+            Instr::Copy(Val::Var(src), _) if is_static(src, self.symbol_table) => Some((Expr::Load(src.clone()), Some(epoch))),
+            Instr::Copy(src, Val::Var(dest)) if !is_static(dest, self.symbol_table) => {
+               self.aliases.insert(dest.clone(), src.clone());
+               None
+            },
+            Instr::Unary(op, src, _) if !is_increment(op) => Some((Expr::Unary(op.clone(), src.clone()), self.epoch_of(&[src], epoch))),
+            Instr::Binary(op, left, right, _) => {
+               let (left, right) = if is_commutative(op) && left > right { (right, left) } else { (left, right) };
+               Some((Expr::Binary(op.clone(), left.clone(), right.clone()), self.epoch_of(&[left, right], epoch)))
+            },
+            _ => None,
+         };
+         if let Some(key) = key && let Some(Val::Var(dest)) = dest(instr) && !is_static(dest, self.symbol_table) {
+            let dest = dest.clone();
+            match self.table.get(&key) {
+               Some(holder) => {
+                  let holder = Val::Var(holder.clone());
+                  self.aliases.insert(dest.clone(), holder.clone());
+                  *instr = Instr::Copy(holder, Val::Var(dest));
+                  self.changed = true;
+               },
+               None => {
+                  self.table.insert(key.clone(), dest);
+                  scope.push(key);
+               },
+            }
+         }
+         if self.writes_static(instr) {
+            epoch += 1;
+         }
+      }
+
+      for &child in &dominators.children[i] {
+         self.number(child, ssa, dominators, epoch);
+      }
+      for key in scope {
+         self.table.remove(&key);
+      }
+   }
+
+   // Replaces a version with the earliest one known to hold its value
+   fn canonicalize(&self, val: &mut Val) {
+      while let Val::Var(name) = val && let Some(alias) = self.aliases.get(name) {
+         *val = alias.clone();
+      }
+   }
+
+   fn epoch_of(&self, operands: &[&Val], epoch: usize) -> Option<usize> {
+      operands.iter().any(|val| matches!(val, Val::Var(name) if is_static(name, self.symbol_table))).then_some(epoch)
+   }
+
+   fn writes_static(&self, instr: &Instr) -> bool {
+      match instr {
+         Instr::FuncCall(..) => true,
+         Instr::Unary(op, Val::Var(name), _) if is_increment(op) => is_static(name, self.symbol_table),
+         _ => matches!(dest(instr), Some(Val::Var(name)) if is_static(name, self.symbol_table)),
+      }
+   }
+}
+
+fn is_increment(op: &UnaryOp) -> bool {
+   matches!(op, UnaryOp::PreIncrement | UnaryOp::PreDecrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement)
+}
+
+fn is_commutative(op: &BinaryOp) -> bool {
+   matches!(op, BinaryOp::Add | BinaryOp::Multiply | BinaryOp::BitwiseAnd | BinaryOp::BitwiseOr | BinaryOp::BitwiseXor | BinaryOp::Equal | BinaryOp::NotEqual)
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{function, int, symbol_table, var, Machine};
+
+   fn block_instrs(ssa: &Ssa, i: usize) -> Vec<&Instr> {
+      ssa.cfg.blocks[i].instrs.iter().filter(|instr| !matches!(instr, Instr::Label(_))).collect()
+   }
+
+   #[test]
+   fn dominating_computations_are_reused() {
+      let instrs = vec![
+         Instr::Binary(BinaryOp::Add, var("a"), var("b"), var("x")),
+         Instr::JumpIfZero(var("c"), "else".to_string()),
+         Instr::Binary(BinaryOp::Subtract, var("a"), var("b"), var("p")),
+         Instr::Jump("end".to_string()),
+         Instr::Label("else".to_string()),
+         Instr::Binary(BinaryOp::Subtract, var("a"), var("b"), var("q")),
+         Instr::Label("end".to_string()),
+         Instr::Binary(BinaryOp::Add, var("b"), var("a"), var("y")),
+         Instr::Binary(BinaryOp::Subtract, var("a"), var("b"), var("r")),
+         Instr::Binary(BinaryOp::Multiply, var("y"), var("r"), var("s")),
+         Instr::Return(var("s")),
+      ];
+      let symbol_table = symbol_table(&[]);
+      let mut ssa = Ssa::new(instrs.clone(), &symbol_table);
+      assert!(number_values(&mut ssa, &symbol_table));
+
+      // b + a is a + b from the first block, which dominates the last one. The
+      // subtractions in the branches don't dominate it, so r is computed again.
+      let last = block_instrs(&ssa, 3);
+      assert!(matches!(last[0], Instr::Copy(Val::Var(x), _) if x.starts_with("x.")));
+      assert!(matches!(last[1], Instr::Binary(BinaryOp::Subtract, ..)));
+
+      let numbered = ssa.into_instrs();
+      for c in [0, 1] {
+         let args = [("a", 7), ("b", 3), ("c", c)];
+         assert_eq!(Machine::new(&[], &[]).run(&numbered, &args), Machine::new(&[], &[]).run(&instrs, &args));
+      }
+   }
+
+   #[test]
+   fn loads_are_not_reused_across_calls() {
+      let bump = function("bump", &[], vec![
+         Instr::Unary(UnaryOp::PreIncrement, var("counter"), var("new")),
+         Instr::Return(var("new")),
+      ]);
+      let instrs = vec![
+         Instr::Copy(var("counter"), var("before")),
+         Instr::Copy(var("counter"), var("again")),
+         Instr::FuncCall("bump".to_string(), vec![], var("ignored")),
+         Instr::Copy(var("counter"), var("after")),
+         Instr::Binary(BinaryOp::Multiply, var("again"), int(100), var("scaled")),
+         Instr::Binary(BinaryOp::Add, var("scaled"), var("after"), var("result")),
+         Instr::Return(var("result")),
+      ];
+      let symbol_table = symbol_table(&["counter"]);
+      let mut ssa = Ssa::new(instrs, &symbol_table);
+      assert!(number_values(&mut ssa, &symbol_table));
+
+      // The second load before the call is the first one again, but the call
+      // may change the variable
+      let loads = block_instrs(&ssa, 0).into_iter().filter(|instr| matches!(instr, Instr::Copy(Val::Var(src), _) if src == "counter")).count();
+      assert_eq!(loads, 2);
+      let numbered = ssa.into_instrs();
+      assert_eq!(Machine::new(&[bump], &[("counter", 1)]).run(&numbered, &[]), 102);
+   }
+}
@@ -0,0 +1,367 @@
+// Replaces calls with a copy of the callee's body, for callees that are small or
+// declared 'inline'. The copy gets its own names for the callee's variables and
+// labels so it can't clash with the caller or with other copies, and each return
+// becomes a copy into the call's result and a jump past the body. Functions
+// that can end up calling themselves are never inlined, so inlining always
+// stops.
+
+use super::{dest_mut, is_static, sources_mut};
+use crate::name_generator;
+use crate::tacky::tacky::*;
+use crate::validator::symbol_table::SymbolTable;
+
+use std::collections::{HashMap, HashSet};
+
+// Callees up to this many instructions are inlined even without 'inline'
+const SMALL_FUNCTION: usize = 16;
+// Callees declared 'inline' are inlined up to this size
+const INLINE_FUNCTION: usize = 200;
+// No more calls are inlined into a function once it has grown this large
+const LARGE_FUNCTION: usize = 2000;
+
+// The indices of the functions in `program`, each after the functions it calls
+// apart from calls that go round a cycle
+pub fn bottom_up(program: &TackyProgram) -> Vec<usize> {
+   let calls = call_graph(program);
+   let mut visited = HashSet::new();
+   let mut order = Vec::new();
+   let mut functions: Vec<usize> = calls.keys().copied().collect();
+   functions.sort();
+   for i in functions {
+      visit(i, &calls, &mut visited, &mut order);
+   }
+   order
+}
+
+fn visit(i: usize, calls: &HashMap<usize, Vec<usize>>, visited: &mut HashSet<usize>, order: &mut Vec<usize>) {
+   if !visited.insert(i) {
+      return;
+   }
+   for &callee in &calls[&i] {
+      visit(callee, calls, visited, order);
+   }
+   order.push(i);
+}
+
+// The names of the functions that can call themselves, directly or through
+// other functions
+pub fn recursive_functions(program: &TackyProgram) -> HashSet<String> {
+   let calls = call_graph(program);
+   calls.keys().filter(|&&i| {
+      let mut reached = HashSet::new();
+      let mut worklist = calls[&i].clone();
+      while let Some(j) = worklist.pop() {
+         if j == i {
+            return true;
+         }
+         if reached.insert(j) {
+            worklist.extend(&calls[&j]);
+         }
+      }
+      false
+   }).map(|&i| match &program.top_level[i] {
+      TopLevel::Function(func) => func.name.clone(),
+      TopLevel::StaticVar(_) => unreachable!(),
+   }).collect()
+}
+
+// The functions each function defined in `program` calls, by index, leaving out
+// the ones defined elsewhere
+fn call_graph(program: &TackyProgram) -> HashMap<usize, Vec<usize>> {
+   let indices: HashMap<&str, usize> = program.top_level.iter().enumerate().filter_map(|(i, top_level)| match top_level {
+      TopLevel::Function(func) => Some((func.name.as_str(), i)),
+      TopLevel::StaticVar(_) => None,
+   }).collect();
+   let mut calls = HashMap::new();
+   for &i in indices.values() {
+      let TopLevel::Function(func) = &program.top_level[i] else {
+         unreachable!()
+      };
+      let mut callees: Vec<usize> = func.instrs.iter().filter_map(|instr| match instr {
+         Instr::FuncCall(callee, ..) => indices.get(callee.as_str()).copied(),
+         _ => None,
+      }).collect();
+      callees.sort();
+      callees.dedup();
+      calls.insert(i, callees);
+   }
+   calls
+}
+
+// Drops the functions with internal linkage that no call is left to, which
+// happens once every call to them has been inlined
+pub fn remove_unused_functions(program: &mut TackyProgram) {
+   loop {
+      let called: HashSet<String> = program.top_level.iter().flat_map(|top_level| match top_level {
+         TopLevel::Function(func) => func.instrs.as_slice(),
+         TopLevel::StaticVar(_) => &[],
+      }).filter_map(|instr| match instr {
+         Instr::FuncCall(name, ..) => Some(name.clone()),
+         _ => None,
+      }).collect();
+      let count = program.top_level.len();
+      program.top_level.retain(|top_level| match top_level {
+         TopLevel::Function(func) => func.global || called.contains(&func.name),
+         TopLevel::StaticVar(_) => true,
+      });
+      if program.top_level.len() == count {
+         break;
+      }
+   }
+}
+
+// Inlines the calls in `func` to the functions in `callees` that are worth it.
+// Returns whether any call was inlined.
+pub fn inline_calls(func: &mut Function, callees: &HashMap<String, Function>, symbol_table: &SymbolTable) -> bool {
+   let mut changed = false;
+   let mut instrs = Vec::with_capacity(func.instrs.len());
+   let mut remaining = func.instrs.len();
+   for instr in std::mem::take(&mut func.instrs) {
+      remaining -= 1;
+      let callee = match &instr {
+         Instr::FuncCall(name, ..) => callees.get(name).filter(|callee| {
+            let size = callee.instrs.len();
+            let worth_it = size <= SMALL_FUNCTION || (callee.inline && size <= INLINE_FUNCTION);
+            worth_it && instrs.len() + remaining + size <= LARGE_FUNCTION
+         }),
+         _ => None,
+      };
+      match (callee, instr) {
+         (Some(callee), Instr::FuncCall(_, args, dest)) => {
+            inline_call(callee, args, dest, &mut instrs, symbol_table);
+            changed = true;
+         },
+         (_, instr) => instrs.push(instr),
+      }
+   }
+   func.instrs = instrs;
+   changed
+}
+
+fn inline_call(callee: &Function, args: Vec<Val>, dest: Val, instrs: &mut Vec<Instr>, symbol_table: &SymbolTable) {
+   let mut renaming = Renaming { vars: HashMap::new(), labels: HashMap::new(), symbol_table };
+   let end = name_generator::gen_label(&format!("{}_return", callee.name));
+   for (param, arg) in callee.params.iter().zip(args) {
+      instrs.push(Instr::Copy(arg, Val::Var(renaming.var(param))));
+   }
+   for instr in &callee.instrs {
+      let mut instr = instr.clone();
+      renaming.rename(&mut instr);
+      match instr {
+         Instr::Return(val) => {
+            instrs.push(Instr::Copy(val, dest.clone()));
+            instrs.push(Instr::Jump(end.clone()));
+         },
+         instr => instrs.push(instr),
+      }
+   }
+   instrs.push(Instr::Label(end));
+}
+
+// The names given to the callee's variables and labels in one inlined copy
+struct Renaming<'a> {
+   vars: HashMap<String, String>,
+   labels: HashMap<String, String>,
+   symbol_table: &'a SymbolTable,
+}
+
+impl Renaming<'_> {
+   fn rename(&mut self, instr: &mut Instr) {
+      if let Instr::Jump(label) | Instr::JumpIfZero(_, label) | Instr::JumpIfNotZero(_, label) | Instr::Label(label) = instr {
+         *label = self.label(label);
+      }
+      for val in sources_mut(instr) {
+         self.rename_val(val);
+      }
+      if let Some(val) = dest_mut(instr) {
+         self.rename_val(val);
+      }
+   }
+
+   // Statics are shared by every copy of the function, so they keep their name
+   fn rename_val(&mut self, val: &mut Val) {
+      if let Val::Var(name) = val && !is_static(name, self.symbol_table) {
+         *name = self.var(name);
+      }
+   }
+
+   fn var(&mut self, name: &str) -> String {
+      let base = name.split('.').next().unwrap().to_string();
+      self.vars.entry(name.to_string()).or_insert_with(|| name_generator::uniquify_identifier(&base)).clone()
+   }
+
+   fn label(&mut self, label: &str) -> String {
+      let base = label.split('.').next().unwrap();
+      self.labels.entry(label.to_string()).or_insert_with(|| name_generator::gen_label(base)).clone()
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::codegen::target::RelocationModel;
+   use crate::optimizer::interpreter::{function, int, symbol_table, var, Machine};
+   use crate::optimizer::{optimize, Optimizations};
+
+   fn call(name: &str, args: Vec<Val>, dest: &str) -> Instr {
+      Instr::FuncCall(name.to_string(), args, var(dest))
+   }
+
+   // A function of `size` instructions returning 1
+   fn sized(name: &str, size: usize) -> Function {
+      let mut instrs: Vec<Instr> = (1..size).map(|i| Instr::Copy(int(i as i64), var("x.0"))).collect();
+      instrs.push(Instr::Return(int(1)));
+      function(name, &[], instrs)
+   }
+
+   fn calls(func: &Function) -> Vec<&str> {
+      func.instrs.iter().filter_map(|instr| match instr {
+         Instr::FuncCall(name, ..) => Some(name.as_str()),
+         _ => None,
+      }).collect()
+   }
+
+   fn tacky(functions: &[Function]) -> TackyIR {
+      let top_level = functions.iter().cloned().map(TopLevel::Function).collect();
+      TackyIR { program: TackyProgram { top_level }, symbol_table: symbol_table(&[]) }
+   }
+
+   fn optimized(tacky: &mut TackyIR, relocation_model: RelocationModel) -> HashMap<String, Function> {
+      let optimizations = Optimizations { inline_functions: true, ..Optimizations::default() };
+      optimize(tacky, optimizations, relocation_model, false);
+      tacky.program.top_level.iter().filter_map(|top_level| match top_level {
+         TopLevel::Function(func) => Some((func.name.clone(), func.clone())),
+         TopLevel::StaticVar(_) => None,
+      }).collect()
+   }
+
+   #[test]
+   fn recursion_is_never_unrolled() {
+      let fact = function("fact", &["n"], vec![
+         Instr::JumpIfNotZero(var("n"), "recurse".to_string()),
+         Instr::Return(int(1)),
+         Instr::Label("recurse".to_string()),
+         Instr::Binary(BinaryOp::Subtract, var("n"), int(1), var("m")),
+         call("fact", vec![var("m")], "r"),
+         Instr::Binary(BinaryOp::Multiply, var("n"), var("r"), var("p")),
+         Instr::Return(var("p")),
+      ]);
+      let even = function("even", &["n"], vec![
+         Instr::JumpIfNotZero(var("n"), "recurse".to_string()),
+         Instr::Return(int(1)),
+         Instr::Label("recurse".to_string()),
+         Instr::Binary(BinaryOp::Subtract, var("n"), int(1), var("m")),
+         call("odd", vec![var("m")], "r"),
+         Instr::Return(var("r")),
+      ]);
+      let odd = function("odd", &["n"], vec![
+         Instr::JumpIfNotZero(var("n"), "recurse".to_string()),
+         Instr::Return(int(0)),
+         Instr::Label("recurse".to_string()),
+         Instr::Binary(BinaryOp::Subtract, var("n"), int(1), var("m")),
+         call("even", vec![var("m")], "r"),
+         Instr::Return(var("r")),
+      ]);
+      let main = function("main", &[], vec![
+         call("fact", vec![int(5)], "a"),
+         call("even", vec![int(7)], "b"),
+         Instr::Binary(BinaryOp::Add, var("a"), var("b"), var("c")),
+         Instr::Return(var("c")),
+      ]);
+      let mut tacky = tacky(&[fact, even, odd, main]);
+      assert_eq!(recursive_functions(&tacky.program), HashSet::from(["fact".to_string(), "even".to_string(), "odd".to_string()]));
+
+      let functions = optimized(&mut tacky, RelocationModel::Static);
+      assert_eq!(calls(&functions["fact"]), vec!["fact"]);
+      assert_eq!(calls(&functions["even"]), vec!["odd"]);
+      assert_eq!(calls(&functions["odd"]), vec!["even"]);
+      assert_eq!(calls(&functions["main"]), vec!["fact", "even"]);
+      let functions: Vec<Function> = functions.into_values().collect();
+      assert_eq!(Machine::new(&functions, &[]).call("main", &[]), 120);
+   }
+
+   #[test]
+   fn size_limits() {
+      let inlines = |callee: &Function, filler: usize| {
+         let mut instrs: Vec<Instr> = (0..filler).map(|i| Instr::Copy(int(i as i64), var("y.0"))).collect();
+         instrs.push(call(&callee.name, vec![], "r.0"));
+         instrs.push(Instr::Return(var("r.0")));
+         let mut caller = function("caller", &[], instrs);
+         let callees = HashMap::from([(callee.name.clone(), callee.clone())]);
+         inline_calls(&mut caller, &callees, &symbol_table(&[]))
+      };
+
+      assert!(inlines(&sized("small", SMALL_FUNCTION), 0));
+      assert!(!inlines(&sized("medium", SMALL_FUNCTION + 1), 0));
+
+      let mut hinted = sized("hinted", INLINE_FUNCTION);
+      hinted.inline = true;
+      assert!(inlines(&hinted, 0));
+      let mut too_big = sized("too_big", INLINE_FUNCTION + 1);
+      too_big.inline = true;
+      assert!(!inlines(&too_big, 0));
+
+      // The caller would grow past the limit: the filler, the return after the
+      // call and the callee's body
+      let small = sized("small", SMALL_FUNCTION);
+      assert!(inlines(&small, LARGE_FUNCTION - SMALL_FUNCTION - 1));
+      assert!(!inlines(&small, LARGE_FUNCTION - SMALL_FUNCTION));
+   }
+
+   #[test]
+   fn copies_get_their_own_names() {
+      let abs = function("abs", &["v"], vec![
+         Instr::Binary(BinaryOp::LessThan, var("v"), int(0), var("t")),
+         Instr::JumpIfZero(var("t"), "positive".to_string()),
+         Instr::Unary(UnaryOp::Negate, var("v"), var("n")),
+         Instr::Return(var("n")),
+         Instr::Label("positive".to_string()),
+         Instr::Return(var("v")),
+      ]);
+      let mut caller = function("caller", &["x", "y"], vec![
+         call("abs", vec![var("x")], "a"),
+         call("abs", vec![var("y")], "b"),
+         Instr::Binary(BinaryOp::Multiply, var("a"), int(100), var("c")),
+         Instr::Binary(BinaryOp::Add, var("c"), var("b"), var("d")),
+         Instr::Return(var("d")),
+      ]);
+      let callees = HashMap::from([("abs".to_string(), abs.clone())]);
+      assert!(inline_calls(&mut caller, &callees, &symbol_table(&[])));
+      assert!(calls(&caller).is_empty());
+
+      let labels: Vec<&String> = caller.instrs.iter().filter_map(|instr| match instr {
+         Instr::Label(label) => Some(label),
+         _ => None,
+      }).collect();
+      assert_eq!(labels.len(), 4);
+      assert_eq!(labels.iter().collect::<HashSet<_>>().len(), 4);
+      let negated: HashSet<&Val> = caller.instrs.iter().filter_map(|instr| match instr {
+         Instr::Unary(UnaryOp::Negate, _, dest) => Some(dest),
+         _ => None,
+      }).collect();
+      assert_eq!(negated.len(), 2);
+      assert!(!negated.contains(&var("n")));
+
+      for (x, y, expected) in [(-3, 4, 304), (5, -6, 506)] {
+         assert_eq!(Machine::new(&[], &[]).run(&caller.instrs, &[("x", x), ("y", y)]), expected);
+      }
+   }
+
+   #[test]
+   fn global_functions_are_not_inlined_into_shared_libraries() {
+      let mut internal = sized("internal", 2);
+      internal.global = false;
+      let main = function("main", &[], vec![
+         call("internal", vec![], "a"),
+         call("exported", vec![], "b"),
+         Instr::Binary(BinaryOp::Add, var("a"), var("b"), var("c")),
+         Instr::Return(var("c")),
+      ]);
+      let functions = optimized(&mut tacky(&[internal.clone(), sized("exported", 2), main.clone()]), RelocationModel::Pic);
+      assert_eq!(calls(&functions["main"]), vec!["exported"]);
+      assert!(!functions.contains_key("internal"));
+
+      let functions = optimized(&mut tacky(&[internal, sized("exported", 2), main]), RelocationModel::Pie);
+      assert!(calls(&functions["main"]).is_empty());
+   }
+}
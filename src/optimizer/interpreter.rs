@@ -0,0 +1,138 @@
+// A TACKY interpreter for the optimizer's tests, which check that a pass keeps
+// the meaning of a function by running it before and after the pass.
+
+use super::constant_folding::{fold_binary, fold_unary};
+use crate::tacky::tacky::*;
+use crate::validator::symbol_table::{Attrs, DeclType, InitialValue, SymbolTable, TypeInfo};
+
+use std::collections::HashMap;
+
+// Gives up on code that runs this long, which is taken to be stuck in a loop
+const MAX_STEPS: usize = 100_000;
+
+pub fn var(name: &str) -> Val {
+   Val::Var(name.to_string())
+}
+
+pub fn int(value: i64) -> Val {
+   Val::Integer(value)
+}
+
+pub fn function(name: &str, params: &[&str], instrs: Vec<Instr>) -> Function {
+   Function {
+      name: name.to_string(),
+      global: true,
+      inline: false,
+      params: params.iter().map(|param| param.to_string()).collect(),
+      instrs,
+   }
+}
+
+// A symbol table declaring `statics` as file scope variables that are not
+// visible to other files
+pub fn symbol_table(statics: &[&str]) -> SymbolTable {
+   statics.iter().map(|name| {
+      let attrs = Attrs::StaticAttr { initial_value: InitialValue::Tentative, global: false };
+      (name.to_string(), TypeInfo { decl_type: DeclType::Int, attrs })
+   }).collect()
+}
+
+pub struct Machine {
+   functions: HashMap<String, Function>,
+   pub statics: HashMap<String, i64>,
+}
+
+impl Machine {
+   pub fn new(functions: &[Function], statics: &[(&str, i64)]) -> Self {
+      Machine {
+         functions: functions.iter().map(|func| (func.name.clone(), func.clone())).collect(),
+         statics: statics.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+      }
+   }
+
+   pub fn call(&mut self, name: &str, args: &[i64]) -> i64 {
+      let func = self.functions.get(name).unwrap_or_else(|| panic!("call to undefined function {}", name)).clone();
+      let args: Vec<(&str, i64)> = func.params.iter().map(String::as_str).zip(args.iter().copied()).collect();
+      self.run(&func.instrs, &args)
+   }
+
+   // Runs a function body with its parameters bound to `args`, returning what
+   // it returns
+   pub fn run(&mut self, instrs: &[Instr], args: &[(&str, i64)]) -> i64 {
+      let labels: HashMap<&str, usize> = instrs.iter().enumerate().filter_map(|(i, instr)| match instr {
+         Instr::Label(label) => Some((label.as_str(), i)),
+         _ => None,
+      }).collect();
+      let mut locals: HashMap<String, i64> = args.iter().map(|(name, value)| (name.to_string(), *value)).collect();
+      let mut pc = 0;
+      for _ in 0..MAX_STEPS {
+         let instr = instrs.get(pc).expect("fell off the end of the function");
+         pc += 1;
+         match instr {
+            Instr::Return(val) => return self.value(val, &locals),
+            Instr::Unary(op @ (UnaryOp::PreIncrement | UnaryOp::PreDecrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement), src, dest) => {
+               let old = self.value(src, &locals) as i32;
+               let new = match op {
+                  UnaryOp::PreIncrement | UnaryOp::PostIncrement => old.wrapping_add(1),
+                  _ => old.wrapping_sub(1),
+               };
+               self.set(src, new as i64, &mut locals);
+               let result = match op {
+                  UnaryOp::PreIncrement | UnaryOp::PreDecrement => new,
+                  _ => old,
+               };
+               self.set(dest, result as i64, &mut locals);
+            },
+            Instr::Unary(op, src, dest) => {
+               let value = fold_unary(op, self.value(src, &locals)).unwrap();
+               self.set(dest, value, &mut locals);
+            },
+            Instr::Binary(op, left, right, dest) => {
+               let value = fold_binary(op, self.value(left, &locals), self.value(right, &locals)).expect("undefined behavior");
+               self.set(dest, value, &mut locals);
+            },
+            Instr::Copy(src, dest) => {
+               let value = self.value(src, &locals);
+               self.set(dest, value, &mut locals);
+            },
+            Instr::Jump(label) => pc = labels[label.as_str()],
+            Instr::JumpIfZero(val, label) => {
+               if self.value(val, &locals) as i32 == 0 {
+                  pc = labels[label.as_str()];
+               }
+            },
+            Instr::JumpIfNotZero(val, label) => {
+               if self.value(val, &locals) as i32 != 0 {
+                  pc = labels[label.as_str()];
+               }
+            },
+            Instr::Label(_) => {},
+            Instr::FuncCall(name, args, dest) => {
+               let args: Vec<i64> = args.iter().map(|arg| self.value(arg, &locals)).collect();
+               let value = self.call(name, &args);
+               self.set(dest, value, &mut locals);
+            },
+         }
+      }
+      panic!("no return after {} steps", MAX_STEPS)
+   }
+
+   fn value(&self, val: &Val, locals: &HashMap<String, i64>) -> i64 {
+      match val {
+         Val::Integer(value) => *value,
+         Val::Var(name) => *locals.get(name).or_else(|| self.statics.get(name)).unwrap_or_else(|| panic!("read of undefined variable {}", name)),
+      }
+   }
+
+   fn set(&mut self, dest: &Val, value: i64, locals: &mut HashMap<String, i64>) {
+      let Val::Var(name) = dest else {
+         panic!("write to a constant");
+      };
+      match self.statics.get_mut(name) {
+         Some(slot) => *slot = value,
+         None => {
+            locals.insert(name.clone(), value);
+         },
+      }
+   }
+}
@@ -0,0 +1,221 @@
+pub mod cfg;
+pub mod dominators;
+pub mod ssa;
+mod constant_folding;
+mod copy_propagation;
+mod dead_stores;
+mod gvn;
+mod inlining;
+mod sccp;
+mod unreachable_code;
+#[cfg(test)]
+mod interpreter;
+
+use crate::codegen::target::RelocationModel;
+use crate::tacky::{self, tacky::{Instr, TackyIR, TopLevel, Val}};
+use crate::validator::symbol_table::{Attrs, SymbolTable};
+use cfg::Cfg;
+use ssa::Ssa;
+
+use std::collections::HashMap;
+
+// The TACKY passes to run
+#[derive(Copy, Clone, Default)]
+pub struct Optimizations {
+   pub fold_constants: bool,
+   pub eliminate_unreachable_code: bool,
+   pub propagate_copies: bool,
+   pub eliminate_dead_stores: bool,
+   pub propagate_constants: bool,
+   pub eliminate_common_subexpressions: bool,
+   pub inline_functions: bool,
+}
+
+impl Optimizations {
+   // The passes enabled by -O<level>
+   pub fn level(level: u8) -> Self {
+      let enabled = level >= 1;
+      Optimizations {
+         fold_constants: enabled,
+         eliminate_unreachable_code: enabled,
+         propagate_copies: enabled,
+         eliminate_dead_stores: enabled,
+         propagate_constants: level >= 2,
+         eliminate_common_subexpressions: level >= 2,
+         inline_functions: level >= 2,
+      }
+   }
+
+   pub fn any(&self) -> bool {
+      self.fold_constants || self.eliminate_unreachable_code || self.propagate_copies || self.eliminate_dead_stores
+         || self.propagate_constants || self.eliminate_common_subexpressions
+         || self.inline_functions
+   }
+}
+
+// Optimizes each function after the ones it calls, so that what gets inlined
+// has already been optimized
+pub fn optimize(tacky: &mut TackyIR, optimizations: Optimizations, relocation_model: RelocationModel, print: bool) {
+   let constants = sccp::constant_statics(tacky);
+   let recursive = inlining::recursive_functions(&tacky.program);
+   let mut callees = HashMap::new();
+   for i in inlining::bottom_up(&tacky.program) {
+      let TopLevel::Function(func) = &mut tacky.program.top_level[i] else {
+         unreachable!()
+      };
+      if optimizations.inline_functions {
+         inlining::inline_calls(func, &callees, &tacky.symbol_table);
+      }
+      optimize_function(&mut func.instrs, optimizations, &tacky.symbol_table, &constants);
+      // In a shared library another definition of a global function may be
+      // interposed at load time, so only calls to static ones can be inlined
+      let interposable = relocation_model == RelocationModel::Pic && func.global;
+      if optimizations.inline_functions && !recursive.contains(&func.name) && !interposable {
+         callees.insert(func.name.clone(), func.clone());
+      }
+   }
+   if optimizations.inline_functions {
+      inlining::remove_unused_functions(&mut tacky.program);
+   }
+   if print {
+      tacky::print_tacky(tacky, "Optimized Tacky IR");
+   }
+}
+
+// Runs the passes until none of them changes anything, since each can expose
+// more work for the others: folding a condition makes a branch unreachable,
+// propagating a copy makes it dead, and so on. The passes over SSA form run
+// first, leaving the copies that replace their phis to the others.
+fn optimize_function(instrs: &mut Vec<Instr>, optimizations: Optimizations, symbol_table: &SymbolTable, constants: &HashMap<String, i64>) {
+   if optimizations.propagate_constants || optimizations.eliminate_common_subexpressions {
+      let mut ssa = Ssa::new(std::mem::take(instrs), symbol_table);
+      loop {
+         let mut changed = false;
+         if optimizations.propagate_constants {
+            changed |= sccp::propagate_constants(&mut ssa, constants, symbol_table);
+         }
+         if optimizations.eliminate_common_subexpressions {
+            changed |= gvn::number_values(&mut ssa, symbol_table);
+         }
+         if !changed {
+            break;
+         }
+      }
+      *instrs = ssa.into_instrs();
+   }
+
+   loop {
+      let mut changed = false;
+      if optimizations.fold_constants {
+         changed |= constant_folding::fold_constants(instrs);
+      }
+      let mut cfg = Cfg::new(std::mem::take(instrs));
+      if optimizations.eliminate_unreachable_code {
+         changed |= unreachable_code::eliminate_unreachable_code(&mut cfg);
+      }
+      if optimizations.propagate_copies {
+         changed |= copy_propagation::propagate_copies(&mut cfg, symbol_table);
+      }
+      if optimizations.eliminate_dead_stores {
+         changed |= dead_stores::eliminate_dead_stores(&mut cfg, symbol_table);
+      }
+      *instrs = cfg.into_instrs();
+      if !changed {
+         break;
+      }
+   }
+}
+
+// Static variables outlive the function, so any call may read or write them
+fn is_static(name: &str, symbol_table: &SymbolTable) -> bool {
+   symbol_table.get(name).is_some_and(|entry| matches!(entry.attrs, Attrs::StaticAttr { .. }))
+}
+
+// The values an instruction reads
+fn sources(instr: &Instr) -> Vec<&Val> {
+   match instr {
+      Instr::Return(val) | Instr::JumpIfZero(val, _) | Instr::JumpIfNotZero(val, _) | Instr::Copy(val, _) | Instr::Unary(_, val, _) => vec![val],
+      Instr::Binary(_, left, right, _) => vec![left, right],
+      Instr::FuncCall(_, args, _) => args.iter().collect(),
+      Instr::Jump(_) | Instr::Label(_) => vec![],
+   }
+}
+
+fn sources_mut(instr: &mut Instr) -> Vec<&mut Val> {
+   match instr {
+      Instr::Return(val) | Instr::JumpIfZero(val, _) | Instr::JumpIfNotZero(val, _) | Instr::Copy(val, _) | Instr::Unary(_, val, _) => vec![val],
+      Instr::Binary(_, left, right, _) => vec![left, right],
+      Instr::FuncCall(_, args, _) => args.iter_mut().collect(),
+      Instr::Jump(_) | Instr::Label(_) => vec![],
+   }
+}
+
+// The variable an instruction writes, besides the operand of an increment or
+// decrement
+fn dest(instr: &Instr) -> Option<&Val> {
+   match instr {
+      Instr::Copy(_, dest) | Instr::Unary(_, _, dest) | Instr::Binary(_, _, _, dest) | Instr::FuncCall(_, _, dest) => Some(dest),
+      _ => None,
+   }
+}
+
+fn dest_mut(instr: &mut Instr) -> Option<&mut Val> {
+   match instr {
+      Instr::Copy(_, dest) | Instr::Unary(_, _, dest) | Instr::Binary(_, _, _, dest) | Instr::FuncCall(_, _, dest) => Some(dest),
+      _ => None,
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{int, symbol_table, var, Machine};
+   use crate::tacky::tacky::BinaryOp;
+
+   // x = 1; if (x) y = 2 * 3; else y = 4; return y;
+   fn branch_on_constant() -> Vec<Instr> {
+      vec![
+         Instr::Copy(int(1), var("x")),
+         Instr::JumpIfZero(var("x"), "else".to_string()),
+         Instr::Binary(BinaryOp::Multiply, int(2), int(3), var("y")),
+         Instr::Jump("end".to_string()),
+         Instr::Label("else".to_string()),
+         Instr::Copy(int(4), var("y")),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("y")),
+      ]
+   }
+
+   fn optimized(optimizations: Optimizations) -> Vec<Instr> {
+      let mut instrs = branch_on_constant();
+      optimize_function(&mut instrs, optimizations, &symbol_table(&[]), &HashMap::new());
+      assert_eq!(Machine::new(&[], &[]).run(&instrs, &[]), 6);
+      instrs
+   }
+
+   #[test]
+   fn levels_enable_passes() {
+      assert!(!Optimizations::level(0).any());
+      let o1 = Optimizations::level(1);
+      assert!(o1.fold_constants && o1.propagate_copies && o1.eliminate_unreachable_code && o1.eliminate_dead_stores);
+      assert!(!o1.propagate_constants && !o1.eliminate_common_subexpressions && !o1.inline_functions);
+      let o2 = Optimizations::level(2);
+      assert!(o2.fold_constants && o2.propagate_constants && o2.eliminate_common_subexpressions && o2.inline_functions);
+   }
+
+   #[test]
+   fn runs_the_passes_to_a_fixed_point() {
+      assert_eq!(optimized(Optimizations::level(0)).len(), branch_on_constant().len());
+      // Propagating x lets the condition fold, which makes the else branch
+      // unreachable, which leaves y with a single value to propagate
+      assert!(matches!(optimized(Optimizations::level(1)).as_slice(), [Instr::Return(Val::Integer(6))]));
+   }
+
+   #[test]
+   fn runs_only_the_passes_asked_for() {
+      let fold_only = Optimizations { fold_constants: true, ..Optimizations::default() };
+      let instrs = optimized(fold_only);
+      assert!(instrs.iter().any(|instr| matches!(instr, Instr::Copy(Val::Integer(6), _))));
+      assert!(instrs.iter().any(|instr| matches!(instr, Instr::JumpIfZero(..))));
+   }
+}
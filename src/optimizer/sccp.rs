@@ -0,0 +1,319 @@
+// Sparse conditional constant propagation over SSA form. Every version starts
+// out unknown and blocks are only evaluated once a branch that can be taken
+// reaches them, so a constant condition keeps the definitions on its untaken
+// side from ever reaching a phi. The versions found to be constant are then
+// replaced by their value and the branches that can't be taken are removed.
+
+use super::cfg::Node;
+use super::constant_folding::{fold_binary, fold_unary};
+use super::ssa::Ssa;
+use super::{dest, is_static, sources_mut};
+use crate::tacky::tacky::*;
+use crate::validator::symbol_table::{Attrs, InitialValue, SymbolTable};
+
+use std::collections::{HashMap, HashSet};
+
+#[derive(Copy, Clone, PartialEq)]
+enum Value {
+   // Not defined by any instruction evaluated so far
+   Unknown,
+   Constant(i64),
+   // Takes more than one value
+   Varying,
+}
+
+impl Value {
+   fn meet(self, other: Value) -> Value {
+      match (self, other) {
+         (Value::Unknown, value) | (value, Value::Unknown) => value,
+         (Value::Constant(a), Value::Constant(b)) if a == b => self,
+         _ => Value::Varying,
+      }
+   }
+}
+
+// The static variables nothing in the translation unit writes, which keep their
+// initial value for the whole run. Those with external linkage may be written
+// by other translation units.
+pub fn constant_statics(tacky: &TackyIR) -> HashMap<String, i64> {
+   let mut written = HashSet::new();
+   for top_level in &tacky.program.top_level {
+      let TopLevel::Function(func) = top_level else {
+         continue;
+      };
+      for instr in &func.instrs {
+         if let Some(Val::Var(name)) = dest(instr) {
+            written.insert(name);
+         }
+         if let Instr::Unary(UnaryOp::PreIncrement | UnaryOp::PreDecrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement, Val::Var(name), _) = instr {
+            written.insert(name);
+         }
+      }
+   }
+   tacky.symbol_table.iter().filter(|(name, _)| !written.contains(name)).filter_map(|(name, entry)| match entry.attrs {
+      Attrs::StaticAttr { initial_value: InitialValue::Initialized(value), global: false } => Some((name.clone(), value)),
+      Attrs::StaticAttr { initial_value: InitialValue::Tentative, global: false } => Some((name.clone(), 0)),
+      _ => None,
+   }).collect()
+}
+
+// Returns whether anything was replaced or removed
+pub fn propagate_constants(ssa: &mut Ssa, constants: &HashMap<String, i64>, symbol_table: &SymbolTable) -> bool {
+   let mut lattice = Lattice { values: HashMap::new(), constants };
+   for (block, phis) in ssa.cfg.blocks.iter().zip(&ssa.phis) {
+      for phi in phis {
+         lattice.values.insert(phi.dest.clone(), Value::Unknown);
+      }
+      for instr in &block.instrs {
+         if let Some(Val::Var(name)) = dest(instr) && !is_static(name, symbol_table) {
+            lattice.values.insert(name.clone(), Value::Unknown);
+         }
+      }
+   }
+   let executable = lattice.solve(ssa);
+   lattice.rewrite(ssa, &executable)
+}
+
+struct Lattice<'a> {
+   values: HashMap<String, Value>,
+   constants: &'a HashMap<String, i64>,
+}
+
+impl Lattice<'_> {
+   // Evaluates the executable blocks until neither the values nor the edges
+   // that can be taken change, returning which blocks can run
+   fn solve(&mut self, ssa: &Ssa) -> Vec<bool> {
+      let labels: HashMap<&String, usize> = ssa.cfg.blocks.iter().enumerate().filter_map(|(i, block)| match block.instrs.first() {
+         Some(Instr::Label(label)) => Some((label, i)),
+         _ => None,
+      }).collect();
+      let mut executable = vec![false; ssa.cfg.blocks.len()];
+      if let Some(first) = executable.first_mut() {
+         *first = true;
+      }
+      let mut edges = HashSet::new();
+
+      let mut changed = true;
+      while changed {
+         changed = false;
+         for (i, block) in ssa.cfg.blocks.iter().enumerate() {
+            if !executable[i] {
+               continue;
+            }
+            for phi in &ssa.phis[i] {
+               let value = phi.args.iter().filter(|(predecessor, _)| match predecessor {
+                  Node::Block(p) => edges.contains(&(*p, i)),
+                  _ => true,
+               }).fold(Value::Unknown, |value, (_, arg)| value.meet(self.value(arg)));
+               changed |= self.update(&phi.dest, value);
+            }
+            for instr in &block.instrs {
+               if let Some(Val::Var(name)) = dest(instr) && self.values.contains_key(name) {
+                  let value = self.evaluate(instr);
+                  changed |= self.update(name, value);
+               }
+            }
+
+            let fall_through = if i + 1 < executable.len() { Node::Block(i + 1) } else { Node::Exit };
+            let taken: Vec<Node> = match block.instrs.last() {
+               Some(Instr::JumpIfZero(condition, label) | Instr::JumpIfNotZero(condition, label)) => {
+                  let jumps_if_zero = matches!(block.instrs.last(), Some(Instr::JumpIfZero(..)));
+                  match self.value(condition) {
+                     Value::Unknown => vec![],
+                     Value::Constant(value) if (value as i32 == 0) == jumps_if_zero => vec![Node::Block(labels[label])],
+                     Value::Constant(_) => vec![fall_through],
+                     Value::Varying => block.successors.clone(),
+                  }
+               },
+               _ => block.successors.clone(),
+            };
+            for successor in taken {
+               if let Node::Block(j) = successor && edges.insert((i, j)) {
+                  executable[j] = true;
+                  changed = true;
+               }
+            }
+         }
+      }
+      executable
+   }
+
+   fn value(&self, val: &Val) -> Value {
+      match val {
+         Val::Integer(value) => Value::Constant(*value),
+         Val::Var(name) => match self.values.get(name) {
+            Some(value) => *value,
+            None => self.constants.get(name).map_or(Value::Varying, |value| Value::Constant(*value)),
+         },
+      }
+   }
+
+   fn evaluate(&self, instr: &Instr) -> Value {
+      let fold = |value: Option<i64>| value.map_or(Value::Varying, Value::Constant);
+      match instr {
+         Instr::Copy(src, _) => self.value(src),
+         Instr::Unary(op, src, _) => match self.value(src) {
+            Value::Constant(src) => fold(fold_unary(op, src)),
+            value => value,
+         },
+         Instr::Binary(op, left, right, _) => match (self.value(left), self.value(right)) {
+            (Value::Constant(left), Value::Constant(right)) => fold(fold_binary(op, left, right)),
+            (Value::Varying, _) | (_, Value::Varying) => Value::Varying,
+            _ => Value::Unknown,
+         },
+         _ => Value::Varying,
+      }
+   }
+
+   // Lowers the value of a version, returning whether it changed
+   fn update(&mut self, name: &str, value: Value) -> bool {
+      let old = self.values[name];
+      let new = old.meet(value);
+      self.values.insert(name.to_string(), new);
+      new != old
+   }
+
+   fn constant(&self, val: &Val) -> Option<i64> {
+      match self.value(val) {
+         Value::Constant(value) if matches!(val, Val::Var(_)) => Some(value),
+         _ => None,
+      }
+   }
+
+   fn rewrite(&self, ssa: &mut Ssa, executable: &[bool]) -> bool {
+      let mut changed = false;
+      for (block, phis) in ssa.cfg.blocks.iter_mut().zip(&mut ssa.phis) {
+         // Every use of a constant version gets its value, so its definition is
+         // no longer needed
+         let before = phis.len() + block.instrs.len();
+         phis.retain(|phi| !matches!(self.values[&phi.dest], Value::Constant(_)));
+         block.instrs.retain(|instr| match dest(instr) {
+            Some(dest) if !matches!(instr, Instr::FuncCall(..)) => self.constant(dest).is_none(),
+            _ => true,
+         });
+         changed |= phis.len() + block.instrs.len() != before;
+
+         for phi in phis {
+            for (_, arg) in &mut phi.args {
+               if let Some(value) = self.constant(arg) {
+                  *arg = Val::Integer(value);
+                  changed = true;
+               }
+            }
+         }
+         for instr in &mut block.instrs {
+            for val in sources_mut(instr) {
+               if let Some(value) = self.constant(val) {
+                  *val = Val::Integer(value);
+                  changed = true;
+               }
+            }
+         }
+
+         let branch = match block.instrs.last() {
+            Some(Instr::JumpIfZero(Val::Integer(value), label)) => Some((*value as i32 == 0).then(|| Instr::Jump(label.clone()))),
+            Some(Instr::JumpIfNotZero(Val::Integer(value), label)) => Some((*value as i32 != 0).then(|| Instr::Jump(label.clone()))),
+            _ => None,
+         };
+         if let Some(branch) = branch {
+            block.instrs.pop();
+            block.instrs.extend(branch);
+            changed = true;
+         }
+      }
+
+      if executable.iter().all(|executable| *executable) {
+         ssa.update_edges();
+      } else {
+         ssa.retain_blocks(|i| executable[i]);
+         changed = true;
+      }
+      changed
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{function, int, symbol_table, var, Machine};
+
+   // Runs the pass over `instrs`, checking that the function still returns
+   // `expected`
+   fn propagate(instrs: Vec<Instr>, constants: &HashMap<String, i64>, symbol_table: &SymbolTable, expected: i64) -> Vec<Instr> {
+      let mut ssa = Ssa::new(instrs, symbol_table);
+      propagate_constants(&mut ssa, constants, symbol_table);
+      let instrs = ssa.into_instrs();
+      let statics: Vec<(&str, i64)> = constants.iter().map(|(name, value)| (name.as_str(), *value)).collect();
+      assert_eq!(Machine::new(&[], &statics).run(&instrs, &[]), expected);
+      instrs
+   }
+
+   #[test]
+   fn constant_branch_folds_through_phi() {
+      // Only the then branch can run, so x is 10 where the branches meet
+      let instrs = vec![
+         Instr::Copy(int(1), var("c")),
+         Instr::JumpIfZero(var("c"), "else".to_string()),
+         Instr::Copy(int(10), var("x")),
+         Instr::Jump("end".to_string()),
+         Instr::Label("else".to_string()),
+         Instr::Copy(int(20), var("x")),
+         Instr::Label("end".to_string()),
+         Instr::Binary(BinaryOp::Add, var("x"), int(1), var("y")),
+         Instr::Return(var("y")),
+      ];
+      let instrs = propagate(instrs, &HashMap::new(), &symbol_table(&[]), 11);
+      assert!(instrs.iter().any(|instr| matches!(instr, Instr::Return(Val::Integer(11)))));
+      assert!(!instrs.iter().any(|instr| matches!(instr, Instr::JumpIfZero(..) | Instr::Copy(Val::Integer(20), _))));
+   }
+
+   #[test]
+   fn loop_carried_value_is_not_constant() {
+      // i is 0 on the way in, but the back edge makes it vary
+      let instrs = vec![
+         Instr::Copy(int(0), var("i")),
+         Instr::Copy(int(3), var("step")),
+         Instr::Label("loop".to_string()),
+         Instr::Binary(BinaryOp::LessThan, var("i"), int(10), var("t")),
+         Instr::JumpIfZero(var("t"), "end".to_string()),
+         Instr::Binary(BinaryOp::Add, var("i"), var("step"), var("i")),
+         Instr::Jump("loop".to_string()),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("i")),
+      ];
+      let instrs = propagate(instrs, &HashMap::new(), &symbol_table(&[]), 12);
+      assert!(instrs.iter().any(|instr| matches!(instr, Instr::JumpIfZero(Val::Var(_), _))));
+      assert!(instrs.iter().any(|instr| matches!(instr, Instr::Return(Val::Var(_)))));
+      // The step is the same on every trip
+      assert!(instrs.iter().any(|instr| matches!(instr, Instr::Binary(BinaryOp::Add, Val::Var(_), Val::Integer(3), _))));
+   }
+
+   #[test]
+   fn only_unwritten_statics_are_constant() {
+      let mut table = symbol_table(&["written", "incremented", "never", "tentative", "exported"]);
+      table.get_mut("never").unwrap().attrs = Attrs::StaticAttr { initial_value: InitialValue::Initialized(7), global: false };
+      // Another translation unit may write a variable with external linkage
+      table.get_mut("exported").unwrap().attrs = Attrs::StaticAttr { initial_value: InitialValue::Initialized(1), global: true };
+      let writer = function("writer", &[], vec![
+         Instr::Copy(int(5), var("written")),
+         Instr::Unary(UnaryOp::PostIncrement, var("incremented"), var("old")),
+         Instr::Return(int(0)),
+      ]);
+      let reader = function("reader", &[], vec![
+         Instr::Binary(BinaryOp::Add, var("never"), var("written"), var("sum")),
+         Instr::Return(var("sum")),
+      ]);
+      let tacky = TackyIR {
+         program: TackyProgram { top_level: vec![TopLevel::Function(writer), TopLevel::Function(reader.clone())] },
+         symbol_table: table,
+      };
+      let constants = constant_statics(&tacky);
+      assert_eq!(constants, HashMap::from([("never".to_string(), 7), ("tentative".to_string(), 0)]));
+
+      let mut ssa = Ssa::new(reader.instrs, &tacky.symbol_table);
+      propagate_constants(&mut ssa, &constants, &tacky.symbol_table);
+      let instrs = ssa.into_instrs();
+      assert!(matches!(&instrs[0], Instr::Binary(BinaryOp::Add, Val::Integer(7), Val::Var(name), _) if name == "written"));
+      assert_eq!(Machine::new(&[], &[("written", 5)]).run(&instrs, &[]), 12);
+   }
+}
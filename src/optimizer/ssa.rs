@@ -0,0 +1,456 @@
+// Static single assignment form of a TACKY function, where every variable is
+// written by exactly one instruction. Where control flow merges, a phi picks the
+// version of a variable that comes in from each predecessor.
+//
+// Static variables stay as they are, since calls can read and write them behind
+// the function's back.
+
+use super::cfg::{BasicBlock, Cfg, Node};
+use super::dominators::Dominators;
+use super::{dest, dest_mut, is_static, sources, sources_mut};
+use crate::name_generator;
+use crate::tacky::tacky::*;
+use crate::validator::symbol_table::SymbolTable;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+pub struct Phi {
+   // The variable whose versions this merges
+   pub var: String,
+   pub dest: String,
+   // The version that comes in from each predecessor
+   pub args: Vec<(Node, Val)>,
+}
+
+pub struct Ssa {
+   pub cfg: Cfg,
+   // The phis at the start of each block
+   pub phis: Vec<Vec<Phi>>,
+}
+
+impl Ssa {
+   pub fn new(instrs: Vec<Instr>, symbol_table: &SymbolTable) -> Self {
+      let mut cfg = Cfg::new(instrs);
+      let reachable: HashSet<usize> = cfg.reverse_postorder().into_iter().collect();
+      cfg.retain_blocks(|i, _| reachable.contains(&i));
+      // The first block holds the initial version of every variable, so it can't
+      // be the target of a jump
+      if cfg.predecessors(Node::Block(0)).len() > 1 {
+         cfg.blocks.insert(0, BasicBlock::new(Vec::new()));
+         cfg.update_edges();
+      }
+      for block in &mut cfg.blocks {
+         block.instrs = std::mem::take(&mut block.instrs).into_iter().flat_map(|instr| lower_increment(instr, symbol_table)).collect();
+      }
+
+      let dominators = Dominators::new(&cfg);
+      let phis = place_phis(&cfg, &dominators, symbol_table);
+      let mut ssa = Ssa { cfg, phis };
+      let mut renamer = Renamer { stacks: HashMap::new(), symbol_table };
+      renamer.rename(0, &mut ssa, &dominators);
+      ssa
+   }
+
+   // Recomputes the edges after a pass has changed some jumps, dropping the phi
+   // arguments from blocks that no longer lead to the phi
+   pub fn update_edges(&mut self) {
+      self.cfg.update_edges();
+      self.prune_phis();
+   }
+
+   // Drops the blocks for which `keep` returns false, renumbering the rest
+   pub fn retain_blocks(&mut self, mut keep: impl FnMut(usize) -> bool) {
+      let mut numbers = Vec::with_capacity(self.cfg.blocks.len());
+      let mut count = 0;
+      for i in 0..self.cfg.blocks.len() {
+         numbers.push(keep(i).then(|| {
+            count += 1;
+            count - 1
+         }));
+      }
+      let phis = std::mem::take(&mut self.phis);
+      self.phis = phis.into_iter().enumerate().filter(|(i, _)| numbers[*i].is_some()).map(|(_, mut block_phis)| {
+         for phi in &mut block_phis {
+            phi.args = std::mem::take(&mut phi.args).into_iter().filter_map(|(predecessor, arg)| match predecessor {
+               Node::Block(p) => numbers[p].map(|p| (Node::Block(p), arg)),
+               _ => Some((predecessor, arg)),
+            }).collect();
+         }
+         block_phis
+      }).collect();
+      self.cfg.retain_blocks(|i, _| numbers[i].is_some());
+      self.prune_phis();
+   }
+
+   fn prune_phis(&mut self) {
+      for (block, block_phis) in self.cfg.blocks.iter().zip(&mut self.phis) {
+         for phi in block_phis {
+            phi.args.retain(|(predecessor, _)| block.predecessors.contains(predecessor));
+         }
+      }
+   }
+
+   // Replaces each phi with copies on the edges into its block. An edge out of a
+   // block that ends in a conditional jump is split with a block of its own,
+   // since copies before the jump would also run on the other path.
+   pub fn into_instrs(self) -> Vec<Instr> {
+      let Ssa { cfg, phis } = self;
+      let mut blocks: Vec<Vec<Instr>> = cfg.blocks.into_iter().map(|block| block.instrs).collect();
+
+      // The parallel copies on each edge, in a fixed order
+      let mut edges: BTreeMap<(usize, usize), Vec<(Val, String)>> = BTreeMap::new();
+      for (i, block_phis) in phis.into_iter().enumerate() {
+         for phi in block_phis {
+            for (predecessor, arg) in phi.args {
+               if let Node::Block(p) = predecessor {
+                  edges.entry((p, i)).or_default().push((arg, phi.dest.clone()));
+               }
+            }
+         }
+      }
+
+      // Blocks for split edges, placed right after the predecessor when the edge
+      // falls through and at the end of the function otherwise
+      let mut after: HashMap<usize, Vec<Instr>> = HashMap::new();
+      let mut appended = Vec::new();
+      for ((p, s), copies) in edges {
+         let copies = sequentialize(copies);
+         let label = match blocks[s].first() {
+            Some(Instr::Label(label)) => Some(label.clone()),
+            _ => None,
+         };
+         match blocks[p].last_mut() {
+            Some(Instr::JumpIfZero(_, target) | Instr::JumpIfNotZero(_, target)) => {
+               let jumps = label.as_ref() == Some(target);
+               let falls_through = s == p + 1;
+               let mut split = Vec::new();
+               if jumps {
+                  let edge = name_generator::gen_label("edge");
+                  *target = edge.clone();
+                  split.push(Instr::Label(edge));
+               }
+               split.extend(copies);
+               if falls_through {
+                  after.insert(p, split);
+               } else {
+                  split.push(Instr::Jump(label.unwrap()));
+                  appended.push(split);
+               }
+            },
+            Some(Instr::Jump(_)) => {
+               let jump = blocks[p].pop().unwrap();
+               blocks[p].extend(copies);
+               blocks[p].push(jump);
+            },
+            _ => blocks[p].extend(copies),
+         }
+      }
+
+      let mut instrs = Vec::new();
+      for (i, block) in blocks.into_iter().enumerate() {
+         instrs.extend(block);
+         instrs.extend(after.remove(&i).unwrap_or_default());
+      }
+      instrs.extend(appended.into_iter().flatten());
+      instrs
+   }
+}
+
+// Splits an increment or decrement of a variable that will be renamed into an
+// addition and a copy, since it both reads and writes its operand
+fn lower_increment(instr: Instr, symbol_table: &SymbolTable) -> Vec<Instr> {
+   let (op, var, dest) = match instr {
+      Instr::Unary(op @ (UnaryOp::PreIncrement | UnaryOp::PreDecrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement), Val::Var(var), dest)
+         if !is_static(&var, symbol_table) => (op, var, dest),
+      _ => return vec![instr],
+   };
+   let binary_op = match op {
+      UnaryOp::PreIncrement | UnaryOp::PostIncrement => BinaryOp::Add,
+      _ => BinaryOp::Subtract,
+   };
+   let update = Instr::Binary(binary_op, Val::Var(var.clone()), Val::Integer(1), Val::Var(var.clone()));
+   let result = Instr::Copy(Val::Var(var), dest);
+   match op {
+      UnaryOp::PreIncrement | UnaryOp::PreDecrement => vec![update, result],
+      _ => vec![result, update],
+   }
+}
+
+// Inserts an empty phi for a variable wherever two of its definitions meet,
+// which is the iterated dominance frontier of the blocks that write it. Only
+// variables read in a different block from where they were written need them.
+fn place_phis(cfg: &Cfg, dominators: &Dominators, symbol_table: &SymbolTable) -> Vec<Vec<Phi>> {
+   let mut live_across = BTreeSet::new();
+   let mut definitions: HashMap<String, Vec<usize>> = HashMap::new();
+   for (i, block) in cfg.blocks.iter().enumerate() {
+      let mut defined = HashSet::new();
+      for instr in &block.instrs {
+         for val in sources(instr) {
+            if let Val::Var(name) = val && !defined.contains(name) && !is_static(name, symbol_table) {
+               live_across.insert(name.clone());
+            }
+         }
+         if let Some(Val::Var(name)) = dest(instr) && !is_static(name, symbol_table) {
+            defined.insert(name.clone());
+            definitions.entry(name.clone()).or_default().push(i);
+         }
+      }
+   }
+
+   let frontiers = dominators.frontiers(cfg);
+   let mut phis: Vec<Vec<Phi>> = cfg.blocks.iter().map(|_| Vec::new()).collect();
+   for var in live_across {
+      // Every variable starts out with its initial version in the first block
+      let mut worklist = definitions.remove(&var).unwrap_or_default();
+      worklist.push(0);
+      let mut visited: HashSet<usize> = worklist.iter().copied().collect();
+      let mut has_phi = HashSet::new();
+      while let Some(i) = worklist.pop() {
+         for &j in &frontiers[i] {
+            if has_phi.insert(j) {
+               phis[j].push(Phi { var: var.clone(), dest: var.clone(), args: Vec::new() });
+               if visited.insert(j) {
+                  worklist.push(j);
+               }
+            }
+         }
+      }
+   }
+   phis
+}
+
+// Gives every write a new version of its variable, walking the dominator tree
+// so that the version on top of each stack is the one that reaches a block
+struct Renamer<'a> {
+   stacks: HashMap<String, Vec<String>>,
+   symbol_table: &'a SymbolTable,
+}
+
+impl Renamer<'_> {
+   fn rename(&mut self, i: usize, ssa: &mut Ssa, dominators: &Dominators) {
+      let mut pushed = Vec::new();
+      for phi in &mut ssa.phis[i] {
+         phi.dest = self.push(&phi.var);
+         pushed.push(phi.var.clone());
+      }
+      for instr in &mut ssa.cfg.blocks[i].instrs {
+         for val in sources_mut(instr) {
+            if let Val::Var(name) = val && let Some(version) = self.current(name) {
+               *name = version;
+            }
+         }
+         if let Some(Val::Var(name)) = dest_mut(instr) && !is_static(name, self.symbol_table) {
+            pushed.push(name.clone());
+            *name = self.push(name);
+         }
+      }
+
+      for successor in ssa.cfg.blocks[i].successors.clone() {
+         let Node::Block(j) = successor else {
+            continue;
+         };
+         for phi in &mut ssa.phis[j] {
+            let version = self.current(&phi.var).unwrap_or_else(|| phi.var.clone());
+            phi.args.push((Node::Block(i), Val::Var(version)));
+         }
+      }
+
+      for &child in &dominators.children[i] {
+         self.rename(child, ssa, dominators);
+      }
+      for var in pushed {
+         self.stacks.get_mut(&var).unwrap().pop();
+      }
+   }
+
+   // Makes a new version of `var` current, named after the source variable
+   fn push(&mut self, var: &str) -> String {
+      let base = var.split('.').next().unwrap().to_string();
+      let version = name_generator::uniquify_identifier(&base);
+      self.stacks.entry(var.to_string()).or_default().push(version.clone());
+      version
+   }
+
+   // Reads before any write see the variable itself
+   fn current(&self, var: &str) -> Option<String> {
+      self.stacks.get(var).and_then(|stack| stack.last()).cloned()
+   }
+}
+
+// Orders the parallel copies on an edge so that no copy overwrites a source
+// another one still has to read, saving a value in a temporary where the
+// copies form a cycle
+fn sequentialize(copies: Vec<(Val, String)>) -> Vec<Instr> {
+   let mut pending: Vec<(Val, String)> = copies.into_iter().filter(|(src, dest)| !matches!(src, Val::Var(name) if name == dest)).collect();
+   let mut instrs = Vec::new();
+   while !pending.is_empty() {
+      let ready = pending.iter().position(|(_, dest)| !pending.iter().any(|(src, _)| matches!(src, Val::Var(name) if name == dest)));
+      match ready {
+         Some(k) => {
+            let (src, dest) = pending.remove(k);
+            instrs.push(Instr::Copy(src, Val::Var(dest)));
+         },
+         None => {
+            let dest = pending[0].1.clone();
+            let tmp = name_generator::gen_tmp_name();
+            instrs.push(Instr::Copy(Val::Var(dest.clone()), Val::Var(tmp.clone())));
+            for (src, _) in &mut pending {
+               if matches!(src, Val::Var(name) if *name == dest) {
+                  *src = Val::Var(tmp.clone());
+               }
+            }
+         },
+      }
+   }
+   instrs
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{int, symbol_table, var, Machine};
+
+   // Converts `instrs` into SSA form, checks that every variable is written
+   // once, and converts back, checking that the function still returns the
+   // same for each of `inputs`
+   fn round_trip(instrs: Vec<Instr>, inputs: &[&[(&str, i64)]]) -> Vec<Instr> {
+      let ssa = Ssa::new(instrs.clone(), &symbol_table(&[]));
+      assert_single_assignment(&ssa);
+      let converted = ssa.into_instrs();
+      for args in inputs {
+         let mut machine = Machine::new(&[], &[]);
+         assert_eq!(machine.run(&converted, args), machine.run(&instrs, args), "with {:?}", args);
+      }
+      converted
+   }
+
+   fn assert_single_assignment(ssa: &Ssa) {
+      let mut written = HashSet::new();
+      for phi in ssa.phis.iter().flatten() {
+         assert!(written.insert(phi.dest.clone()), "{} written twice", phi.dest);
+      }
+      for instr in ssa.cfg.blocks.iter().flat_map(|block| &block.instrs) {
+         if let Some(Val::Var(name)) = dest(instr) {
+            assert!(written.insert(name.clone()), "{} written twice", name);
+         }
+      }
+   }
+
+   fn phi_vars(ssa: &Ssa, block: usize) -> Vec<&str> {
+      let mut vars: Vec<&str> = ssa.phis[block].iter().map(|phi| phi.var.as_str()).collect();
+      vars.sort();
+      vars
+   }
+
+   #[test]
+   fn diamond() {
+      let instrs = vec![
+         Instr::JumpIfZero(var("c"), "else".to_string()),
+         Instr::Copy(int(1), var("x")),
+         Instr::Jump("end".to_string()),
+         Instr::Label("else".to_string()),
+         Instr::Copy(int(2), var("x")),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("x")),
+      ];
+      let cfg = Cfg::new(instrs.clone());
+      let dominators = Dominators::new(&cfg);
+      assert_eq!(dominators.idom, vec![None, Some(0), Some(0), Some(0)]);
+      assert!(dominators.dominates(0, 3));
+      assert!(!dominators.dominates(1, 3));
+      let frontiers = dominators.frontiers(&cfg);
+      assert_eq!(frontiers, vec![HashSet::new(), HashSet::from([3]), HashSet::from([3]), HashSet::new()]);
+
+      let ssa = Ssa::new(instrs.clone(), &symbol_table(&[]));
+      assert_eq!(phi_vars(&ssa, 3), vec!["x"]);
+      assert_eq!(ssa.phis[3][0].args.len(), 2);
+      assert!(ssa.phis[..3].iter().all(Vec::is_empty));
+      round_trip(instrs, &[&[("c", 0)], &[("c", 1)]]);
+   }
+
+   #[test]
+   fn loop_carried_variables() {
+      let instrs = vec![
+         Instr::Copy(int(0), var("i")),
+         Instr::Copy(int(0), var("s")),
+         Instr::Label("loop".to_string()),
+         Instr::Binary(BinaryOp::LessThan, var("i"), var("n"), var("t")),
+         Instr::JumpIfZero(var("t"), "end".to_string()),
+         Instr::Binary(BinaryOp::Add, var("s"), var("i"), var("s")),
+         Instr::Unary(UnaryOp::PostIncrement, var("i"), var("old")),
+         Instr::Jump("loop".to_string()),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("s")),
+      ];
+      let cfg = Cfg::new(instrs.clone());
+      let dominators = Dominators::new(&cfg);
+      assert_eq!(dominators.idom, vec![None, Some(0), Some(1), Some(1)]);
+      let frontiers = dominators.frontiers(&cfg);
+      assert_eq!(frontiers[1], HashSet::from([1]));
+      assert_eq!(frontiers[2], HashSet::from([1]));
+
+      // t is only read in the block that writes it, so it needs no phi
+      let ssa = Ssa::new(instrs.clone(), &symbol_table(&[]));
+      assert_eq!(phi_vars(&ssa, 1), vec!["i", "s"]);
+      round_trip(instrs, &[&[("n", 0)], &[("n", 1)], &[("n", 5)]]);
+   }
+
+   #[test]
+   fn swap_needs_a_temporary() {
+      // a and b trade places on every trip around the loop, so the copies on
+      // the back edge form a cycle
+      let instrs = vec![
+         Instr::Copy(var("n"), var("m.0")),
+         Instr::Label("loop".to_string()),
+         Instr::JumpIfZero(var("m.1"), "end".to_string()),
+         Instr::Binary(BinaryOp::Subtract, var("m.1"), int(1), var("m.2")),
+         Instr::Jump("loop".to_string()),
+         Instr::Label("end".to_string()),
+         Instr::Binary(BinaryOp::Multiply, var("a.1"), int(10), var("r.0")),
+         Instr::Binary(BinaryOp::Add, var("r.0"), var("b.1"), var("r.1")),
+         Instr::Return(var("r.1")),
+      ];
+      let phi = |name: &str, dest: &str, entry: &str, back: &str| Phi {
+         var: name.to_string(),
+         dest: dest.to_string(),
+         args: vec![(Node::Block(0), var(entry)), (Node::Block(2), var(back))],
+      };
+      let phis = vec![
+         Vec::new(),
+         vec![phi("a", "a.1", "a", "b.1"), phi("b", "b.1", "b", "a.1"), phi("m", "m.1", "m.0", "m.2")],
+         Vec::new(),
+         Vec::new(),
+      ];
+      let ssa = Ssa { cfg: Cfg::new(instrs), phis };
+      let converted = ssa.into_instrs();
+      assert!(converted.iter().any(|instr| matches!(instr, Instr::Copy(_, Val::Var(name)) if name.starts_with("tmp."))));
+      for (n, expected) in [(0, 12), (1, 21), (2, 12), (3, 21)] {
+         let mut machine = Machine::new(&[], &[]);
+         assert_eq!(machine.run(&converted, &[("a", 1), ("b", 2), ("n", n)]), expected);
+      }
+   }
+
+   #[test]
+   fn critical_edges_are_split() {
+      // Every edge here is critical: both jumps leave blocks with two
+      // successors for blocks with two predecessors, once by jumping and once
+      // by falling through
+      let instrs = vec![
+         Instr::Copy(int(0), var("x")),
+         Instr::JumpIfZero(var("c"), "end".to_string()),
+         Instr::Label("loop".to_string()),
+         Instr::Binary(BinaryOp::Add, var("x"), int(1), var("x")),
+         Instr::Binary(BinaryOp::LessThan, var("x"), int(5), var("t")),
+         Instr::JumpIfNotZero(var("t"), "loop".to_string()),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("x")),
+      ];
+      let ssa = Ssa::new(instrs.clone(), &symbol_table(&[]));
+      assert_eq!(phi_vars(&ssa, 1), vec!["x"]);
+      assert_eq!(phi_vars(&ssa, 2), vec!["x"]);
+      let converted = round_trip(instrs, &[&[("c", 0)], &[("c", 1)]]);
+      let jumps_to_edges = converted.iter().filter(|instr| matches!(instr,
+         Instr::JumpIfZero(_, label) | Instr::JumpIfNotZero(_, label) if label.starts_with("edge."))).count();
+      assert_eq!(jumps_to_edges, 2);
+   }
+}
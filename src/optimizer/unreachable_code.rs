@@ -0,0 +1,107 @@
+// Removes the blocks control can never reach, jumps to the instruction that
+// follows them anyway and labels nothing jumps to.
+
+use super::cfg::{Cfg, Node};
+use crate::tacky::tacky::Instr;
+
+use std::collections::HashSet;
+
+// Returns whether anything was removed
+pub fn eliminate_unreachable_code(cfg: &mut Cfg) -> bool {
+   let reachable: HashSet<usize> = cfg.reverse_postorder().into_iter().collect();
+   let mut changed = reachable.len() != cfg.blocks.len();
+   cfg.retain_blocks(|i, _| reachable.contains(&i));
+
+   for i in 0..cfg.blocks.len() {
+      let block = &cfg.blocks[i];
+      let jumps = matches!(block.instrs.last(), Some(Instr::Jump(_) | Instr::JumpIfZero(..) | Instr::JumpIfNotZero(..)));
+      if jumps && block.successors.iter().all(|successor| falls_through(cfg, i, *successor)) {
+         cfg.blocks[i].instrs.pop();
+         changed = true;
+      }
+   }
+
+   let targets: HashSet<String> = cfg.blocks.iter().filter_map(|block| match block.instrs.last() {
+      Some(Instr::Jump(label) | Instr::JumpIfZero(_, label) | Instr::JumpIfNotZero(_, label)) => Some(label.clone()),
+      _ => None,
+   }).collect();
+   for block in &mut cfg.blocks {
+      if let Some(Instr::Label(label)) = block.instrs.first() && !targets.contains(label) {
+         block.instrs.remove(0);
+         changed = true;
+      }
+   }
+
+   cfg.update_edges();
+   changed
+}
+
+// Whether falling off the end of block `from` reaches `to` anyway, because
+// every block in between only holds labels
+fn falls_through(cfg: &Cfg, from: usize, to: Node) -> bool {
+   match to {
+      Node::Block(to) => to > from && cfg.blocks[from + 1..to].iter().all(|block| {
+         block.instrs.iter().all(|instr| matches!(instr, Instr::Label(_)))
+      }),
+      _ => false,
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::optimizer::interpreter::{int, var, Machine};
+   use crate::tacky::tacky::Val;
+
+   fn eliminate(instrs: Vec<Instr>, args: &[(&str, i64)], expected: i64) -> Vec<Instr> {
+      let mut cfg = Cfg::new(instrs);
+      eliminate_unreachable_code(&mut cfg);
+      let instrs = cfg.into_instrs();
+      assert_eq!(Machine::new(&[], &[]).run(&instrs, args), expected);
+      instrs
+   }
+
+   #[test]
+   fn removes_blocks_nothing_reaches() {
+      let instrs = eliminate(vec![
+         Instr::Jump("end".to_string()),
+         Instr::Copy(int(1), var("x")),
+         Instr::Label("dead".to_string()),
+         Instr::Copy(int(2), var("x")),
+         Instr::Jump("dead".to_string()),
+         Instr::Label("end".to_string()),
+         Instr::Return(int(0)),
+         Instr::Return(int(1)),
+      ], &[], 0);
+      // Only the return is left once the jump to it goes
+      assert!(matches!(instrs.as_slice(), [Instr::Return(Val::Integer(0))]));
+   }
+
+   #[test]
+   fn keeps_jumps_over_code_and_their_labels() {
+      let original = vec![
+         Instr::JumpIfZero(var("c"), "else".to_string()),
+         Instr::Copy(int(1), var("x")),
+         Instr::Jump("end".to_string()),
+         Instr::Label("else".to_string()),
+         Instr::Copy(int(2), var("x")),
+         Instr::Label("end".to_string()),
+         Instr::Return(var("x")),
+      ];
+      let instrs = eliminate(original.clone(), &[("c", 0)], 2);
+      assert_eq!(instrs.len(), original.len());
+      assert_eq!(Machine::new(&[], &[]).run(&instrs, &[("c", 1)]), 1);
+   }
+
+   #[test]
+   fn removes_jumps_to_the_next_block_and_unused_labels() {
+      let instrs = eliminate(vec![
+         Instr::Copy(int(3), var("x")),
+         Instr::JumpIfNotZero(var("x"), "next".to_string()),
+         Instr::Label("unused".to_string()),
+         Instr::Label("next".to_string()),
+         Instr::Return(var("x")),
+      ], &[], 3);
+      assert!(matches!(instrs.as_slice(), [Instr::Copy(..), Instr::Return(_)]));
+   }
+}
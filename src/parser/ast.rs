@@ -1,6 +1,8 @@
+use crate::error::Span;
 use crate::validator::symbol_table::SymbolTable;
 
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
 pub struct AST {
    pub program: Program,
@@ -17,7 +19,10 @@ pub struct FuncDecl {
    pub params: Vec<String>,
    pub body: Option<Block>,
    pub storage_class: Option<StorageClass>,
+   pub inline: bool,
    pub line_number: usize,
+   // The header the function is in, or None for the main file
+   pub file: Option<Rc<str>>,
 }
 
 #[derive(Clone)]
@@ -63,7 +68,7 @@ pub enum Stmt {
    Expression(Expr, Vec<Label>, ()),
    If(Expr, Box<Stmt>, Option<Box<Stmt>>, Vec<Label>, ()),
    Compound(Block, Vec<Label>, ()),
-   Break(String, Vec<Label>, usize),
+   Break(String, Vec<Label>, Span),
    Continue(String, Vec<Label>, usize),
    While(Expr, Box<Stmt>, Vec<Label>, usize),
    DoWhile(Box<Stmt>, Expr, Vec<Label>, usize),
@@ -73,6 +78,17 @@ pub enum Stmt {
    Null(Vec<Label>, ()),
 }
 
+impl Stmt {
+   pub fn labels(&self) -> &[Label] {
+      match self {
+         Stmt::Return(_, labels, _) | Stmt::Expression(_, labels, _) | Stmt::If(_, _, _, labels, _) |
+         Stmt::Compound(_, labels, _) | Stmt::Break(_, labels, _) | Stmt::Continue(_, labels, _) |
+         Stmt::While(_, _, labels, _) | Stmt::DoWhile(_, _, labels, _) | Stmt::For(_, _, _, _, labels, _) |
+         Stmt::Goto(_, labels, _) | Stmt::Switch(_, _, labels, _, _) | Stmt::Null(labels, _) => labels
+      }
+   }
+}
+
 #[derive(Clone)]
 pub struct SwitchInfo {
    pub cases: Vec<CaseInfo>,
@@ -125,12 +141,12 @@ impl Label {
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Expr {
    Integer(i64),
-   Var(String, usize),
+   Var(String, Span),
    UnaryOp(UnaryOp, Box<Expr>, usize),
    BinaryOp(BinaryOp, Box<Expr>, Box<Expr>),
    Assignment(Box<Expr>, Box<Expr>, usize),
    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
-   FunctionCall(String, Vec<Expr>, usize),
+   FunctionCall(String, Vec<Expr>, Span),
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -3,7 +3,7 @@ pub mod ast_printer;
 
 use crate::lexer::token::{Token, TokenType};
 use crate::validator::symbol_table::SymbolTable;
-use crate::error;
+use crate::error::{self, Code, FixIt};
 use crate::name_generator;
 
 use anyhow::{bail, Result};
@@ -172,10 +172,26 @@ fn compound_to_arithmetic(t: &TokenType) -> BinaryOp {
    }
 }
 
+// Whether `next` can't carry on the statement that ends with `previous`, so
+// that a missing ';' between them is the likely mistake. Something like the ','
+// in `int a = 5, b = 7;` needs more than a ';' to compile.
+fn starts_statement(previous: &Token, next: &Token) -> bool {
+   next.line_number > previous.line_number || matches!(next.token_type,
+      TokenType::Int | TokenType::Void | TokenType::Return | TokenType::If | TokenType::Else | TokenType::Do
+      | TokenType::While | TokenType::For | TokenType::Break | TokenType::Continue | TokenType::Static
+      | TokenType::Extern | TokenType::Inline | TokenType::Goto | TokenType::Switch | TokenType::Case
+      | TokenType::Default | TokenType::CloseBrace | TokenType::EOF)
+}
+
+fn inline_variable(decl: &VarDecl) -> error::Error {
+   error::error(decl.line_number, format!("'inline' used on variable \"{}\"", decl.name), Code::InlineVariable)
+}
+
 struct Parser {
    tokens: Vec<Option<Token>>,
    current: usize,
-   switch_context_stack: Vec<SwitchInfo>
+   switch_context_stack: Vec<SwitchInfo>,
+   errors: Vec<error::Error>,
 }
 
 pub fn parse(tokens: Vec<Option<Token>>, print_ast: bool) -> Result<AST> {
@@ -189,11 +205,13 @@ impl Parser {
          tokens,
          current: 0,
          switch_context_stack: Vec::new(),
+         errors: Vec::new(),
       }
    }
 
    pub fn parse(&mut self, print_ast: bool) -> Result<AST> {
       let program = self.program()?;
+      error::finish(std::mem::take(&mut self.errors))?;
       let ast = AST { program , symbol_table: SymbolTable::new() };
       if print_ast {
          ast_printer::print_ast(&ast);
@@ -204,13 +222,19 @@ impl Parser {
    fn program(&mut self) -> Result<Program> {
       let mut decls: Vec<Decl> = Vec::new();
       while !self.at_end() {
-         let decl = self.declaration()?;
-         decls.push(decl);
+         match self.declaration() {
+            Ok(decl) => decls.push(decl),
+            Err(e) => {
+               error::push(&mut self.errors, e)?;
+               self.synchronize_declaration();
+            }
+         }
       }
       Ok(Program{ decls })
    }
 
-   fn function_decl(&mut self, name: String, storage_class: Option<StorageClass>, line_number: usize) -> Result<FuncDecl> {
+   fn function_decl(&mut self, name: String, storage_class: Option<StorageClass>, inline: bool, line_number: usize) -> Result<FuncDecl> {
+      let file = self.peek().as_ref().unwrap().file.clone();
       self.consume(TokenType::OpenParen)?;
       let params = self.params()?;
       self.consume(TokenType::CloseParen)?;
@@ -223,7 +247,7 @@ impl Parser {
          self.consume(TokenType::CloseBrace)?;
          Some(block)
       };
-      Ok(FuncDecl{ name, params, body: block, storage_class, line_number })
+      Ok(FuncDecl{ name, params, body: block, storage_class, inline, line_number, file })
    }
 
    fn variable_decl(&mut self, name: String, storage_class: Option<StorageClass>,line_number: usize) -> Result<VarDecl> {
@@ -255,24 +279,32 @@ impl Parser {
    fn block(&mut self) -> Result<Block> {
       let mut items = Vec::new();
       while !self.at_end() && self.peek().as_ref().unwrap().token_type != TokenType::CloseBrace {
-         let block_item = self.block_item()?;
-         items.push(block_item);
+         match self.block_item() {
+            Ok(block_item) => items.push(block_item),
+            Err(e) => {
+               error::push(&mut self.errors, e)?;
+               self.synchronize();
+            }
+         }
       }
       Ok(Block{ items })
    }
 
    fn block_item(&mut self) -> Result<BlockItem> {
       match self.peek().as_ref().unwrap().token_type {
-         TokenType::Int | TokenType::Static | TokenType::Extern => {
+         TokenType::Int | TokenType::Static | TokenType::Extern | TokenType::Inline => {
             Ok(BlockItem::Decl(self.declaration()?))
          },
          _ => Ok(BlockItem::Stmt(self.statement()?))
       }
    }
 
-   fn type_and_storage_class(&mut self) -> Result<Option<StorageClass>> {
+   // Returns the storage class and whether the 'inline' function specifier was
+   // given
+   fn type_and_storage_class(&mut self) -> Result<(Option<StorageClass>, bool)> {
       let mut types = Vec::new();
       let mut storage_classes = Vec::new();
+      let mut inline = false;
       while self.peek().as_ref().unwrap().token_type != TokenType::Identifier {
          if self.match_token(TokenType::Int) {
             types.push(TokenType::Int);
@@ -280,40 +312,40 @@ impl Parser {
             storage_classes.push(StorageClass::Static);
          } else if self.match_token(TokenType::Extern) {
             storage_classes.push(StorageClass::Extern);
+         } else if self.match_token(TokenType::Inline) {
+            inline = true;
          } else {
-            let t = self.peek();
-            bail!(error::error(t.as_ref().unwrap().line_number,
-                  format!("Expected a type or storage class, found '{}'", t.as_ref().unwrap().lexeme),
-                  error::ErrorType::SyntaxError))
+            let msg = format!("Expected a type or storage class, found '{}'", self.peek().as_ref().unwrap().lexeme);
+            bail!(self.syntax_error(msg, Code::ExpectedTypeOrStorageClass))
          }
       }
       if types.len() != 1 {
-         bail!(error::error(self.peek().as_ref().unwrap().line_number,
-               format!("Invalid type specifier"),
-               error::ErrorType::SyntaxError))
+         bail!(self.syntax_error("Invalid type specifier".to_string(), Code::InvalidTypeSpecifier))
       }
       if storage_classes.len() > 1 {
-         bail!(error::error(self.peek().as_ref().unwrap().line_number,
-               format!("Invalid storage class"),
-               error::ErrorType::SyntaxError))
+         bail!(self.syntax_error("Invalid storage class".to_string(), Code::InvalidStorageClass))
       }
       let storage_class = if storage_classes.len() == 1 {
          Some(storage_classes[0])
       } else {
          None
       };
-      Ok(storage_class)
+      Ok((storage_class, inline))
    }
 
    fn declaration(&mut self) -> Result<Decl> {
-      let storage_class = self.type_and_storage_class()?;
+      let (storage_class, inline) = self.type_and_storage_class()?;
       let name = self.identifier()?;
       let line_number = self.peek().as_ref().unwrap().line_number;
       let decl =
          if self.peek().as_ref().unwrap().token_type == TokenType::OpenParen {
-            Ok(Decl::FuncDecl(self.function_decl(name, storage_class, line_number)?))
+            Ok(Decl::FuncDecl(self.function_decl(name, storage_class, inline, line_number)?))
          } else {
-            Ok(Decl::VarDecl(self.variable_decl(name, storage_class, line_number)?))
+            let decl = self.variable_decl(name, storage_class, line_number)?;
+            if inline {
+               self.errors.push(inline_variable(&decl));
+            }
+            Ok(Decl::VarDecl(decl))
          };
       return decl;
    }
@@ -322,13 +354,11 @@ impl Parser {
       match self.peek().as_ref().unwrap().token_type {
          TokenType::Identifier => {
             self.advance();
-            Ok(self.previous().take().unwrap().lexeme)
+            Ok(self.previous().as_ref().unwrap().lexeme.clone())
          },
          _ => {
-            let t = self.peek();
-            bail!(error::error(t.as_ref().unwrap().line_number,
-                  format!("Expected an identifier, found '{}'", t.as_ref().unwrap().lexeme),
-                  error::ErrorType::SyntaxError))
+            let msg = format!("Expected an identifier, found '{}'", self.peek().as_ref().unwrap().lexeme);
+            bail!(self.syntax_error(msg, Code::ExpectedIdentifier))
          }
       }
    }
@@ -357,13 +387,14 @@ impl Parser {
                self.consume(TokenType::Colon)?;
                let switch_info = self.switch_context_stack.last_mut();
                if let Some(switch_info) = switch_info {
-                  if switch_info.default.is_some() {
-                     bail!(error::error(label.line_number, format!("Multiple default labels in one switch statement"), error::ErrorType::SemanticError))
+                  if let Some(default) = &switch_info.default {
+                     bail!(error::error(label.line_number, "Multiple default labels in one switch statement".to_string(), Code::MultipleDefaults)
+                        .with_note(format!("Previous default label is on line {}", default.line_number)))
                   } else {
                      switch_info.default = Some(label.clone());
                   }
                } else {
-                  bail!(error::error(label.line_number, format!("default label outside of switch statement"), error::ErrorType::SemanticError))
+                  bail!(error::error(label.line_number, "default label outside of switch statement".to_string(), Code::DefaultOutsideSwitch))
                }
                labels.push(label);
             },
@@ -379,7 +410,7 @@ impl Parser {
                if let Some(switch_info) = switch_info {
                   switch_info.cases.push(CaseInfo { value: expr.clone(), label: label, line_number });
                } else {
-                  bail!(error::error(line_number, format!("case label outside of switch statement"), error::ErrorType::SemanticError))
+                  bail!(error::error(line_number, "case label outside of switch statement".to_string(), Code::CaseOutsideSwitch))
                }
             },
             _ => {
@@ -425,9 +456,10 @@ impl Parser {
          },
          TokenType::Break => {
             self.advance();
-            let line_number = self.previous().as_ref().unwrap().line_number;
+            let span = self.previous().as_ref().unwrap().span();
             self.consume(TokenType::Semicolon)?;
-            return Ok(Stmt::Break("".into(), labels, line_number));
+            let span = span.to(self.previous().as_ref().unwrap().span());
+            return Ok(Stmt::Break("".into(), labels, span));
          },
          TokenType::Continue => {
             self.advance();
@@ -482,8 +514,9 @@ impl Parser {
             self.consume(TokenType::CloseParen)?;
             let switch_info = SwitchInfo { cases: Vec::new(), end_label: name_generator::gen_label("switch_end"), default: None };
             self.switch_context_stack.push(switch_info);
-            let stmt = self.statement()?;
+            let stmt = self.statement();
             let switch_info = self.switch_context_stack.pop().unwrap();
+            let stmt = stmt?;
             Ok(Stmt::Switch(expr, Box::new(stmt), labels, switch_info, ()))
          },
          _ => {
@@ -497,11 +530,15 @@ impl Parser {
    fn for_init(&mut self) -> Result<Option<ForInit>> {
       if !self.match_token(TokenType::Semicolon) {
          let next_token_type = &self.peek().as_ref().unwrap().token_type;
-         if *next_token_type == TokenType::Int || *next_token_type == TokenType::Static || *next_token_type == TokenType::Extern {
-            let storage_class = self.type_and_storage_class()?;
+         if *next_token_type == TokenType::Int || *next_token_type == TokenType::Static || *next_token_type == TokenType::Extern || *next_token_type == TokenType::Inline {
+            let (storage_class, inline) = self.type_and_storage_class()?;
             let name = self.identifier()?;
             let line_number = self.peek().as_ref().unwrap().line_number;
-            Ok(Some(ForInit::Decl(self.variable_decl(name, storage_class, line_number)?)))
+            let decl = self.variable_decl(name, storage_class, line_number)?;
+            if inline {
+               self.errors.push(inline_variable(&decl));
+            }
+            Ok(Some(ForInit::Decl(decl)))
          } else {
             let init = Some(ForInit::Expr(self.expression(Precedence::None)?));
             self.consume(TokenType::Semicolon)?;
@@ -556,7 +593,7 @@ impl Parser {
       if self.match_token(TokenType::DoublePlus) || self.match_token(TokenType::DoubleDash) {
          let line_number = self.previous().as_ref().unwrap().line_number;
          let op =
-            if self.previous().take().unwrap().token_type == TokenType::DoublePlus {
+            if self.previous().as_ref().unwrap().token_type == TokenType::DoublePlus {
                UnaryOp::PostIncrement
             } else {
                UnaryOp::PostDecrement
@@ -613,28 +650,26 @@ impl Parser {
                   expr
                },
                TokenType::Identifier => {
-                  let line_number = self.peek().as_ref().unwrap().line_number;
+                  let span = self.peek().as_ref().unwrap().span();
                   let name = self.identifier()?;
                   if self.match_token(TokenType::OpenParen) {
                      let args = self.args()?;
                      self.consume(TokenType::CloseParen)?;
-                     Expr::FunctionCall(name, args, line_number)
+                     Expr::FunctionCall(name, args, span)
                   } else {
-                     Expr::Var(name, line_number)
+                     Expr::Var(name, span)
                   }
                },
                _ => {
-                  let t = self.peek();
-                  bail!(error::error(t.as_ref().unwrap().line_number,
-                                    format!("Expected an expression, found '{}'", t.as_ref().unwrap().lexeme),
-                                    error::ErrorType::SyntaxError))
+                  let msg = format!("Expected an expression, found '{}'", self.peek().as_ref().unwrap().lexeme);
+                  bail!(self.syntax_error(msg, Code::ExpectedExpression))
                }
             }
       }
       if self.match_token(TokenType::DoublePlus) || self.match_token(TokenType::DoubleDash) {
          let line_number = self.previous().as_ref().unwrap().line_number;
          let op =
-            if self.previous().take().unwrap().token_type == TokenType::DoublePlus {
+            if self.previous().as_ref().unwrap().token_type == TokenType::DoublePlus {
                UnaryOp::PostIncrement
             } else {
                UnaryOp::PostDecrement
@@ -644,13 +679,78 @@ impl Parser {
       Ok(expr)
    }
 
+   // Skip ahead to a point where parsing can resume after a syntax error: just past
+   // the next ';' or nested block, or just before the '}' that closes the
+   // enclosing block so that it can close.
+   fn synchronize(&mut self) {
+      let mut depth = 0;
+      while !self.at_end() {
+         match self.peek().as_ref().unwrap().token_type {
+            TokenType::Semicolon if depth == 0 => {
+               self.advance();
+               return;
+            },
+            TokenType::OpenBrace => depth += 1,
+            TokenType::CloseBrace if depth == 0 => return,
+            TokenType::CloseBrace if depth == 1 => {
+               self.advance();
+               return;
+            },
+            TokenType::CloseBrace => depth -= 1,
+            _ => ()
+         }
+         self.advance();
+      }
+   }
+
+   // Skip the rest of a broken file scope declaration, including a function body
+   // if one follows.
+   fn synchronize_declaration(&mut self) {
+      let mut depth = 0;
+      while !self.at_end() {
+         match self.peek().as_ref().unwrap().token_type {
+            TokenType::Semicolon if depth == 0 => {
+               self.advance();
+               return;
+            },
+            TokenType::OpenBrace => depth += 1,
+            TokenType::CloseBrace if depth <= 1 => {
+               self.advance();
+               return;
+            },
+            TokenType::CloseBrace => depth -= 1,
+            _ => ()
+         }
+         self.advance();
+      }
+   }
+
    fn consume(&mut self, token_type: TokenType) -> Result<&Option<Token>> {
       if self.check(&token_type) {
          return Ok(self.advance());
       }
-      bail!(error::error(self.peek().as_ref().unwrap().line_number,
-                        format!("Expected '{}', found '{}'", token_type, self.peek().as_ref().unwrap().token_type),
-                        error::ErrorType::SyntaxError))
+      let msg = format!("Expected '{}', found '{}'", token_type, self.peek().as_ref().unwrap().token_type);
+      if token_type == TokenType::Semicolon && self.current > 0 {
+         // The semicolon belongs right after the previous token, which may be on
+         // an earlier line than the token that was found instead
+         let previous = self.tokens[self.current - 1].as_ref().unwrap();
+         let next = self.tokens[self.current].as_ref().unwrap();
+         let end = previous.span().end();
+         let mut error = error::error(end.line, msg, Code::ExpectedToken)
+            .with_column(end.column)
+            .with_file(previous.file.as_deref());
+         if starts_statement(previous, next) {
+            error = error.with_fixit(FixIt::insert(end, ";"));
+         }
+         bail!(error)
+      }
+      bail!(self.syntax_error(msg, Code::ExpectedToken))
+   }
+
+   // Reports a syntax error at the next unconsumed token
+   fn syntax_error(&mut self, msg: String, code: Code) -> error::Error {
+      let token = self.peek().as_ref().unwrap();
+      error::error(token.line_number, msg, code).with_column(token.column).with_file(token.file.as_deref())
    }
 
    fn match_token(&mut self, token_type: TokenType) -> bool {
@@ -722,4 +822,38 @@ impl Parser {
       }
       false
    }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::lexer;
+
+   fn error_lines(source: &str) -> Vec<(usize, Code)> {
+      let tokens = lexer::lex(&source.to_string(), false).unwrap();
+      let Err(err) = parse(tokens, false) else {
+         panic!("expected a syntax error");
+      };
+      error::source_errors(&err).unwrap().iter().map(|e| (e.line, e.code)).collect()
+   }
+
+   fn fixits(source: &str) -> Vec<String> {
+      let tokens = lexer::lex(&source.to_string(), false).unwrap();
+      let Err(err) = parse(tokens, false) else {
+         panic!("expected a syntax error");
+      };
+      error::source_errors(&err).unwrap().iter().flat_map(|e| &e.fixits).map(FixIt::to_string).collect()
+   }
+
+   #[test]
+   fn suggests_a_semicolon_where_the_statement_ends() {
+      assert_eq!(fixits("int main(void) {\n   int y = 2\n   return y;\n}"), vec!["insert ';' at line 2, column 13"]);
+      assert_eq!(fixits("int main(void) { return 0 }"), vec!["insert ';' at line 1, column 26"]);
+      assert!(fixits("int main(void) { int a = 5, b = 7; return a; }").is_empty());
+   }
+
+   #[test]
+   fn resumes_after_a_nested_block() {
+      let source = "int main(void) {\n   for (int i = 0; i < 3 i++) { int x = 1; }\n   int y = 2\n   return y;\n}";
+      assert_eq!(error_lines(source), vec![(2, Code::ExpectedToken), (3, Code::ExpectedToken)]);
+   }
+}
@@ -0,0 +1,122 @@
+use crate::codegen::assembly::{self, Assembly};
+use crate::parser::ast::*;
+use crate::tacky::tacky::{self, TackyIR};
+
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+// What the compiler did with one translation unit, reported by --time-passes
+// and --stats
+#[derive(Default)]
+pub struct Stats {
+   // Wall time of each pass that ran, in order
+   pub passes: Vec<(&'static str, Duration)>,
+   pub tokens: usize,
+   pub ast_nodes: usize,
+   pub tacky_instructions: usize,
+   pub assembly_instructions: usize,
+   // Stack frame size in bytes of each function
+   pub frames: Vec<(String, i64)>,
+}
+
+impl Stats {
+   // Runs `pass`, recording how long it took under `name`
+   pub fn time<T>(&mut self, name: &'static str, pass: impl FnOnce() -> T) -> T {
+      let start = Instant::now();
+      let result = pass();
+      self.passes.push((name, start.elapsed()));
+      result
+   }
+
+   pub fn count_ast(&mut self, ast: &AST) {
+      self.ast_nodes = ast.program.decls.iter().map(decl_nodes).sum();
+   }
+
+   pub fn count_tacky(&mut self, tacky: &TackyIR) {
+      self.tacky_instructions = tacky.program.top_level.iter().map(|top_level| match top_level {
+         tacky::TopLevel::Function(func) => func.instrs.len(),
+         tacky::TopLevel::StaticVar(_) => 0,
+      }).sum();
+   }
+
+   pub fn count_assembly(&mut self, assembly: &Assembly) {
+      self.assembly_instructions = 0;
+      self.frames.clear();
+      for top_level in &assembly.program.top_level {
+         if let assembly::TopLevel::Function(func) = top_level {
+            self.assembly_instructions += func.instructions.len();
+            self.frames.push((func.name.clone(), func.frame_size()));
+         }
+      }
+   }
+
+   pub fn timings(&self) -> String {
+      let mut report = String::new();
+      let total: Duration = self.passes.iter().map(|(_, time)| *time).sum();
+      for (name, time) in &self.passes {
+         let share = if total.is_zero() { 0.0 } else { time.as_secs_f64() / total.as_secs_f64() * 100.0 };
+         writeln!(report, "  {:<12} {:>10.3} ms {:>6.1}%", name, time.as_secs_f64() * 1000.0, share).unwrap();
+      }
+      writeln!(report, "  {:<12} {:>10.3} ms", "total", total.as_secs_f64() * 1000.0).unwrap();
+      report
+   }
+
+   pub fn counts(&self) -> String {
+      let mut report = String::new();
+      writeln!(report, "  {:<24} {:>8}", "tokens", self.tokens).unwrap();
+      writeln!(report, "  {:<24} {:>8}", "AST nodes", self.ast_nodes).unwrap();
+      writeln!(report, "  {:<24} {:>8}", "TACKY instructions", self.tacky_instructions).unwrap();
+      writeln!(report, "  {:<24} {:>8}", "assembly instructions", self.assembly_instructions).unwrap();
+      for (name, size) in &self.frames {
+         writeln!(report, "  {:<24} {:>8} bytes", format!("stack frame of {}", name), size).unwrap();
+      }
+      report
+   }
+}
+
+// Counts declarations, statements and expressions
+fn decl_nodes(decl: &Decl) -> usize {
+   match decl {
+      Decl::VarDecl(var) => 1 + var.init.as_ref().map_or(0, expr_nodes),
+      Decl::FuncDecl(func) => 1 + func.body.as_ref().map_or(0, block_nodes),
+   }
+}
+
+fn block_nodes(block: &Block) -> usize {
+   block.items.iter().map(|item| match item {
+      BlockItem::Stmt(stmt) => stmt_nodes(stmt),
+      BlockItem::Decl(decl) => decl_nodes(decl),
+   }).sum()
+}
+
+fn stmt_nodes(stmt: &Stmt) -> usize {
+   1 + match stmt {
+      Stmt::Return(expr, ..) | Stmt::Expression(expr, ..) => expr_nodes(expr),
+      Stmt::If(cond, then_stmt, else_stmt, ..) => {
+         expr_nodes(cond) + stmt_nodes(then_stmt) + else_stmt.as_deref().map_or(0, stmt_nodes)
+      },
+      Stmt::Compound(block, ..) => block_nodes(block),
+      Stmt::While(cond, body, ..) | Stmt::DoWhile(body, cond, ..) | Stmt::Switch(cond, body, ..) => {
+         expr_nodes(cond) + stmt_nodes(body)
+      },
+      Stmt::For(init, cond, post, body, ..) => {
+         let init = match init {
+            Some(ForInit::Decl(var)) => 1 + var.init.as_ref().map_or(0, expr_nodes),
+            Some(ForInit::Expr(expr)) => expr_nodes(expr),
+            None => 0,
+         };
+         init + cond.as_ref().map_or(0, expr_nodes) + post.as_ref().map_or(0, expr_nodes) + stmt_nodes(body)
+      },
+      Stmt::Break(..) | Stmt::Continue(..) | Stmt::Goto(..) | Stmt::Null(..) => 0,
+   }
+}
+
+fn expr_nodes(expr: &Expr) -> usize {
+   1 + match expr {
+      Expr::Integer(_) | Expr::Var(..) => 0,
+      Expr::UnaryOp(_, operand, _) => expr_nodes(operand),
+      Expr::BinaryOp(_, left, right) | Expr::Assignment(left, right, _) => expr_nodes(left) + expr_nodes(right),
+      Expr::Conditional(cond, then_expr, else_expr) => expr_nodes(cond) + expr_nodes(then_expr) + expr_nodes(else_expr),
+      Expr::FunctionCall(_, args, _) => args.iter().map(expr_nodes).sum(),
+   }
+}
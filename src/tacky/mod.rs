@@ -1,6 +1,8 @@
 pub mod tacky;
 mod tacky_printer;
 
+pub use tacky_printer::print_tacky;
+
 use crate::name_generator::{self, gen_label};
 use crate::parser::ast;
 use crate::parser::ast::{AST, BlockItem, Decl, Expr, Stmt, ForInit, Label};
@@ -13,7 +15,7 @@ use anyhow::Result;
 pub fn gen_tacky(ast: AST, print_tacky: bool) -> Result<TackyIR> {
     let tacky = gen_tacky_program(ast)?;
     if print_tacky {
-        tacky_printer::print_tacky(&tacky);
+        tacky_printer::print_tacky(&tacky, "Tacky IR");
     }
     Ok(tacky)
 }
@@ -55,8 +57,11 @@ fn convert_symbols_to_tacky(symbol_table: &SymbolTable, top_level: &mut Vec<TopL
 
 fn gen_tacky_function(name: String, params: Vec<String>, body: ast::Block, symbol_table: &SymbolTable) -> Result<Function> {
     let mut instrs = Vec::new();
-    let global = match &symbol_table.get(&name).unwrap().attrs {
-        Attrs::FuncAttr { global, .. } => *global,
+    // An inline definition that isn't an external one is only there for calls in
+    // this file, so it gets a local copy that doesn't clash with the same header
+    // included elsewhere
+    let (global, inline) = match &symbol_table.get(&name).unwrap().attrs {
+        Attrs::FuncAttr { global, inline, external, .. } => (*global && *external, *inline),
         _ => unreachable!()
     };
     for item in body.items {
@@ -75,7 +80,7 @@ fn gen_tacky_function(name: String, params: Vec<String>, body: ast::Block, symbo
     // Push a dummy return instruction in case the function doesn't have a return statement
     instrs.push(Instr::Return(Val::Integer(0)));
 
-    Ok(Function{name, global, params, instrs})
+    Ok(Function{name, global, inline, params, instrs})
 }
 
 fn generate_var_decl_instrs(decl: ast::VarDecl, instrs: &mut Vec<Instr>, symbol_table: &SymbolTable) -> Result<()> {
@@ -357,3 +362,40 @@ fn gen_logical_or(left: Box<Expr>, right: Box<Expr>, instrs: &mut Vec<Instr>) ->
     instrs.push(Instr::Label(end_label));
     Ok(dest)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser, validator};
+
+    // Whether each function defined in `source` is emitted as a global symbol
+    fn global_functions(source: &str) -> Vec<(String, bool)> {
+        let tokens = lexer::lex(&source.to_string(), false).unwrap();
+        let Ok(mut ast) = parser::parse(tokens, false) else {
+            panic!("syntax error");
+        };
+        validator::validate(&mut ast, false, &mut Vec::new()).unwrap();
+        let Ok(tacky) = gen_tacky(ast, false) else {
+            panic!("failed to generate TACKY");
+        };
+        tacky.program.top_level.iter().filter_map(|top_level| match top_level {
+            TopLevel::Function(func) => Some((func.name.clone(), func.global)),
+            TopLevel::StaticVar(_) => None,
+        }).collect()
+    }
+
+    #[test]
+    fn plain_inline_definitions_are_local() {
+        let source = "inline int twice(int x) { return x * 2; }\n\
+                      inline int thrice(int x) { return x * 3; }\n\
+                      int thrice(int x);\n\
+                      extern inline int half(int x) { return x / 2; }\n\
+                      int main(void) { return twice(1) + thrice(1) + half(2); }";
+        assert_eq!(global_functions(source), vec![
+            ("twice".to_string(), false),
+            ("thrice".to_string(), true),
+            ("half".to_string(), true),
+            ("main".to_string(), true),
+        ]);
+    }
+}
@@ -20,13 +20,17 @@ pub struct StaticVar {
    pub value: i64,
 }
 
+#[derive(Clone)]
 pub struct Function {
    pub name: String,
    pub global: bool,
+   // Declared 'inline', asking for calls to be replaced by the body
+   pub inline: bool,
    pub params: Vec<String>,
    pub instrs: Vec<Instr>,
 }
 
+#[derive(Clone)]
 pub enum Instr {
    Return(Val),
    Unary(UnaryOp, Val, Val),
@@ -39,13 +43,13 @@ pub enum Instr {
    FuncCall(String, Vec<Val>, Val),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Val {
    Integer(i64),
    Var(String),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum UnaryOp {
    Negate,
    Complement,
@@ -56,7 +60,7 @@ pub enum UnaryOp {
    PostDecrement,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum BinaryOp {
    Add,
    Subtract,
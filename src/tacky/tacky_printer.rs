@@ -1,7 +1,7 @@
 use crate::tacky::tacky::*;
 
-pub fn print_tacky(tacky: &TackyIR) {
-   println!("Tacky IR:");
+pub fn print_tacky(tacky: &TackyIR, title: &str) {
+   println!("{}:", title);
    for top_level in &tacky.program.top_level {
       match top_level {
          TopLevel::Function(func) => print_function(func),
@@ -0,0 +1,59 @@
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+// Intermediate files (.i, .s) of one invocation. They are written to a private
+// temporary directory, so parallel builds of the same source never race and no
+// file beside the source is ever overwritten. With -save-temps they are copied
+// to the current directory once compilation is done.
+pub struct Temps {
+   // Created on first use, so a run that needs no intermediate files (e.g.
+   // `-S -o -`) doesn't touch the filesystem
+   dir: Option<TempDir>,
+   files: Vec<PathBuf>,
+   save: bool,
+}
+
+impl Temps {
+   pub fn new(save: bool) -> Self {
+      Self { dir: None, files: Vec::new(), save }
+   }
+
+   // A path for an intermediate file of the `index`th input. Each input gets its
+   // own subdirectory, so sources with the same name never collide.
+   pub fn file(&mut self, index: usize, source: &Path, extension: &str) -> Result<PathBuf> {
+      if self.dir.is_none() {
+         self.dir = Some(tempfile::Builder::new().prefix("crust").tempdir()?);
+      }
+      let subdir = self.dir.as_ref().unwrap().path().join(index.to_string());
+      fs::create_dir_all(&subdir)?;
+      let name = Path::new(source.file_name().unwrap_or(source.as_os_str())).with_extension(extension);
+      let path = subdir.join(name);
+      self.files.push(path.clone());
+      Ok(path)
+   }
+
+   // Copies the intermediate files to the current directory if -save-temps was
+   // given, refusing to overwrite any of the invocation's inputs
+   pub fn save(&self, inputs: &[PathBuf]) -> Result<()> {
+      if !self.save {
+         return Ok(());
+      }
+      for file in self.files.iter().filter(|file| file.exists()) {
+         let dest = PathBuf::from(file.file_name().unwrap());
+         if inputs.iter().any(|input| same_file(input, &dest)) {
+            bail!("Refusing to overwrite input file '{}' with a temporary file", dest.display());
+         }
+         fs::copy(file, &dest)?;
+      }
+      Ok(())
+   }
+}
+
+pub fn same_file(a: &Path, b: &Path) -> bool {
+   match (fs::canonicalize(a), fs::canonicalize(b)) {
+      (Ok(a), Ok(b)) => a == b,
+      _ => false,
+   }
+}
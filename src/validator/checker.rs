@@ -1,23 +1,28 @@
-use crate::{error, parser::ast::*};
+use crate::{error::{self, Code}, parser::ast::*};
+use crate::error::warning::{warning, Warning, WarningKind};
 use super::symbol_table::*;
 use anyhow::{Result, bail};
 
-pub fn typecheck_ast(ast: &mut AST) -> Result<()> {
-   typecheck_program(&ast.program, &mut ast.symbol_table)
+pub fn typecheck_ast(ast: &mut AST, warnings: &mut Vec<Warning>) -> Result<()> {
+   typecheck_program(&ast.program, &mut ast.symbol_table, warnings)
 }
 
-fn typecheck_program(program: &Program, symbol_table: &mut SymbolTable) -> Result<()> {
+fn typecheck_program(program: &Program, symbol_table: &mut SymbolTable, warnings: &mut Vec<Warning>) -> Result<()> {
+   let mut errors = Vec::new();
    for decl in &program.decls {
-      match decl {
+      let result = match decl {
          Decl::VarDecl(decl) => {
-            typecheck_global_var_decl(decl, symbol_table)?;
+            typecheck_global_var_decl(decl, symbol_table)
          },
          Decl::FuncDecl(decl) => {
-            typecheck_func_decl(decl, symbol_table, false)?;
+            typecheck_func_decl(decl, symbol_table, false).map(|_| check_return(decl, warnings))
          }
+      };
+      if let Err(e) = result {
+         error::push(&mut errors, e)?;
       }
    }
-   Ok(())
+   error::finish(errors)
 }
 
 fn typecheck_global_var_decl(decl: &VarDecl, symbol_table: &mut SymbolTable) -> Result<()> {
@@ -27,7 +32,7 @@ fn typecheck_global_var_decl(decl: &VarDecl, symbol_table: &mut SymbolTable) ->
             if let Expr::Integer(i) = init {
                InitialValue::Initialized(*i)
             } else {
-               bail!(error::error(decl.line_number, format!("Global variable initializer must be a constant"), error::ErrorType::SemanticError))
+               bail!(error::error(decl.line_number, "Global variable initializer must be a constant".to_string(), Code::NonConstantInitializer))
             }
          },
          None => {
@@ -43,7 +48,7 @@ fn typecheck_global_var_decl(decl: &VarDecl, symbol_table: &mut SymbolTable) ->
 
    if let Some(existing_decl) = symbol_table.get(&decl.name) {
       if existing_decl.decl_type != DeclType::Int {
-         bail!(error::error(decl.line_number, format!("\"{}\" redeclared as a variable", decl.name), error::ErrorType::SemanticError))
+         bail!(error::error(decl.line_number, format!("\"{}\" redeclared as a variable", decl.name), Code::RedeclaredAsVariable))
       }
 
       let (existing_initial_value, existing_global) = match existing_decl.attrs {
@@ -54,12 +59,12 @@ fn typecheck_global_var_decl(decl: &VarDecl, symbol_table: &mut SymbolTable) ->
       if decl.storage_class == Some(StorageClass::Extern) {
          global = existing_global;
       } else if existing_global != global {
-         bail!(error::error(decl.line_number, format!("Conflicting storage class specifiers for \"{}\".", decl.name), error::ErrorType::SemanticError))
+         bail!(error::error(decl.line_number, format!("Conflicting storage class specifiers for \"{}\"", decl.name), Code::ConflictingStorageClass))
       }
 
       if matches!(existing_initial_value, InitialValue::Initialized(_)) {
          if matches!(initial_value, InitialValue::Initialized(_))  {
-            bail!(error::error(decl.line_number, format!("Conflicting file scope variable definitions"), error::ErrorType::SemanticError))
+            bail!(error::error(decl.line_number, format!("Conflicting file scope variable definitions for \"{}\"", decl.name), Code::ConflictingDefinitions))
          } else {
             initial_value = existing_initial_value;
          }
@@ -83,36 +88,40 @@ fn typecheck_func_decl(decl: &FuncDecl, symbol_table: &mut SymbolTable, block_sc
       };
    let mut already_defined = false;
    let mut global = decl.storage_class != Some(StorageClass::Static);
+   let mut inline = decl.inline;
+   let mut external = !block_scope && (!decl.inline || decl.storage_class == Some(StorageClass::Extern));
 
    if !global && block_scope {
-      bail!(error::error(decl.line_number, format!("Static function declaration not allowed in block scope"), error::ErrorType::SemanticError))
+      bail!(error::error(decl.line_number, "Static function declaration not allowed in block scope".to_string(), Code::StaticFunctionInBlockScope))
    }
 
    if let Some(existing_decl) = symbol_table.get(&decl.name) {
       match existing_decl.decl_type {
          DeclType::Func(p) if p == decl.params.len() => {
             match existing_decl.attrs {
-               Attrs::FuncAttr { defined, global: old_global } => {
+               Attrs::FuncAttr { defined, global: old_global, inline: old_inline, external: old_external } => {
                   already_defined = defined;
                   if already_defined && has_body {
-                     bail!(error::error(decl.line_number, "Function is defined more than once".to_string(), error::ErrorType::SemanticError))
+                     bail!(error::error(decl.line_number, format!("Function \"{}\" is defined more than once", decl.name), Code::FunctionRedefined))
                   }
                   if old_global && decl.storage_class == Some(StorageClass::Static) {
-                     bail!(error::error(decl.line_number, format!("Conflicting storage class specifiers for function"), error::ErrorType::SemanticError))
+                     bail!(error::error(decl.line_number, format!("Conflicting storage class specifiers for \"{}\"", decl.name), Code::ConflictingStorageClass))
                   }
                   global = old_global;
+                  inline |= old_inline;
+                  external |= old_external;
                },
                _ => unreachable!()
             }
          },
          _ => {
-            bail!(error::error(decl.line_number, format!("Incompatible function declarations"), error::ErrorType::SemanticError))
+            bail!(error::error(decl.line_number, format!("Incompatible declarations for function \"{}\"", decl.name), Code::IncompatibleFunctionDeclarations))
          }
       }
    }
 
    let defined = already_defined || has_body;
-   let attrs = Attrs::FuncAttr { defined, global };
+   let attrs = Attrs::FuncAttr { defined, global, inline, external };
    symbol_table.insert(decl.name.clone(), TypeInfo{ decl_type, attrs });
 
    if let Some(body) = body {
@@ -124,11 +133,82 @@ fn typecheck_func_decl(decl: &FuncDecl, symbol_table: &mut SymbolTable, block_sc
    Ok(())
 }
 
+// Every function returns int, so falling off the end of any function other than
+// main (which implicitly returns 0) returns an indeterminate value.
+fn check_return(decl: &FuncDecl, warnings: &mut Vec<Warning>) {
+   if let Some(body) = &decl.body && decl.name != "main" && !block_returns(body) {
+      let msg = format!("Control reaches end of non-void function \"{}\"", decl.name);
+      warnings.push(warning(decl.line_number, msg, WarningKind::ReturnType).with_file(decl.file.as_deref()));
+   }
+}
+
+// Conservatively decides whether control can never reach the end of a block. A
+// labeled statement may be the target of a jump, so it makes the code following
+// it reachable again.
+fn block_returns(block: &Block) -> bool {
+   let mut returns = false;
+   for item in &block.items {
+      if let BlockItem::Stmt(stmt) = item {
+         if !stmt.labels().is_empty() {
+            returns = false;
+         }
+         if stmt_returns(stmt) {
+            returns = true;
+         }
+      }
+   }
+   returns
+}
+
+fn stmt_returns(stmt: &Stmt) -> bool {
+   match stmt {
+      Stmt::Return(..) | Stmt::Goto(..) => true,
+      Stmt::Compound(block, _, _) => block_returns(block),
+      Stmt::If(_, then_stmt, Some(else_stmt), _, _) => stmt_returns(then_stmt) && stmt_returns(else_stmt),
+      Stmt::While(condition, body, _, _) => is_nonzero_constant(condition) && !contains_break(body),
+      // A continue jumps to the condition, which may end the loop
+      Stmt::DoWhile(body, condition, _, _) => !contains_break(body) && (is_nonzero_constant(condition) || (stmt_returns(body) && !contains_continue(body))),
+      Stmt::For(_, condition, _, body, _, _) => condition.as_ref().is_none_or(is_nonzero_constant) && !contains_break(body),
+      Stmt::Switch(_, body, _, switch_info, _) => switch_info.default.is_some() && stmt_returns(body) && !contains_break(body),
+      _ => false
+   }
+}
+
+fn is_nonzero_constant(expr: &Expr) -> bool {
+   matches!(expr, Expr::Integer(i) if *i != 0)
+}
+
+// Whether `stmt` contains a break that exits the enclosing loop or switch, i.e.
+// one that is not nested in an inner loop or switch.
+fn contains_break(stmt: &Stmt) -> bool {
+   match stmt {
+      Stmt::Break(..) => true,
+      Stmt::Compound(block, _, _) => block.items.iter().any(|item| matches!(item, BlockItem::Stmt(s) if contains_break(s))),
+      Stmt::If(_, then_stmt, else_stmt, _, _) => contains_break(then_stmt) || else_stmt.as_ref().is_some_and(|s| contains_break(s)),
+      _ => false
+   }
+}
+
+// Whether `stmt` contains a continue of the enclosing loop, which unlike a break
+// may be nested in a switch.
+fn contains_continue(stmt: &Stmt) -> bool {
+   match stmt {
+      Stmt::Continue(..) => true,
+      Stmt::Compound(block, _, _) => block.items.iter().any(|item| matches!(item, BlockItem::Stmt(s) if contains_continue(s))),
+      Stmt::If(_, then_stmt, else_stmt, _, _) => contains_continue(then_stmt) || else_stmt.as_ref().is_some_and(|s| contains_continue(s)),
+      Stmt::Switch(_, body, _, _, _) => contains_continue(body),
+      _ => false
+   }
+}
+
 fn typecheck_block(block: &Block, symbol_table: &mut SymbolTable) -> Result<()> {
+   let mut errors = Vec::new();
    for block_item in &block.items {
-      typecheck_block_item(block_item, symbol_table)?;
+      if let Err(e) = typecheck_block_item(block_item, symbol_table) {
+         error::push(&mut errors, e)?;
+      }
    }
-   Ok(())
+   error::finish(errors)
 }
 
 fn typecheck_block_item(block_item: &BlockItem, symbol_table: &mut SymbolTable) -> Result<()> {
@@ -201,7 +281,7 @@ fn typecheck_for_init(init: &Option<ForInit>, symbol_table: &mut SymbolTable) ->
       },
       Some(ForInit::Decl(d)) => {
          if d.storage_class == Some(StorageClass::Static) {
-            bail!(error::error(d.line_number, format!("Static variable declaration not allowed in for loop initializer"), error::ErrorType::SemanticError))
+            bail!(error::error(d.line_number, "Static variable declaration not allowed in for loop initializer".to_string(), Code::StaticInForInit))
          }
          typecheck_local_var_decl(d, symbol_table)?;
       },
@@ -220,11 +300,11 @@ fn typecheck_optional_expr(expr: &Option<Expr>, symbol_table: &mut SymbolTable)
 fn typecheck_local_var_decl(decl: &VarDecl, symbol_table: &mut SymbolTable) -> Result<()> {
    if decl.storage_class == Some(StorageClass::Extern) {
       if let Some(_) = &decl.init {
-         bail!(error::error(decl.line_number, format!("Initializer on local extern variable declaration"), error::ErrorType::SemanticError))
+         bail!(error::error(decl.line_number, "Initializer on local extern variable declaration".to_string(), Code::ExternLocalInitializer))
       }
       if let Some(existing_decl) = symbol_table.get(&decl.name) {
          if !matches!(existing_decl.decl_type, DeclType::Int) {
-            bail!(error::error(decl.line_number, format!("Function redeclared as a variable"), error::ErrorType::SemanticError))
+            bail!(error::error(decl.line_number, "Function redeclared as a variable".to_string(), Code::RedeclaredAsVariable))
          }
       } else {
          let attrs = Attrs::StaticAttr { initial_value: InitialValue::NoInitializer, global: true };
@@ -237,7 +317,7 @@ fn typecheck_local_var_decl(decl: &VarDecl, symbol_table: &mut SymbolTable) -> R
                if let Expr::Integer(i) = init {
                   InitialValue::Initialized(*i)
                } else {
-                  bail!(error::error(decl.line_number, format!("Global variable initializer must be a constant"), error::ErrorType::SemanticError))
+                  bail!(error::error(decl.line_number, "Global variable initializer must be a constant".to_string(), Code::NonConstantInitializer))
                }
             },
             None => {
@@ -262,13 +342,13 @@ fn typecheck_expr(expr: &Expr, symbol_table: &mut SymbolTable) -> Result<()> {
             typecheck_expr(left, symbol_table)?;
             typecheck_expr(right, symbol_table)?;
          } else {
-            bail!(error::error(*line_number, format!("Invalid lvalue"), error::ErrorType::SemanticError))
+            bail!(error::error(*line_number, "Invalid lvalue".to_string(), Code::InvalidLvalue))
          }
       },
-      Expr::Var(name, line_number) => {
+      Expr::Var(name, span) => {
          if let Some(t) = symbol_table.get(name) {
             if t.decl_type != DeclType::Int {
-               bail!(error::error(*line_number, "Function name used as variable".to_string(), error::ErrorType::SemanticError))
+               bail!(error::error(span.line, "Function name used as variable".to_string(), Code::FunctionUsedAsVariable).with_column(span.column))
             }
          }
       },
@@ -281,7 +361,7 @@ fn typecheck_expr(expr: &Expr, symbol_table: &mut SymbolTable) -> Result<()> {
          if let Expr::Var(_, _) = **expr {
             typecheck_expr(expr, symbol_table)?;
          } else {
-            bail!(error::error(*line_number, format!("Invalid lvalue"), error::ErrorType::SemanticError))
+            bail!(error::error(*line_number, "Invalid lvalue".to_string(), Code::InvalidLvalue))
          }
       }
       Expr::UnaryOp(_, expr, _) => {
@@ -292,15 +372,15 @@ fn typecheck_expr(expr: &Expr, symbol_table: &mut SymbolTable) -> Result<()> {
          typecheck_expr(middle, symbol_table)?;
          typecheck_expr(right, symbol_table)?;
       },
-      Expr::FunctionCall(name, args , line_number) => {
+      Expr::FunctionCall(name, args , span) => {
          if let Some(t) = symbol_table.get(name) {
             match t.decl_type {
                DeclType::Int => {
-                  bail!(error::error(*line_number, "Variable used as function name".to_string(), error::ErrorType::SemanticError))
+                  bail!(error::error(span.line, "Variable used as function name".to_string(), Code::VariableUsedAsFunction).with_column(span.column))
                },
                DeclType::Func(num_args) => {
                   if num_args != args.len() {
-                     bail!(error::error(*line_number, "Function called with the wrong number of arguments".to_string(), error::ErrorType::SemanticError))
+                     bail!(error::error(span.line, "Function called with the wrong number of arguments".to_string(), Code::WrongArgumentCount).with_column(span.column))
                   }
                }
             }
@@ -311,4 +391,44 @@ fn typecheck_expr(expr: &Expr, symbol_table: &mut SymbolTable) -> Result<()> {
       }
    }
    Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+   use crate::error::warning::{Warning, WarningKind};
+   use crate::{lexer, parser, validator};
+
+   fn warnings(source: &str) -> Vec<Warning> {
+      let tokens = lexer::lex(&source.to_string(), false).unwrap();
+      let Ok(mut ast) = parser::parse(tokens, false) else {
+         panic!("syntax error");
+      };
+      let mut warnings = Vec::new();
+      validator::validate(&mut ast, false, &mut warnings).unwrap();
+      warnings
+   }
+
+   fn reaches_end(body: &str) -> bool {
+      let source = format!("int f(int x) {{ {} }}", body);
+      warnings(&source).iter().any(|w| w.kind == WarningKind::ReturnType)
+   }
+
+   #[test]
+   fn loops_left_by_a_break_do_not_return() {
+      assert!(reaches_end("do { if (x) break; return 1; } while (0);"));
+      assert!(reaches_end("do { if (x) continue; return 1; } while (0);"));
+      assert!(reaches_end("for (;;) { if (x) { break; } }"));
+      assert!(reaches_end("while (1) { if (x) break; }"));
+      assert!(!reaches_end("do { return 1; } while (0);"));
+      assert!(!reaches_end("while (1) { switch (x) { case 1: break; } }"));
+      assert!(!reaches_end("for (;;) { while (x) break; }"));
+   }
+
+   #[test]
+   fn warnings_name_the_header_they_are_in() {
+      let source = "# 1 \"main.c\"\n# 1 \"a.h\" 1\nint f(int x) { }\n# 2 \"main.c\" 2\nint main(void) { int y; return 0; }";
+      let files: Vec<_> = warnings(source).iter().map(|w| (w.kind, w.file.clone())).collect();
+      assert!(files.contains(&(WarningKind::ReturnType, Some("a.h".to_string()))));
+      assert!(files.contains(&(WarningKind::UnusedParameter, Some("a.h".to_string()))));
+      assert!(files.contains(&(WarningKind::UnusedVariable, None)));
+   }
+}
@@ -1,59 +1,78 @@
 use anyhow::{Result, bail};
-use crate::error::{self, error};
+use crate::error::{self, error, Code};
+use crate::error::warning::{warning, Warning, WarningKind};
 use crate::name_generator;
 use crate::parser::ast::*;
 
 use std::collections::HashMap;
 
-type Labels = HashMap<String, String>;
+struct LabelInfo {
+   unique_name: String,
+   line_number: usize,
+   used: bool,
+}
+
+type Labels = HashMap<String, LabelInfo>;
 
-pub fn validate(program: &mut Program) -> Result<()> {
+pub fn validate(program: &mut Program, warnings: &mut Vec<Warning>) -> Result<()> {
+   let mut errors = Vec::new();
    for decl in &mut program.decls {
-      if let Decl::FuncDecl(decl) = decl {
-         validate_func_decl_labels(decl)?;
+      if let Decl::FuncDecl(decl) = decl && let Err(e) = validate_func_decl_labels(decl, warnings) {
+         error::push(&mut errors, e)?;
       }
    }
-   Ok(())
+   error::finish(errors)
 }
 
-fn validate_func_decl_labels(func: &mut FuncDecl) -> Result<()> {
+fn validate_func_decl_labels(func: &mut FuncDecl, warnings: &mut Vec<Warning>) -> Result<()> {
    if let Some(body) = &mut func.body {
-      validate_func_labels(body)?;
+      validate_func_labels(body, func.file.as_deref(), warnings)?;
    }
    Ok(())
 }
 
-fn validate_func_labels(body: &mut Block) -> Result<()> {
+fn validate_func_labels(body: &mut Block, file: Option<&str>, warnings: &mut Vec<Warning>) -> Result<()> {
    let mut func_labels = Labels::new();
+   let mut errors = Vec::new();
    for block_item in &mut *body.items {
-      validate_block_item_labels(block_item, &mut func_labels)?;
+      if let Err(e) = validate_block_item_labels(block_item, &mut func_labels) {
+         error::push(&mut errors, e)?;
+      }
+   }
+   if let Err(e) = validate_func_goto_stmts(body, &mut func_labels) {
+      error::push(&mut errors, e)?;
    }
-   validate_func_goto_stmts(body, &func_labels)?;
-   Ok(())
-}
 
-fn validate_func_goto_stmts(body: &mut Block, labels: &Labels) -> Result<()> {
-   for block_item in &mut body.items {
-      validate_block_item_goto_stmts(block_item, labels)?;
+   let mut unused: Vec<_> = func_labels.iter().filter(|(_, info)| !info.used).collect();
+   unused.sort_by_key(|(_, info)| info.line_number);
+   for (name, info) in unused {
+      warnings.push(warning(info.line_number, format!("Label \"{}\" defined but not used", name), WarningKind::UnusedLabel).with_file(file));
    }
-   Ok(())
+   error::finish(errors)
+}
+
+fn validate_func_goto_stmts(body: &mut Block, labels: &mut Labels) -> Result<()> {
+   validate_block_goto_stmts(body, labels)
 }
 
-fn validate_block_item_goto_stmts(block_item: &mut BlockItem, labels: &Labels) -> Result<()> {
+fn validate_block_item_goto_stmts(block_item: &mut BlockItem, labels: &mut Labels) -> Result<()> {
    if let BlockItem::Stmt(stmt) = block_item {
       validate_stmt_goto_stmts(stmt, labels)?;
    }
    Ok(())
 }
 
-fn validate_block_goto_stmts(block: &mut Block, labels: &Labels) -> Result<()> {
+fn validate_block_goto_stmts(block: &mut Block, labels: &mut Labels) -> Result<()> {
+   let mut errors = Vec::new();
    for block_item in &mut block.items {
-      validate_block_item_goto_stmts(block_item, labels)?;
+      if let Err(e) = validate_block_item_goto_stmts(block_item, labels) {
+         error::push(&mut errors, e)?;
+      }
    }
-   Ok(())
+   error::finish(errors)
 }
 
-fn validate_stmt_goto_stmts(stmt: &mut Stmt, labels: &Labels) -> Result<()> {
+fn validate_stmt_goto_stmts(stmt: &mut Stmt, labels: &mut Labels) -> Result<()> {
    match stmt {
       Stmt::Break(..) => (),
       Stmt::Compound(block, _, _) => {
@@ -88,11 +107,12 @@ fn validate_stmt_goto_stmts(stmt: &mut Stmt, labels: &Labels) -> Result<()> {
    Ok(())
 }
 
-fn validate_jump_label(label: &mut String, labels: &Labels, line_number: usize) -> Result<()> {
-   if let Some(unique_label) = labels.get(label) {
-      *label = unique_label.clone();
+fn validate_jump_label(label: &mut String, labels: &mut Labels, line_number: usize) -> Result<()> {
+   if let Some(info) = labels.get_mut(label) {
+      info.used = true;
+      *label = info.unique_name.clone();
    } else {
-      bail!(error(line_number, format!("Label not found: {}", label), error::ErrorType::SemanticError))
+      bail!(error(line_number, format!("Label not found: {}", label), Code::UndefinedLabel))
    };
    Ok(())
 }
@@ -108,10 +128,13 @@ fn validate_block_item_labels(item: &mut BlockItem, labels: &mut Labels) -> Resu
 }
 
 fn validate_block_labels(block: &mut Block, labels: &mut Labels) -> Result<()> {
+   let mut errors = Vec::new();
    for block_item in &mut *block.items {
-      validate_block_item_labels(block_item, labels)?;
+      if let Err(e) = validate_block_item_labels(block_item, labels) {
+         error::push(&mut errors, e)?;
+      }
    }
-   Ok(())
+   error::finish(errors)
 }
 
 fn validate_stmt_labels(stmt: &mut Stmt, labels: &mut Labels) -> Result<()> {
@@ -174,11 +197,12 @@ fn validate_labels(stmt_labels: &mut Vec<Label>, func_labels: &mut Labels) -> Re
 
 fn validate_label(stmt_label: &mut Label, func_labels: &mut Labels) -> Result<()> {
    if !stmt_label.name.starts_with("case.") && !stmt_label.name.starts_with("default.") && !stmt_label.name.starts_with("for.") && !stmt_label.name.starts_with("while.") && !stmt_label.name.starts_with("dowhile.") {
-      if let Some(label) = func_labels.get(&stmt_label.name) {
-            bail!(error(stmt_label.line_number, format!("Duplicate label: {}", label), error::ErrorType::SemanticError))
+      if let Some(previous) = func_labels.get(&stmt_label.name) {
+            bail!(error(stmt_label.line_number, format!("Duplicate label: {}", stmt_label.name), Code::DuplicateLabel)
+               .with_note(format!("Previous definition is on line {}", previous.line_number)))
       } else {
          let unique_name = name_generator::gen_label(&stmt_label.name);
-         func_labels.insert(stmt_label.name.clone(), unique_name.clone());
+         func_labels.insert(stmt_label.name.clone(), LabelInfo { unique_name: unique_name.clone(), line_number: stmt_label.line_number, used: false });
          stmt_label.name = unique_name.clone();
       }
    }
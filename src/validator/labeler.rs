@@ -1,5 +1,5 @@
 use anyhow::{Result, bail};
-use crate::error;
+use crate::error::{self, Code, FixIt};
 use crate::name_generator;
 use crate::parser::ast::*;
 
@@ -9,12 +9,13 @@ enum Context {
 }
 
 pub fn label_program(program: &mut Program)  -> Result<()> {
+   let mut errors = Vec::new();
    for decl in &mut program.decls {
-      if let Decl::FuncDecl(decl) = decl {
-         label_func_decl(decl)?;
+      if let Decl::FuncDecl(decl) = decl && let Err(e) = label_func_decl(decl) {
+         error::push(&mut errors, e)?;
       }
    }
-   Ok(())
+   error::finish(errors)
 }
 
 fn label_func_decl(decl: &mut FuncDecl) -> Result<()> {
@@ -26,10 +27,13 @@ fn label_func_decl(decl: &mut FuncDecl) -> Result<()> {
 }
 
 fn label_block(block: &mut Block, loop_label: &Option<String>, switch_end_label: &Option<String>, in_loop: bool, context_stack: &mut Vec<Context>) -> Result<()> {
+   let mut errors = Vec::new();
    for block_item in &mut *block.items {
-      label_block_item(block_item, &loop_label, switch_end_label, in_loop, context_stack)?;
+      if let Err(e) = label_block_item(block_item, loop_label, switch_end_label, in_loop, context_stack) {
+         error::push(&mut errors, e)?;
+      }
    }
-   Ok(())
+   error::finish(errors)
 }
 
 fn label_block_item(item: &mut BlockItem, loop_label: &Option<String>, switch_end_label: &Option<String>, in_loop: bool, context_stack: &mut Vec<Context>) -> Result<()> {
@@ -44,7 +48,7 @@ fn label_block_item(item: &mut BlockItem, loop_label: &Option<String>, switch_en
 
 fn label_statement(stmt: &mut Stmt, loop_label: &Option<String>, switch_end_label: &Option<String>, in_loop: bool, context_stack: &mut Vec<Context>) -> Result<()> {
    match stmt {
-      Stmt::Break(label, _, line_number) => {
+      Stmt::Break(label, _, span) => {
          if let Some(context) = context_stack.last() {
             match context {
                Context::Loop => {
@@ -55,12 +59,14 @@ fn label_statement(stmt: &mut Stmt, loop_label: &Option<String>, switch_end_labe
                }
             }
          } else {
-            bail!(error::error(*line_number, format!("break statement outside of loop or switch statement"), error::ErrorType::SemanticError))
+            bail!(error::error(span.line, "break statement outside of loop or switch statement".to_string(), Code::BreakOutsideLoop)
+               .with_column(span.column)
+               .with_fixit(FixIt::remove(*span)))
          }
       },
       Stmt::Continue(label, _, line_number) => {
          if !in_loop {
-            bail!(error::error(*line_number, format!("continue statement outside of loop"), error::ErrorType::SemanticError))
+            bail!(error::error(*line_number, "continue statement outside of loop".to_string(), Code::ContinueOutsideLoop))
          }
          *label = loop_label.as_ref().unwrap().clone();
       },
@@ -68,25 +74,28 @@ fn label_statement(stmt: &mut Stmt, loop_label: &Option<String>, switch_end_labe
          let label_name = name_generator::gen_label("while");
          let new_label = Label::new(label_name.clone(), *line_number);
          context_stack.push(Context::Loop);
-         label_statement(body, &Some(label_name), switch_end_label, true, context_stack)?;
+         let result = label_statement(body, &Some(label_name), switch_end_label, true, context_stack);
          context_stack.pop();
          labels.push(new_label);
+         result?;
       },
       Stmt::DoWhile(body, _, labels, line_number) => {
          let label_name = name_generator::gen_label("dowhile");
          let new_label = Label::new(label_name.clone(), *line_number);
          context_stack.push(Context::Loop);
-         label_statement(body, &Some(label_name), switch_end_label, true, context_stack)?;
+         let result = label_statement(body, &Some(label_name), switch_end_label, true, context_stack);
          context_stack.pop();
          labels.push(new_label);
+         result?;
       },
       Stmt::For(_, _, _, body, labels, line_number) => {
          let label_name = name_generator::gen_label("for");
          let new_label = Label::new(label_name.clone(), *line_number);
          context_stack.push(Context::Loop);
-         label_statement(body, &Some(label_name), switch_end_label, true, context_stack)?;
+         let result = label_statement(body, &Some(label_name), switch_end_label, true, context_stack);
          context_stack.pop();
          labels.push(new_label);
+         result?;
       },
       Stmt::Compound(block, _, _) => {
          label_block(block, loop_label, switch_end_label, in_loop, context_stack)?;
@@ -96,9 +105,12 @@ fn label_statement(stmt: &mut Stmt, loop_label: &Option<String>, switch_end_labe
          label_optional_stmt(else_stmt, loop_label, switch_end_label, in_loop, context_stack)?;
       },
       Stmt::Switch(_, stmt, _, switch_info, _) => {
+         // Leave the context before reporting an error, since labelling carries
+         // on with the statements after this one
          context_stack.push(Context::Switch);
-         label_statement(stmt, loop_label, &Some(switch_info.end_label.clone()), in_loop, context_stack)?;
+         let result = label_statement(stmt, loop_label, &Some(switch_info.end_label.clone()), in_loop, context_stack);
          context_stack.pop();
+         result?;
       },
       _ => ()
    }
@@ -111,3 +123,22 @@ fn label_optional_stmt(stmt: &mut Option<Box<Stmt>>, loop_label: &Option<String>
    }
    Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+   use crate::error::{self, Code};
+   use crate::{lexer, parser, validator};
+
+   fn error_codes(source: &str) -> Vec<Code> {
+      let tokens = lexer::lex(&source.to_string(), false).unwrap();
+      let mut ast = parser::parse(tokens, false).unwrap();
+      let err = validator::validate(&mut ast, false, &mut Vec::new()).unwrap_err();
+      error::source_errors(&err).unwrap().iter().map(|e| e.code).collect()
+   }
+
+   #[test]
+   fn leaves_the_switch_after_an_error_in_its_body() {
+      let codes = error_codes("int main(void){ switch(1){ case 1: continue; } break; return 0; }");
+      assert_eq!(codes, vec![Code::ContinueOutsideLoop, Code::BreakOutsideLoop]);
+   }
+}
@@ -6,20 +6,39 @@ mod switch;
 
 pub mod symbol_table;
 
+use crate::error;
+use crate::error::warning::Warning;
 use crate::parser::ast_printer;
 use crate::parser::ast::*;
 
 use anyhow::Result;
 
-pub fn validate(ast: &mut AST, print_ast: bool) -> Result<()> {
-   resolver::resolve_program(&mut ast.program)?;
-   checker::typecheck_ast(ast)?;
-   labeler::label_program(&mut ast.program)?;
-   goto::validate(&mut ast.program)?;
-   switch::validate(&mut ast.program)?;
+pub fn validate(ast: &mut AST, print_ast: bool, warnings: &mut Vec<Warning>) -> Result<()> {
+   let mut errors = Vec::new();
+
+   // Type checking relies on the unique names assigned during resolution, so it
+   // only runs once resolution succeeds.
+   match resolver::resolve_program(&mut ast.program, warnings) {
+      Ok(()) => {
+         if let Err(e) = checker::typecheck_ast(ast, warnings) {
+            error::push(&mut errors, e)?;
+         }
+      },
+      Err(e) => error::push(&mut errors, e)?,
+   }
+   if let Err(e) = labeler::label_program(&mut ast.program) {
+      error::push(&mut errors, e)?;
+   }
+   if let Err(e) = goto::validate(&mut ast.program, warnings) {
+      error::push(&mut errors, e)?;
+   }
+   if let Err(e) = switch::validate(&ast.program, warnings) {
+      error::push(&mut errors, e)?;
+   }
+   error::finish(errors)?;
 
    if print_ast {
       ast_printer::print_ast(&ast);
    }
    Ok(())
-}
\ No newline at end of file
+}
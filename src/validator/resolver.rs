@@ -1,42 +1,50 @@
 use anyhow::{Result, bail};
-use crate::error;
+use crate::error::{self, Code, FixIt, Span};
+use crate::error::warning::{warning, Warning, WarningKind};
 use crate::name_generator;
 use crate::parser::ast::*;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 struct IdentifierInfo {
    unique_name: String,
    from_current_scope: bool,
    has_linkage: bool,
+   is_function: bool,
 }
 
 type IdentifierMap = HashMap<String, IdentifierInfo>;
 
-pub fn resolve_program(program: &mut Program) -> Result<()> {
+pub fn resolve_program(program: &mut Program, warnings: &mut Vec<Warning>) -> Result<()> {
    let mut identifier_map: IdentifierMap = HashMap::new();
+   let mut errors = Vec::new();
    for decl in &mut program.decls {
-      if let Decl::FuncDecl(decl) = decl {
-         resolve_func_decl(decl, &mut identifier_map, false)?;
+      let result = if let Decl::FuncDecl(decl) = decl {
+         resolve_func_decl(decl, &mut identifier_map, false).map(|_| warn_unused(decl, warnings))
       } else if let Decl::VarDecl(decl) = decl {
-         resolve_global_var(decl, &mut identifier_map)?;
+         resolve_global_var(decl, &mut identifier_map)
+      } else {
+         Ok(())
+      };
+      if let Err(e) = result {
+         error::push(&mut errors, e)?;
       }
    }
-   Ok(())
+   error::finish(errors)
 }
 
 fn resolve_global_var(decl: &mut VarDecl, identifier_map: &mut IdentifierMap) -> Result<()> {
-   identifier_map.insert(decl.name.clone(), IdentifierInfo{ unique_name: decl.name.clone(), from_current_scope: true, has_linkage: true });
+   identifier_map.insert(decl.name.clone(), IdentifierInfo{ unique_name: decl.name.clone(), from_current_scope: true, has_linkage: true, is_function: false });
    Ok(())
 }
 
 fn resolve_func_decl(decl: &mut FuncDecl, identifier_map: &mut IdentifierMap, is_local: bool) -> Result<()> {
    if let Some(prev_decl) =  identifier_map.get(&decl.name) {
       if prev_decl.from_current_scope && !prev_decl.has_linkage {
-         bail!(error::error(decl.line_number, format!("\"{}\" already declared.", decl.name), error::ErrorType::SemanticError))
+         bail!(error::error(decl.line_number, format!("\"{}\" already declared", decl.name), Code::Redeclaration))
       }
    }
-   identifier_map.insert(decl.name.clone(), IdentifierInfo{ unique_name: decl.name.clone(), from_current_scope: true, has_linkage: true });
+   identifier_map.insert(decl.name.clone(), IdentifierInfo{ unique_name: decl.name.clone(), from_current_scope: true, has_linkage: true, is_function: true });
 
    let mut inner_map = copy_identifier_map(identifier_map);
    for param in &mut decl.params {
@@ -45,7 +53,7 @@ fn resolve_func_decl(decl: &mut FuncDecl, identifier_map: &mut IdentifierMap, is
 
    if let Some(body) = &mut decl.body {
       if is_local {
-         bail!(error::error(decl.line_number, "Nested function definitions are not permitted".to_string(), error::ErrorType::SemanticError))
+         bail!(error::error(decl.line_number, "Nested function definitions are not permitted".to_string(), Code::NestedFunctionDefinition))
       }
       resolve_block(body, &mut inner_map)?;
    }
@@ -54,19 +62,22 @@ fn resolve_func_decl(decl: &mut FuncDecl, identifier_map: &mut IdentifierMap, is
 
 fn resolve_param(name: &mut String, line_number: usize, identifier_map: &mut IdentifierMap) -> Result<()> {
    if identifier_map.contains_key(name) && identifier_map.get(name).unwrap().from_current_scope == true {
-      bail!(error::error(line_number, format!("\"{}\" already declared.", name), error::ErrorType::SemanticError))
+      bail!(error::error(line_number, format!("\"{}\" already declared", name), Code::Redeclaration))
    }
    let unique_name = name_generator::uniquify_identifier(name);
-   identifier_map.insert(name.clone(), IdentifierInfo{ unique_name: unique_name.clone(), from_current_scope: true, has_linkage: false });
+   identifier_map.insert(name.clone(), IdentifierInfo{ unique_name: unique_name.clone(), from_current_scope: true, has_linkage: false, is_function: false });
    *name = unique_name;
    Ok(())
 }
 
 fn resolve_block(block: &mut Block, identifier_map: &mut IdentifierMap) -> Result<()> {
+   let mut errors = Vec::new();
    for block_item in &mut *block.items {
-      resolve_block_item(block_item, identifier_map)?;
+      if let Err(e) = resolve_block_item(block_item, identifier_map) {
+         error::push(&mut errors, e)?;
+      }
    }
-   Ok(())
+   error::finish(errors)
 }
 
 fn resolve_block_item(item: &mut BlockItem, identifier_map: &mut IdentifierMap) -> Result<()> {
@@ -159,18 +170,18 @@ fn resolve_local_var(decl: &mut VarDecl, identifier_map: &mut IdentifierMap) ->
    if let Some(prev_decl) = identifier_map.get(&decl.name) {
       if prev_decl.from_current_scope {
          if !(prev_decl.has_linkage && decl.storage_class == Some(StorageClass::Extern)) {
-            bail!(error::error(line_number, format!("Conflicting local declarations for \"{}\".", decl.name), error::ErrorType::SemanticError))
+            bail!(error::error(line_number, format!("Conflicting local declarations for \"{}\"", decl.name), Code::Redeclaration))
          }
       }
    }
 
    if decl.storage_class == Some(StorageClass::Extern) {
-      identifier_map.insert(decl.name.clone(), IdentifierInfo{ unique_name: decl.name.clone(), from_current_scope: true, has_linkage: true });
+      identifier_map.insert(decl.name.clone(), IdentifierInfo{ unique_name: decl.name.clone(), from_current_scope: true, has_linkage: true, is_function: false });
       return Ok(())
    }
 
    let unique_name = name_generator::uniquify_identifier(&decl.name.clone());
-   identifier_map.insert(decl.name.clone(), IdentifierInfo{ unique_name: unique_name.clone(), from_current_scope: true, has_linkage: false });
+   identifier_map.insert(decl.name.clone(), IdentifierInfo{ unique_name: unique_name.clone(), from_current_scope: true, has_linkage: false, is_function: false });
    decl.name = unique_name;
    Ok(())
 }
@@ -191,14 +202,14 @@ fn resolve_expr(expr: &mut Expr, identifier_map: &mut IdentifierMap) -> Result<(
             resolve_expr(left, identifier_map)?;
             resolve_expr(right, identifier_map)?;
          } else {
-            bail!(error::error(*line_number, format!("Invalid lvalue"), error::ErrorType::SemanticError))
+            bail!(error::error(*line_number, "Invalid lvalue".to_string(), Code::InvalidLvalue))
          }
       },
-      Expr::Var(name, line_number) => {
+      Expr::Var(name, span) => {
          if let Some(_entry @ IdentifierInfo {unique_name, ..}) = identifier_map.get(name) {
             *name = unique_name.clone();
          } else {
-            bail!(error::error(*line_number, format!("Undeclared variable {}", name), error::ErrorType::SemanticError))
+            bail!(undeclared("variable", name, *span, Code::UndeclaredVariable, false, identifier_map))
          }
       },
       Expr::BinaryOp(_, left, right) => {
@@ -214,28 +225,183 @@ fn resolve_expr(expr: &mut Expr, identifier_map: &mut IdentifierMap) -> Result<(
          resolve_expr(middle, identifier_map)?;
          resolve_expr(right, identifier_map)?;
       },
-      Expr::FunctionCall(name, args , line_number) => {
+      Expr::FunctionCall(name, args , span) => {
          if let Some(id_info) = identifier_map.get(name) {
             *name = id_info.unique_name.clone();
             for arg in args {
                resolve_expr(arg, identifier_map)?;
             }
          } else {
-            bail!(error::error(*line_number, format!("Undeclared function {}", name), error::ErrorType::SemanticError))
+            bail!(undeclared("function", name, *span, Code::UndeclaredFunction, true, identifier_map))
          }
       }
    }
    Ok(())
 }
 
+// Reports an undeclared identifier, suggesting the closest name in scope when
+// the identifier looks like a misspelling of it. Only names of the same kind are
+// suggested, since the fix-it has to leave behind code that type checks.
+fn undeclared(kind: &str, name: &str, span: Span, code: Code, is_function: bool, identifier_map: &IdentifierMap) -> error::Error {
+   let err = error::error(span.line, format!("Undeclared {} \"{}\"", kind, name), code).with_column(span.column);
+   let candidates = identifier_map.iter()
+      .filter(|(_, info)| info.is_function == is_function)
+      .map(|(candidate, _)| candidate);
+   match closest_name(name, candidates) {
+      Some(suggestion) => err
+         .with_note(format!("did you mean `{}`?", suggestion))
+         .with_fixit(FixIt::replace(span, suggestion)),
+      None => err,
+   }
+}
+
+fn closest_name<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Option<&'a str> {
+   let max_distance = (name.len() / 3).max(1);
+   candidates
+      .map(|candidate| (edit_distance(name, candidate), candidate))
+      .filter(|(distance, _)| *distance <= max_distance)
+      .min()
+      .map(|(_, candidate)| candidate.as_str())
+}
+
+// Edit distance between two identifiers, counting a swap of two adjacent
+// characters as a single edit (optimal string alignment)
+fn edit_distance(a: &str, b: &str) -> usize {
+   let a: Vec<char> = a.chars().collect();
+   let b: Vec<char> = b.chars().collect();
+   let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+   for (i, row) in d.iter_mut().enumerate() {
+      row[0] = i;
+   }
+   for (j, cell) in d[0].iter_mut().enumerate() {
+      *cell = j;
+   }
+   for i in 1..=a.len() {
+      for j in 1..=b.len() {
+         let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+         d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+         if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+            d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+         }
+      }
+   }
+   d[a.len()][b.len()]
+}
+
 fn copy_identifier_map(map: &HashMap<String, IdentifierInfo>) -> HashMap<String, IdentifierInfo> {
    let mut new_map = HashMap::new();
    for (key, value) in map {
       new_map.insert(key.clone(), IdentifierInfo {
          unique_name: value.unique_name.clone(),
          from_current_scope: false,
-         has_linkage: value.has_linkage
+         has_linkage: value.has_linkage,
+         is_function: value.is_function,
       });
    }
    new_map
 }
+
+// Local variables and parameters have unique names once resolved, so a single walk
+// over the function body is enough to find the ones that are never referenced.
+fn warn_unused(decl: &FuncDecl, warnings: &mut Vec<Warning>) {
+   let Some(body) = &decl.body else {
+      return;
+   };
+   let mut locals = Vec::new();
+   let mut used = HashSet::new();
+   collect_block_names(body, &mut locals, &mut used);
+
+   for param in &decl.params {
+      if !used.contains(param) {
+         let msg = format!("Unused parameter \"{}\"", source_name(param));
+         warnings.push(warning(decl.line_number, msg, WarningKind::UnusedParameter).with_file(decl.file.as_deref()));
+      }
+   }
+   for (name, line_number) in locals {
+      if !used.contains(&name) {
+         let msg = format!("Unused variable \"{}\"", source_name(&name));
+         warnings.push(warning(line_number, msg, WarningKind::UnusedVariable).with_file(decl.file.as_deref()));
+      }
+   }
+}
+
+fn source_name(unique_name: &str) -> &str {
+   unique_name.rsplit_once('.').map_or(unique_name, |(name, _)| name)
+}
+
+fn collect_block_names(block: &Block, locals: &mut Vec<(String, usize)>, used: &mut HashSet<String>) {
+   for item in &block.items {
+      match item {
+         BlockItem::Stmt(stmt) => collect_stmt_names(stmt, locals, used),
+         BlockItem::Decl(Decl::VarDecl(decl)) => collect_var_decl_names(decl, locals, used),
+         BlockItem::Decl(Decl::FuncDecl(_)) => (),
+      }
+   }
+}
+
+fn collect_var_decl_names(decl: &VarDecl, locals: &mut Vec<(String, usize)>, used: &mut HashSet<String>) {
+   if decl.storage_class != Some(StorageClass::Extern) {
+      locals.push((decl.name.clone(), decl.line_number));
+   }
+   if let Some(init) = &decl.init {
+      collect_expr_names(init, used);
+   }
+}
+
+fn collect_stmt_names(stmt: &Stmt, locals: &mut Vec<(String, usize)>, used: &mut HashSet<String>) {
+   match stmt {
+      Stmt::Return(e, _, _) | Stmt::Expression(e, _, _) => collect_expr_names(e, used),
+      Stmt::If(condition, then_stmt, else_stmt, _, _) => {
+         collect_expr_names(condition, used);
+         collect_stmt_names(then_stmt, locals, used);
+         if let Some(else_stmt) = else_stmt {
+            collect_stmt_names(else_stmt, locals, used);
+         }
+      },
+      Stmt::Compound(block, _, _) => collect_block_names(block, locals, used),
+      Stmt::While(condition, body, _, _) | Stmt::DoWhile(body, condition, _, _) => {
+         collect_expr_names(condition, used);
+         collect_stmt_names(body, locals, used);
+      },
+      Stmt::For(init, condition, post, body, _, _) => {
+         match init {
+            Some(ForInit::Decl(decl)) => collect_var_decl_names(decl, locals, used),
+            Some(ForInit::Expr(e)) => collect_expr_names(e, used),
+            None => ()
+         }
+         for e in [condition, post].into_iter().flatten() {
+            collect_expr_names(e, used);
+         }
+         collect_stmt_names(body, locals, used);
+      },
+      Stmt::Switch(expr, body, _, _, _) => {
+         collect_expr_names(expr, used);
+         collect_stmt_names(body, locals, used);
+      },
+      Stmt::Break(..) | Stmt::Continue(..) | Stmt::Goto(..) | Stmt::Null(..) => (),
+   }
+}
+
+fn collect_expr_names(expr: &Expr, used: &mut HashSet<String>) {
+   match expr {
+      Expr::Var(name, _) => {
+         used.insert(name.clone());
+      },
+      Expr::Integer(_) => (),
+      Expr::UnaryOp(_, e, _) => collect_expr_names(e, used),
+      Expr::BinaryOp(_, left, right) | Expr::Assignment(left, right, _) => {
+         collect_expr_names(left, used);
+         collect_expr_names(right, used);
+      },
+      Expr::Conditional(condition, middle, right) => {
+         collect_expr_names(condition, used);
+         collect_expr_names(middle, used);
+         collect_expr_names(right, used);
+      },
+      Expr::FunctionCall(_, args, _) => {
+         for arg in args {
+            collect_expr_names(arg, used);
+         }
+      }
+   }
+}
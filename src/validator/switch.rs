@@ -1,44 +1,55 @@
 use anyhow::{bail, Result};
-use crate::error::{error, ErrorType};
+use crate::error::{self, error, Code};
+use crate::error::warning::{warning, Warning, WarningKind};
 use crate::parser::ast::*;
 use std::collections::HashSet;
 
-pub fn validate(program: &Program) -> Result<()> {
+pub fn validate(program: &Program, warnings: &mut Vec<Warning>) -> Result<()> {
+   let mut errors = Vec::new();
    for decl in &program.decls {
-      if let Decl::FuncDecl(decl) = decl {
-         validate_func_decl_switch_stmts(decl)?;
+      if let Decl::FuncDecl(decl) = decl && let Err(e) = validate_func_decl_switch_stmts(decl, warnings) {
+         error::push(&mut errors, e)?;
       }
    }
-   Ok(())
+   error::finish(errors)
 }
 
-fn validate_func_decl_switch_stmts(func: &FuncDecl) -> Result<()> {
+fn validate_func_decl_switch_stmts(func: &FuncDecl, warnings: &mut Vec<Warning>) -> Result<()> {
    if let Some(body) = &func.body {
-      validate_func_switch_stmts(body)?;
+      let first = warnings.len();
+      let result = validate_func_switch_stmts(body, warnings);
+      // The warnings are all in the function's body, so in its file
+      for warning in &mut warnings[first..] {
+         warning.file = func.file.as_deref().map(String::from);
+      }
+      result?;
    }
    Ok(())
 }
 
-fn validate_func_switch_stmts(body: &Block) -> Result<()> {
-   validate_block_switch_stmts(body, &None)?;
+fn validate_func_switch_stmts(body: &Block, warnings: &mut Vec<Warning>) -> Result<()> {
+   validate_block_switch_stmts(body, &None, warnings)?;
    Ok(())
 }
 
-fn validate_block_switch_stmts(block: &Block, switch_info: &Option<&SwitchInfo>) -> Result<()> {
+fn validate_block_switch_stmts(block: &Block, switch_info: &Option<&SwitchInfo>, warnings: &mut Vec<Warning>) -> Result<()> {
+   let mut errors = Vec::new();
    for block_item in &*block.items {
-      validate_block_item_switch_stmts(block_item, switch_info)?;
+      if let Err(e) = validate_block_item_switch_stmts(block_item, switch_info, warnings) {
+         error::push(&mut errors, e)?;
+      }
    }
-   Ok(())
+   error::finish(errors)
 }
 
-fn validate_block_item_switch_stmts(block_item: &BlockItem, switch_info: &Option<&SwitchInfo>) -> Result<()> {
+fn validate_block_item_switch_stmts(block_item: &BlockItem, switch_info: &Option<&SwitchInfo>, warnings: &mut Vec<Warning>) -> Result<()> {
    if let BlockItem::Stmt(stmt) = block_item {
-      validate_stmt_switch_stmts(stmt, switch_info)?;
+      validate_stmt_switch_stmts(stmt, switch_info, warnings)?;
    }
    Ok(())
 }
 
-fn validate_stmt_switch_stmts(stmt: &Stmt, switch_info: &Option<&SwitchInfo>) -> Result<()> {
+fn validate_stmt_switch_stmts(stmt: &Stmt, switch_info: &Option<&SwitchInfo>, warnings: &mut Vec<Warning>) -> Result<()> {
    match stmt {
       Stmt::Return(_, labels, _) => {
          validate_labels(labels, switch_info)?
@@ -48,14 +59,14 @@ fn validate_stmt_switch_stmts(stmt: &Stmt, switch_info: &Option<&SwitchInfo>) ->
       },
       Stmt::If(_, stmt, then_stmt, labels, _) => {
          validate_labels(labels, switch_info)?;
-         validate_stmt_switch_stmts(stmt, switch_info)?;
+         validate_stmt_switch_stmts(stmt, switch_info, warnings)?;
          if let Some(stmt) = then_stmt {
-            validate_stmt_switch_stmts(stmt, switch_info)?;
+            validate_stmt_switch_stmts(stmt, switch_info, warnings)?;
          }
       },
       Stmt::Compound(block, labels, _) => {
          validate_labels(labels, switch_info)?;
-         validate_block_switch_stmts(block, switch_info)?
+         validate_block_switch_stmts(block, switch_info, warnings)?
       },
       Stmt::Break(_, labels, _) => {
          validate_labels(labels, switch_info)?;
@@ -65,23 +76,24 @@ fn validate_stmt_switch_stmts(stmt: &Stmt, switch_info: &Option<&SwitchInfo>) ->
       },
       Stmt::While(_, stmt, labels, _) => {
          validate_labels(labels, switch_info)?;
-         validate_stmt_switch_stmts(stmt, switch_info)?
+         validate_stmt_switch_stmts(stmt, switch_info, warnings)?
       },
       Stmt::DoWhile(body, _, labels, _) => {
          validate_labels(labels, switch_info)?;
-         validate_stmt_switch_stmts(&*body, switch_info)?
+         validate_stmt_switch_stmts(body, switch_info, warnings)?
       },
       Stmt::For(_, _, _, stmt, labels, _) => {
          validate_labels(labels, switch_info)?;
-         validate_stmt_switch_stmts(stmt, switch_info)?
+         validate_stmt_switch_stmts(stmt, switch_info, warnings)?
       },
       Stmt::Goto(_, labels, _) => {
          validate_labels(labels, switch_info)?;
       },
       Stmt::Switch(_, stmt, labels, switch_info, _) => {
          validate_switch_info(switch_info)?;
+         check_fallthrough(stmt, warnings);
          validate_labels(labels, &Some(&switch_info))?;
-         validate_stmt_switch_stmts(stmt, &Some(&switch_info))?;
+         validate_stmt_switch_stmts(stmt, &Some(switch_info), warnings)?;
       },
       Stmt::Null(labels, _) => {
          validate_labels(labels, switch_info)?;
@@ -93,7 +105,7 @@ fn validate_stmt_switch_stmts(stmt: &Stmt, switch_info: &Option<&SwitchInfo>) ->
 fn validate_switch_info(switch_info: &SwitchInfo) -> Result<()> {
    for case in &switch_info.cases {
       let Expr::Integer(_) = case.value else {
-         bail!(error(case.line_number, format!("case label must be an integer constant expression"), ErrorType::SemanticError))
+         bail!(error(case.line_number, "case label must be an integer constant expression".to_string(), Code::NonConstantCase))
       };
    }
    find_duplicates(&switch_info.cases)?;
@@ -101,21 +113,69 @@ fn validate_switch_info(switch_info: &SwitchInfo) -> Result<()> {
 }
 
 fn find_duplicates(cases: &Vec<CaseInfo>) -> Result<()> {
-    let mut seen = HashSet::new();
+    let mut seen: HashSet<&CaseInfo> = HashSet::new();
 
     for case in cases {
-        if !seen.insert(case.clone()) {
-            bail!(error(case.line_number, format!("Duplicate case label"), ErrorType::SemanticError))
+        if let Some(previous) = seen.get(case) {
+            bail!(error(case.line_number, "Duplicate case value".to_string(), Code::DuplicateCase)
+               .with_note(format!("Previous case with the same value is on line {}", previous.line_number)))
         }
+        seen.insert(case);
     }
     Ok(())
 }
 
 fn validate_labels(labels: &Vec<Label>, switch_info: &Option<&SwitchInfo>) -> Result<()> {
    for label in labels {
-      if let None = switch_info && (label.name.starts_with("default.") || label.name.starts_with("case.")) {
-         bail!(error(label.line_number, format!("{} label outside of switch statement", label.name), ErrorType::SemanticError))
+      if switch_info.is_some() {
+         continue;
+      }
+      if label.name.starts_with("default.") {
+         bail!(error(label.line_number, "default label outside of switch statement".to_string(), Code::DefaultOutsideSwitch))
+      } else if label.name.starts_with("case.") {
+         bail!(error(label.line_number, "case label outside of switch statement".to_string(), Code::CaseOutsideSwitch))
       }
    }
    Ok(())
 }
+
+// Warns about case labels that can be reached by falling through from the
+// statement above them, rather than only by jumping to them.
+fn check_fallthrough(body: &Stmt, warnings: &mut Vec<Warning>) {
+   let Stmt::Compound(block, _, _) = body else {
+      return;
+   };
+   let mut falls_through = false;
+   for item in &block.items {
+      if let BlockItem::Stmt(stmt) = item {
+         let case_label = stmt.labels().iter().find(|label| is_case_label(label));
+         if let Some(label) = case_label && falls_through {
+            warnings.push(warning(label.line_number, "Statement may fall through to the next case label".to_string(), WarningKind::ImplicitFallthrough));
+         }
+         if falls_through || !stmt.labels().is_empty() {
+            falls_through = stmt_falls_through(stmt);
+         }
+      }
+   }
+}
+
+fn stmt_falls_through(stmt: &Stmt) -> bool {
+   match stmt {
+      Stmt::Break(..) | Stmt::Continue(..) | Stmt::Return(..) | Stmt::Goto(..) => false,
+      Stmt::If(_, then_stmt, Some(else_stmt), _, _) => stmt_falls_through(then_stmt) || stmt_falls_through(else_stmt),
+      Stmt::Compound(block, _, _) => {
+         let mut falls_through = true;
+         for item in &block.items {
+            if let BlockItem::Stmt(stmt) = item && (falls_through || !stmt.labels().is_empty()) {
+               falls_through = stmt_falls_through(stmt);
+            }
+         }
+         falls_through
+      },
+      _ => true
+   }
+}
+
+fn is_case_label(label: &Label) -> bool {
+   label.name.starts_with("case.") || label.name.starts_with("default.")
+}
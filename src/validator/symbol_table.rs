@@ -16,7 +16,11 @@ pub enum InitialValue {
 pub enum Attrs {
    FuncAttr {
       defined: bool,
-      global: bool
+      global: bool,
+      inline: bool,
+      // Whether the definition is an external one, which it is unless every file
+      // scope declaration says 'inline' without 'extern'
+      external: bool
    },
    StaticAttr {
       initial_value: InitialValue,